@@ -1,5 +1,28 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use image::{DynamicImage, ImageBuffer, Rgb};
+use lab::Lab;
 use pixify::embroidery::canvas::{Canvas, CanvasConfig};
+use pixify::embroidery::catalog::ThreadCatalog;
+use pixify::embroidery::colors::RgbColor;
+use pixify::embroidery::index::{ColorMatcher, LabIndex};
+use pixify::embroidery::metric::ColorMetric;
+use pixify::embroidery::quantizer::{Quantizer, QuantizerKind};
+use pixify::embroidery::sampling::Sampling;
+use std::io::Cursor;
+
+fn generate_image_bytes(width: u32, height: u32) -> Vec<u8> {
+    let image_buffer = ImageBuffer::from_fn(width, height, |x, y| {
+        let r = (x * 255 / width) as u8;
+        let g = (y * 255 / height) as u8;
+        let b = ((x + y) % 256) as u8;
+        Rgb([r, g, b])
+    });
+    let mut bytes = Vec::new();
+    DynamicImage::ImageRgb8(image_buffer)
+        .write_to(&mut Cursor::new(&mut bytes), image::ImageFormat::Png)
+        .expect("Failed to encode image");
+    bytes
+}
 
 fn bench_canvas_matrix(c: &mut Criterion) {
     let pic = black_box(include_bytes!("../tests/pic.png").to_vec());
@@ -13,6 +36,126 @@ fn bench_canvas_matrix(c: &mut Criterion) {
     });
 }
 
+fn bench_large_canvas_matrix(c: &mut Criterion) {
+    let pic = black_box(generate_image_bytes(400, 400));
+    let mut group = c.benchmark_group("canvas matrix generation 200x200");
+    for metric in ColorMetric::ALL {
+        let config = black_box(
+            CanvasConfig::new(pic.clone(), Some(200), Some(20))
+                .expect("Failed to create config")
+                .with_metric(metric),
+        );
+        group.bench_function(metric.to_string(), |b| {
+            b.iter(|| {
+                let _ = Canvas::new(config.clone()).expect("Failed to create canvas");
+            })
+        });
+    }
+    group.finish();
+}
+
+/// Matching every cell of a 200x200-cell pattern to the catalog: the linear
+/// scan done before the k-d tree, the tree alone and the tree behind the
+/// per-color cache used by the canvas.
+fn bench_cell_matching(c: &mut Criterion) {
+    let cells: Vec<RgbColor> = image::load_from_memory(&generate_image_bytes(400, 400))
+        .expect("Failed to decode image")
+        .resize_exact(200, 200, image::imageops::FilterType::CatmullRom)
+        .to_rgb8()
+        .pixels()
+        .map(|&pixel| pixel.into())
+        .collect();
+    let labs: Vec<Lab> = ThreadCatalog::dmc()
+        .threads()
+        .map(|thread| Lab::from_rgb(&thread.rgb.into()))
+        .collect();
+    let mut group = c.benchmark_group("cell matching 200x200");
+    for metric in ColorMetric::ALL {
+        group.bench_function(format!("{metric} linear scan"), |b| {
+            b.iter(|| {
+                for color in &cells {
+                    let lab = Lab::from_rgb(&(*color).into());
+                    black_box(
+                        labs.iter()
+                            .map(|&thread| metric.difference(lab, thread))
+                            .enumerate()
+                            .min_by(|x, y| x.1.total_cmp(&y.1)),
+                    );
+                }
+            })
+        });
+        let index = LabIndex::new(labs.clone(), metric);
+        group.bench_function(format!("{metric} k-d tree"), |b| {
+            b.iter(|| {
+                for color in &cells {
+                    black_box(index.closest(Lab::from_rgb(&(*color).into())));
+                }
+            })
+        });
+        group.bench_function(format!("{metric} k-d tree cached"), |b| {
+            b.iter(|| {
+                let mut matcher = ColorMatcher::new(&index);
+                for &color in &cells {
+                    black_box(matcher.closest(color));
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_thread_lookup(c: &mut Criterion) {
+    let colors: Vec<RgbColor> = (0..=255u8)
+        .step_by(17)
+        .flat_map(|r| (0..=255u8).step_by(17).map(move |g| (r, g)))
+        .flat_map(|(r, g)| {
+            (0..=255u8).step_by(17).map(move |b| RgbColor {
+                red: r,
+                green: g,
+                blue: b,
+            })
+        })
+        .collect();
+    let catalog = ThreadCatalog::dmc();
+    let mut group = c.benchmark_group("thread lookup");
+    for metric in ColorMetric::ALL {
+        group.bench_function(metric.to_string(), |b| {
+            b.iter(|| {
+                for color in &colors {
+                    black_box(color.find_thread(catalog, metric));
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_quantization(c: &mut Criterion) {
+    let pic = image::load_from_memory(include_bytes!("../tests/pic.png"))
+        .expect("Failed to decode image")
+        .to_rgb8();
+    let pixels: Vec<RgbColor> = pic.pixels().map(|&pixel| pixel.into()).collect();
+    let mut group = c.benchmark_group("palette quantization");
+    for quantizer in QuantizerKind::ALL {
+        group.bench_function(quantizer.to_string(), |b| {
+            b.iter(|| black_box(quantizer.quantize(&pixels, 20)))
+        });
+    }
+    group.finish();
+}
+
+fn bench_sampling(c: &mut Criterion) {
+    let img =
+        image::load_from_memory(&generate_image_bytes(800, 800)).expect("Failed to decode image");
+    let mut group = c.benchmark_group("cell sampling 100x100");
+    for sampling in Sampling::ALL {
+        group.bench_function(sampling.to_string(), |b| {
+            b.iter(|| black_box(sampling.sample(&img, 100, 100)))
+        });
+    }
+    group.finish();
+}
+
 fn bench_bytes_canvas(c: &mut Criterion) {
     let pic = black_box(include_bytes!("../tests/pic.png").to_vec());
     let config = black_box(
@@ -27,5 +170,14 @@ fn bench_bytes_canvas(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, bench_canvas_matrix, bench_bytes_canvas);
+criterion_group!(
+    benches,
+    bench_canvas_matrix,
+    bench_large_canvas_matrix,
+    bench_cell_matching,
+    bench_thread_lookup,
+    bench_quantization,
+    bench_sampling,
+    bench_bytes_canvas
+);
 criterion_main!(benches);
@@ -1,19 +1,70 @@
-use actix_multipart::Multipart;
+use actix_multipart::{Field, Multipart};
 use actix_web::{post, HttpResponse};
 use futures_util::StreamExt;
 use serde::Serialize;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::ops::RangeBounds;
+use std::path::Path;
+use std::str::FromStr;
 
-use crate::embroidery::canvas::{Canvas, CanvasConfig, Palette};
-use crate::embroidery::colors::RgbColor;
-use crate::error::{ExportError, InvalidPayloadError, UploadError};
+use crate::embroidery::background::{Background, DEFAULT_TOLERANCE};
+use crate::embroidery::blend::DEFAULT_MAX_DIFFERENCE;
+use crate::embroidery::canvas::{
+    Canvas, CanvasConfig, Drift, Palette, DEFAULT_ALPHA_THRESHOLD, DEFAULT_N_COLORS,
+};
+use crate::embroidery::catalog::ThreadCatalog;
+use crate::embroidery::cleanup::{Cleanup, Connectivity};
+use crate::embroidery::colors::{RgbColor, ThreadColor};
+use crate::embroidery::dither::{Dither, DitherMode};
+use crate::embroidery::export::ExportFormat;
+use crate::embroidery::inventory::{parse_csv_codes, Inventory};
+use crate::embroidery::metric::ColorMetric;
+use crate::embroidery::oxs::{parse_oxs, render_oxs};
+use crate::embroidery::pdf::{render_pdf, PageSize};
+use crate::embroidery::preprocess::Preprocess;
+use crate::embroidery::quantizer::QuantizerKind;
+use crate::embroidery::sampling::Sampling;
+use crate::embroidery::size::{Dimensions, Fabric, TargetSize, Unit, MAX_CELLS};
+use crate::embroidery::svg::render_svg;
+use crate::embroidery::symbols::{is_valid_symbol, override_key};
+use crate::error::{CanvasError, ExportError, InvalidPayloadError, UploadError};
 use crate::http::multipart::get_bytes;
 
 #[derive(Default)]
 struct ImageData {
     pub file: FileData,
-    pub n_cells_in_width: Option<u8>,
+    pub n_cells_in_width: Option<u32>,
     pub n_colors: Option<u8>,
+    pub preprocess: Preprocess,
+    pub cell_aspect_ratio: Option<f32>,
+    pub target_size: TargetSize,
+    pub unit: Unit,
+    pub fabric_count: Option<f32>,
+    pub margin: Option<f32>,
+    pub catalog: Option<&'static ThreadCatalog>,
+    pub metric: ColorMetric,
+    pub quantizer: QuantizerKind,
+    pub sampling: Sampling,
+    pub dither: DitherMode,
+    pub dither_strength: Option<f32>,
+    pub alpha_threshold: Option<u8>,
+    pub fabric_color: Option<RgbColor>,
+    pub fabric_tolerance: Option<f32>,
+    pub auto_background: bool,
+    pub min_cluster_size: Option<usize>,
+    pub connectivity: Connectivity,
+    /// Included threads and the last color stay even below it, counted by
+    /// `rareColors` in the response
+    pub min_stitches_per_color: Option<u32>,
+    pub include_threads: Vec<ThreadColor>,
+    pub exclude_threads: Vec<ThreadColor>,
+    pub inventory: Option<Inventory>,
+    pub blends: bool,
+    pub palette: Option<Vec<ThreadColor>>,
+    pub format: ExportFormat,
+    pub page_size: PageSize,
+    pub show_symbols: Option<bool>,
+    pub symbols: HashMap<String, String>,
 }
 
 #[derive(Default)]
@@ -25,57 +76,197 @@ struct FileData {
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
 struct UploadResponse {
-    pub embroidery: Vec<Vec<RgbColor>>,
+    pub embroidery: Vec<Vec<Option<RgbColor>>>,
     pub palette: Vec<Palette>,
+    pub color_shortfall: u8,
+    /// Colors kept with fewer stitches than `minStitchesPerColor`
+    pub rare_colors: usize,
+    pub cleaned_cells: usize,
+    pub drift: Option<Drift>,
+    pub dimensions: Dimensions,
 }
 
 #[post("/upload")]
 pub async fn upload(mut payload: Multipart) -> Result<HttpResponse, UploadError> {
     let data: ImageData = get_data_from_payload(&mut payload).await?;
 
-    let config = CanvasConfig::new(data.file.buffer, data.n_cells_in_width, data.n_colors)?;
-    let canvas = Canvas::new(config)?;
-    let canvas_palette = canvas.get_dmc_palette();
+    let canvas = Canvas::new(data.into_config()?)?;
+    let canvas_palette = canvas.get_thread_palette();
+    let color_shortfall = canvas.color_shortfall();
+    let rare_colors = canvas.rare_colors();
+    let cleaned_cells = canvas.cleaned_cells();
+    let drift = canvas.drift().cloned();
+    let dimensions = canvas.dimensions();
 
     Ok(HttpResponse::Ok().json(UploadResponse {
         embroidery: canvas.embroidery,
         palette: canvas_palette,
+        color_shortfall,
+        rare_colors,
+        cleaned_cells,
+        drift,
+        dimensions,
     }))
 }
 
 #[post("/export")]
 pub async fn export(mut payload: Multipart) -> Result<HttpResponse, ExportError> {
     let data: ImageData = get_data_from_payload(&mut payload).await?;
+    let (format, page_size, show_symbols) = (data.format, data.page_size, data.show_symbols);
+    let filename = Path::new(&data.file.filename).with_extension(format.extension());
+    let title = Path::new(&data.file.filename)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
 
-    let config = CanvasConfig::new(data.file.buffer, data.n_cells_in_width, data.n_colors)?;
-    let canvas_bytes = Canvas::new(config)?.get_bytes()?;
+    let canvas = Canvas::new(data.into_config()?)?;
+    let canvas_bytes = match format {
+        ExportFormat::Png => canvas.get_bytes()?,
+        ExportFormat::Pdf => render_pdf(&canvas, page_size),
+        ExportFormat::Svg => render_svg(&canvas, show_symbols.unwrap_or(true)).into_bytes(),
+        ExportFormat::Oxs => render_oxs(&canvas, &title).into_bytes(),
+    };
 
     Ok(HttpResponse::Ok()
-        .content_type("image/png")
+        .content_type(format.content_type())
         .append_header((
             "Content-Disposition",
-            format!("attachment; filename={}", data.file.filename),
+            format!("attachment; filename={}", filename.display()),
         ))
         .body(canvas_bytes))
 }
 
+/// Reads an OXS chart made here or in another chart editor, answering like
+/// `upload` does.
+#[post("/import")]
+pub async fn import(mut payload: Multipart) -> Result<HttpResponse, UploadError> {
+    let (text, catalog) = get_chart_from_payload(&mut payload).await?;
+
+    let canvas = parse_oxs(&text, catalog)?;
+    let canvas_palette = canvas.get_thread_palette();
+    let dimensions = canvas.dimensions();
+
+    Ok(HttpResponse::Ok().json(UploadResponse {
+        embroidery: canvas.embroidery,
+        palette: canvas_palette,
+        color_shortfall: 0,
+        rare_colors: 0,
+        cleaned_cells: 0,
+        drift: None,
+        dimensions,
+    }))
+}
+
+/// Chart file of an import and the catalog of threads without a brand
+async fn get_chart_from_payload(
+    payload: &mut Multipart,
+) -> Result<(String, &'static ThreadCatalog), InvalidPayloadError> {
+    let mut fields: HashSet<String> = HashSet::new();
+    let mut file: Option<Vec<u8>> = None;
+    let mut catalog: Option<&'static ThreadCatalog> = None;
+
+    while let Some(item) = payload.next().await {
+        let field = item?;
+        let Some(name) = field.content_disposition().and_then(|c| c.get_name()) else {
+            continue;
+        };
+        check_unique(&mut fields, name)?;
+        match name {
+            "file" => file = Some(get_bytes(field).await?),
+            "brand" => catalog = Some(parse_field_with_reason(field, "brand").await?),
+            _ => {}
+        }
+    }
+    let file = file
+        .filter(|file| !file.is_empty())
+        .ok_or_else(|| InvalidPayloadError::MissingValue("file".into()))?;
+    let text = String::from_utf8(file).map_err(|_| {
+        InvalidPayloadError::InvalidValue("file".into(), "Value should be an OXS chart".into())
+    })?;
+    Ok((text, catalog.unwrap_or_else(ThreadCatalog::dmc)))
+}
+
+impl ImageData {
+    fn into_config(self) -> Result<CanvasConfig, CanvasError> {
+        let mut config = CanvasConfig::new(self.file.buffer, self.n_cells_in_width, self.n_colors)?
+            .with_preprocess(self.preprocess)?
+            .with_catalog(self.catalog.unwrap_or_else(ThreadCatalog::dmc))
+            .with_metric(self.metric)
+            .with_quantizer(self.quantizer)
+            .with_sampling(self.sampling)
+            .with_dither(Dither::new(
+                self.dither,
+                self.dither_strength.unwrap_or(1.0),
+            ))
+            .with_alpha_threshold(self.alpha_threshold.unwrap_or(DEFAULT_ALPHA_THRESHOLD));
+
+        if let Some(aspect_ratio) = self.cell_aspect_ratio {
+            config = config.with_cell_aspect_ratio(aspect_ratio);
+        }
+        let default_fabric = Fabric::default();
+        config = config.with_fabric(Fabric {
+            count: self.fabric_count.unwrap_or(default_fabric.count),
+            unit: self.unit,
+            margin: self
+                .margin
+                .unwrap_or_else(|| self.unit.from_inches(default_fabric.margin)),
+        });
+        if self.target_size.width.is_some() || self.target_size.height.is_some() {
+            config = config.with_target_size(self.target_size);
+        }
+        if let Some(fabric_color) = self.fabric_color {
+            config = config.with_fabric_color(fabric_color);
+        }
+        if self.fabric_color.is_some() || self.auto_background {
+            config = config.with_background(Background {
+                color: self.fabric_color,
+                tolerance: self.fabric_tolerance.unwrap_or(DEFAULT_TOLERANCE),
+                flood_fill: self.auto_background,
+            });
+        }
+        if let Some(min_cluster_size) = self.min_cluster_size {
+            config = config.with_cleanup(Cleanup::new(min_cluster_size, self.connectivity));
+        }
+        if let Some(min_stitches) = self.min_stitches_per_color {
+            config = config.with_min_stitches_per_color(min_stitches);
+        }
+        if let Some(inventory) = self.inventory {
+            config = config.with_inventory(inventory);
+        }
+        if self.blends {
+            config = config.with_blends(DEFAULT_MAX_DIFFERENCE);
+        }
+        if let Some(palette) = self.palette {
+            config = config.with_palette(palette);
+        }
+        let brand = self.catalog.unwrap_or_else(ThreadCatalog::dmc).brand();
+        let symbols = self
+            .symbols
+            .into_iter()
+            .map(|(code, symbol)| (override_key(brand, &code), symbol))
+            .collect();
+        Ok(config
+            .with_include_threads(self.include_threads)
+            .with_exclude_threads(self.exclude_threads)
+            .with_symbol_overrides(symbols))
+    }
+}
+
 async fn get_data_from_payload(payload: &mut Multipart) -> Result<ImageData, InvalidPayloadError> {
     let mut fields: HashSet<String> = HashSet::new();
     let mut data: ImageData = Default::default();
+    // Codes are resolved once the brand is known
+    let mut include_threads = String::new();
+    let mut exclude_threads = String::new();
+    let mut inventory: Option<Vec<String>> = None;
+    let mut palette: Option<String> = None;
 
     while let Some(item) = payload.next().await {
         let field = item?;
         let content_disposition = field.content_disposition().unwrap();
 
         if let Some(name) = content_disposition.get_name() {
-            if fields.contains(name) {
-                return Err(InvalidPayloadError::InvalidValue(
-                    name.into(),
-                    "Field should contain 1 item".into(),
-                ));
-            } else {
-                fields.insert(name.into());
-            }
+            check_unique(&mut fields, name)?;
             match name {
                 "file" => {
                     data.file.filename = content_disposition
@@ -85,28 +276,181 @@ async fn get_data_from_payload(payload: &mut Multipart) -> Result<ImageData, Inv
                     data.file.buffer = get_bytes(field).await?;
                 }
                 "nCellsInWidth" => {
-                    let content = get_bytes(field).await?;
-                    data.n_cells_in_width =
-                        Some(String::from_utf8(content)?.parse().map_err(|_| {
-                            InvalidPayloadError::MissingValue("nCellsInWidth".into())
-                        })?);
+                    let message = format!("Value should be within 1 and {}", MAX_CELLS);
+                    data.n_cells_in_width = Some(
+                        parse_field_within(field, "nCellsInWidth", 1..=MAX_CELLS, &message).await?,
+                    );
                 }
                 "nColors" => {
-                    let content = get_bytes(field).await?;
-                    let value = String::from_utf8(content)?.parse().map_err(|_| {
-                        InvalidPayloadError::InvalidValue(
-                            "nColors".into(),
-                            "Value should be within 2 and 200".into(),
-                        )
-                    })?;
-
-                    if value <= 2 || value > 200 {
-                        return Err(InvalidPayloadError::InvalidValue(
-                            "nColors".into(),
-                            "Value should be within 2 and 200".into(),
-                        ));
+                    let message = "Value should be within 2 and 200";
+                    data.n_colors =
+                        Some(parse_field_within(field, "nColors", 3..=200, message).await?);
+                }
+                "crop" => {
+                    data.preprocess.crop = Some(parse_field_with_reason(field, "crop").await?);
+                }
+                "rotation" => {
+                    data.preprocess.rotation = parse_field_with_reason(field, "rotation").await?;
+                }
+                "mirror" => {
+                    data.preprocess.mirror = parse_field_with_reason(field, "mirror").await?;
+                }
+                "brightness" | "contrast" | "saturation" => {
+                    let name = name.to_string();
+                    let message = "Value should be within -1 and 1";
+                    let value = parse_field_within(field, &name, -1.0..=1.0, message).await?;
+                    match name.as_str() {
+                        "brightness" => data.preprocess.brightness = value,
+                        "contrast" => data.preprocess.contrast = value,
+                        _ => data.preprocess.saturation = value,
                     }
-                    data.n_colors = Some(value);
+                }
+                "gamma" => {
+                    let message = "Value should be within 0.1 and 10";
+                    data.preprocess.gamma =
+                        parse_field_within(field, "gamma", 0.1..=10.0, message).await?;
+                }
+                "autoLevels" => {
+                    let message = "Value should be true or false";
+                    data.preprocess.auto_levels = parse_field(field, "autoLevels", message).await?;
+                }
+                "targetWidth" | "targetHeight" | "margin" => {
+                    let name = name.to_string();
+                    // Only the margin can be zero
+                    let min = if name == "margin" {
+                        0.0
+                    } else {
+                        f32::MIN_POSITIVE
+                    };
+                    let message = "Value should be a positive number";
+                    let value = parse_field_within(field, &name, min..=f32::MAX, message).await?;
+                    match name.as_str() {
+                        "targetWidth" => data.target_size.width = Some(value),
+                        "targetHeight" => data.target_size.height = Some(value),
+                        _ => data.margin = Some(value),
+                    }
+                }
+                "unit" => {
+                    data.unit = parse_field_with_reason(field, "unit").await?;
+                }
+                "fabricCount" => {
+                    let message = "Value should be within 1 and 60";
+                    data.fabric_count =
+                        Some(parse_field_within(field, "fabricCount", 1.0..=60.0, message).await?);
+                }
+                "cellAspectRatio" => {
+                    let message = "Value should be within 0.25 and 4";
+                    data.cell_aspect_ratio = Some(
+                        parse_field_within(field, "cellAspectRatio", 0.25..=4.0, message).await?,
+                    );
+                }
+                "brand" => {
+                    data.catalog = Some(parse_field_with_reason(field, "brand").await?);
+                }
+                "metric" => {
+                    data.metric = parse_field_with_reason(field, "metric").await?;
+                }
+                "quantizer" => {
+                    data.quantizer = parse_field_with_reason(field, "quantizer").await?;
+                }
+                "format" => {
+                    data.format = parse_field_with_reason(field, "format").await?;
+                }
+                "pageSize" => {
+                    data.page_size = parse_field_with_reason(field, "pageSize").await?;
+                }
+                "showSymbols" => {
+                    let message = "Value should be true or false";
+                    data.show_symbols = Some(parse_field(field, "showSymbols", message).await?);
+                }
+                "symbols" => {
+                    let content = get_bytes(field).await?;
+                    data.symbols = parse_symbols(&String::from_utf8(content)?)?;
+                }
+                "sampling" => {
+                    data.sampling = parse_field_with_reason(field, "sampling").await?;
+                }
+                "dither" => {
+                    data.dither = parse_field_with_reason(field, "dither").await?;
+                }
+                "ditherStrength" => {
+                    let message = "Value should be within 0 and 1";
+                    data.dither_strength = Some(
+                        parse_field_within(field, "ditherStrength", 0.0..=1.0, message).await?,
+                    );
+                }
+                "alphaThreshold" => {
+                    let message = "Value should be within 0 and 255";
+                    data.alpha_threshold =
+                        Some(parse_field(field, "alphaThreshold", message).await?);
+                }
+                "fabricColor" => {
+                    let content = get_bytes(field).await?;
+                    let value = String::from_utf8(content)?;
+                    let value = value.trim();
+                    let color = ThreadCatalog::dmc()
+                        .get(value)
+                        .map(|thread| thread.rgb)
+                        .or_else(|| RgbColor::from_hex(value))
+                        .ok_or_else(|| {
+                            InvalidPayloadError::InvalidValue(
+                                "fabricColor".into(),
+                                "Value should be a DMC code or a hex color".into(),
+                            )
+                        })?;
+                    data.fabric_color = Some(color);
+                }
+                "fabricTolerance" => {
+                    let message = "Value should be within 0 and 100";
+                    data.fabric_tolerance = Some(
+                        parse_field_within(field, "fabricTolerance", 0.0..=100.0, message).await?,
+                    );
+                }
+                "autoBackground" => {
+                    let message = "Value should be true or false";
+                    data.auto_background = parse_field(field, "autoBackground", message).await?;
+                }
+                "minClusterSize" => {
+                    let message = "Value should be within 1 and 100";
+                    data.min_cluster_size =
+                        Some(parse_field_within(field, "minClusterSize", 1..=100, message).await?);
+                }
+                "connectivity" => {
+                    data.connectivity = parse_field_with_reason(field, "connectivity").await?;
+                }
+                "minStitchesPerColor" => {
+                    let message = "Value should be a positive number";
+                    data.min_stitches_per_color =
+                        Some(parse_field_within(field, "minStitchesPerColor", 1.., message).await?);
+                }
+                "includeThreads" => {
+                    include_threads = String::from_utf8(get_bytes(field).await?)?;
+                }
+                "excludeThreads" => {
+                    exclude_threads = String::from_utf8(get_bytes(field).await?)?;
+                }
+                "palette" => {
+                    palette = Some(String::from_utf8(get_bytes(field).await?)?);
+                }
+                "blends" => {
+                    let message = "Value should be true or false";
+                    data.blends = parse_field(field, "blends", message).await?;
+                }
+                "inventory" => {
+                    let is_file = content_disposition.get_filename().is_some();
+                    let content = String::from_utf8(get_bytes(field).await?)?;
+                    // A list of codes, or a CSV file with a code column
+                    let codes = if is_file {
+                        parse_csv_codes(&content).map_err(|_| {
+                            InvalidPayloadError::InvalidValue(
+                                "inventory".into(),
+                                "Value should be a CSV file with a code column".into(),
+                            )
+                        })?
+                    } else {
+                        split_codes(&content).map(String::from).collect()
+                    };
+                    inventory = Some(codes);
                 }
                 _ => {}
             }
@@ -115,5 +459,215 @@ async fn get_data_from_payload(payload: &mut Multipart) -> Result<ImageData, Inv
     if data.file.buffer.is_empty() {
         return Err(InvalidPayloadError::MissingValue("file".into()));
     }
+
+    let has_target_size = data.target_size.width.is_some() || data.target_size.height.is_some();
+    if has_target_size && data.n_cells_in_width.is_some() {
+        return Err(InvalidPayloadError::InvalidValue(
+            "nCellsInWidth".into(),
+            "Value cannot be combined with targetWidth or targetHeight".into(),
+        ));
+    }
+
+    let catalog = data.catalog.unwrap_or_else(ThreadCatalog::dmc);
+    data.include_threads = parse_threads("includeThreads", split_codes(&include_threads), catalog)?;
+    // Cells are told apart by color, so two included threads can't share one
+    if let Some(thread) = data
+        .include_threads
+        .iter()
+        .enumerate()
+        .find(|&(i, thread)| {
+            data.include_threads[..i]
+                .iter()
+                .any(|other| other.rgb == thread.rgb)
+        })
+        .map(|(_, thread)| thread)
+    {
+        return Err(InvalidPayloadError::InvalidValue(
+            "includeThreads".into(),
+            format!("Duplicate color '{}'", thread.name),
+        ));
+    }
+    data.exclude_threads = parse_threads("excludeThreads", split_codes(&exclude_threads), catalog)?;
+    if let Some(codes) = inventory {
+        let threads = parse_threads("inventory", codes.iter().map(String::as_str), catalog)?;
+        if threads.is_empty() {
+            return Err(InvalidPayloadError::InvalidValue(
+                "inventory".into(),
+                "Value should contain at least one thread code".into(),
+            ));
+        }
+        let inventory = Inventory::new(threads);
+        if let Some(thread) = data
+            .include_threads
+            .iter()
+            .find(|thread| !inventory.contains(thread))
+        {
+            return Err(InvalidPayloadError::InvalidValue(
+                "includeThreads".into(),
+                format!("Thread '{}' is not in the inventory", thread.name),
+            ));
+        }
+        data.inventory = Some(inventory);
+    }
+    if let Some(thread) = data
+        .include_threads
+        .iter()
+        .find(|thread| data.exclude_threads.contains(thread))
+    {
+        return Err(InvalidPayloadError::InvalidValue(
+            "excludeThreads".into(),
+            format!("Thread '{}' is also included", thread.name),
+        ));
+    }
+    if data.include_threads.len() > data.n_colors.unwrap_or(DEFAULT_N_COLORS) as usize {
+        return Err(InvalidPayloadError::InvalidValue(
+            "includeThreads".into(),
+            "Value should not list more threads than nColors".into(),
+        ));
+    }
+    if let Some(palette) = palette {
+        if !data.include_threads.is_empty()
+            || !data.exclude_threads.is_empty()
+            || data.inventory.is_some()
+        {
+            return Err(InvalidPayloadError::InvalidValue(
+                "palette".into(),
+                "Value cannot be combined with includeThreads, excludeThreads or inventory".into(),
+            ));
+        }
+        data.palette = Some(parse_palette(&palette, catalog)?);
+    }
     Ok(data)
 }
+
+/// Records the field `name`, failing if the payload already had one
+fn check_unique(fields: &mut HashSet<String>, name: &str) -> Result<(), InvalidPayloadError> {
+    if !fields.insert(name.into()) {
+        return Err(InvalidPayloadError::InvalidValue(
+            name.into(),
+            "Field should contain 1 item".into(),
+        ));
+    }
+    Ok(())
+}
+
+/// Parses the text of `field`, failing with `message` if it is not a `T`
+async fn parse_field<T: FromStr>(
+    field: Field,
+    name: &str,
+    message: &str,
+) -> Result<T, InvalidPayloadError> {
+    let content = String::from_utf8(get_bytes(field).await?)?;
+    content
+        .trim()
+        .parse()
+        .map_err(|_| InvalidPayloadError::InvalidValue(name.into(), message.into()))
+}
+
+/// Parses the text of `field`, failing with `message` if it is not a `T`
+/// within `range`
+async fn parse_field_within<T: FromStr + PartialOrd>(
+    field: Field,
+    name: &str,
+    range: impl RangeBounds<T>,
+    message: &str,
+) -> Result<T, InvalidPayloadError> {
+    let value = parse_field(field, name, message).await?;
+    if !range.contains(&value) {
+        return Err(InvalidPayloadError::InvalidValue(
+            name.into(),
+            message.into(),
+        ));
+    }
+    Ok(value)
+}
+
+/// Parses the text of `field`, failing with the reason given by the parser
+async fn parse_field_with_reason<T: FromStr<Err = String>>(
+    field: Field,
+    name: &str,
+) -> Result<T, InvalidPayloadError> {
+    let content = String::from_utf8(get_bytes(field).await?)?;
+    content
+        .trim()
+        .parse()
+        .map_err(|err| InvalidPayloadError::InvalidValue(name.into(), err))
+}
+
+/// Parses a list of thread codes and hex colors, hex colors standing for
+/// custom threads of exactly that color
+fn parse_palette(
+    value: &str,
+    catalog: &ThreadCatalog,
+) -> Result<Vec<ThreadColor>, InvalidPayloadError> {
+    let invalid = |message: String| InvalidPayloadError::InvalidValue("palette".into(), message);
+    let mut threads: Vec<ThreadColor> = Vec::new();
+    for entry in split_codes(value) {
+        let thread = catalog
+            .get(entry)
+            .or_else(|| RgbColor::from_hex(entry).map(|color| color.custom_thread()))
+            .ok_or_else(|| invalid(format!("Unknown thread code or color '{}'", entry)))?;
+        if threads
+            .iter()
+            .any(|other| other.name == thread.name || other.rgb == thread.rgb)
+        {
+            return Err(invalid(format!("Duplicate color '{}'", entry)));
+        }
+        threads.push(thread);
+    }
+    if threads.is_empty() {
+        return Err(invalid("Value should contain at least one color".into()));
+    }
+    Ok(threads)
+}
+
+/// Parses `code:symbol` pairs separated by commas or whitespace, codes in
+/// upper case
+fn parse_symbols(value: &str) -> Result<HashMap<String, String>, InvalidPayloadError> {
+    let invalid = |message: String| InvalidPayloadError::InvalidValue("symbols".into(), message);
+    let mut symbols: HashMap<String, String> = HashMap::new();
+    for entry in split_codes(value) {
+        let (code, symbol) = entry
+            .split_once(':')
+            .filter(|(code, _)| !code.is_empty())
+            .ok_or_else(|| invalid("Value should be a list of code:symbol pairs".into()))?;
+        if !is_valid_symbol(symbol) {
+            return Err(invalid(format!(
+                "Symbol '{}' should be one or two printable ASCII characters",
+                symbol
+            )));
+        }
+        if symbols.values().any(|other| other == symbol) {
+            return Err(invalid(format!("Duplicate symbol '{}'", symbol)));
+        }
+        symbols.insert(code.to_ascii_uppercase(), symbol.into());
+    }
+    Ok(symbols)
+}
+
+/// Splits a list of thread codes separated by commas or whitespace
+fn split_codes(value: &str) -> impl Iterator<Item = &str> {
+    value
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|code| !code.is_empty())
+}
+
+fn parse_threads<'a>(
+    field: &str,
+    codes: impl Iterator<Item = &'a str>,
+    catalog: &ThreadCatalog,
+) -> Result<Vec<ThreadColor>, InvalidPayloadError> {
+    let mut threads: Vec<ThreadColor> = Vec::new();
+    for code in codes {
+        let thread = catalog.get(code).ok_or_else(|| {
+            InvalidPayloadError::InvalidValue(
+                field.into(),
+                format!("Unknown thread code '{}'", code),
+            )
+        })?;
+        if !threads.contains(&thread) {
+            threads.push(thread);
+        }
+    }
+    Ok(threads)
+}
@@ -5,6 +5,7 @@ pub fn services(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/api")
             .service(api::image::upload)
-            .service(api::image::export),
+            .service(api::image::export)
+            .service(api::image::import),
     );
 }
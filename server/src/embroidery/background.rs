@@ -0,0 +1,200 @@
+use image::{Rgb, RgbImage};
+use lab::Lab;
+use std::collections::{HashMap, VecDeque};
+
+use crate::embroidery::colors::RgbColor;
+use crate::embroidery::metric::ColorMetric;
+
+pub const DEFAULT_TOLERANCE: f32 = 8.0;
+
+/// Cells left as bare fabric because they are close to the background color.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Background {
+    /// Color the cells are compared with. When `None`, the most common color
+    /// on the image borders is used.
+    pub color: Option<RgbColor>,
+    /// Largest color difference from `color` that is still background
+    pub tolerance: f32,
+    /// Only removes background reachable from the image borders, keeping
+    /// the same color inside the subject stitched
+    pub flood_fill: bool,
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Background {
+            color: None,
+            tolerance: DEFAULT_TOLERANCE,
+            flood_fill: true,
+        }
+    }
+}
+
+impl Background {
+    /// Returns in row-major order which pixels of `pic` are background.
+    /// Pixels that are not `stitched` are never background, but the flood
+    /// fill passes through them.
+    pub fn detect(&self, pic: &RgbImage, stitched: &[bool], metric: ColorMetric) -> Vec<bool> {
+        let (width, height) = (pic.width() as usize, pic.height() as usize);
+        let is_stitched = |i: usize| stitched.get(i).copied().unwrap_or(true);
+
+        let Some(reference) = self
+            .color
+            .or_else(|| border_color(pic, &is_stitched))
+            .map(|color| Lab::from_rgb(&color.into()))
+        else {
+            return vec![false; width * height];
+        };
+
+        let mut differences: HashMap<RgbColor, bool> = HashMap::new();
+        let matches: Vec<bool> = pic
+            .pixels()
+            .enumerate()
+            .map(|(i, &pixel)| {
+                is_stitched(i)
+                    && *differences.entry(pixel.into()).or_insert_with(|| {
+                        metric.difference(reference, Lab::from_rgb(&pixel.0)) <= self.tolerance
+                    })
+            })
+            .collect();
+        if !self.flood_fill {
+            return matches;
+        }
+
+        let passable = |i: usize| matches[i] || !is_stitched(i);
+        let mut reached = vec![false; width * height];
+        let mut queue: VecDeque<usize> = border(width, height).filter(|&i| passable(i)).collect();
+        for &i in &queue {
+            reached[i] = true;
+        }
+        while let Some(i) = queue.pop_front() {
+            let (x, y) = (i % width, i / width);
+            let neighbours = [
+                (x > 0).then(|| i - 1),
+                (x + 1 < width).then(|| i + 1),
+                (y > 0).then(|| i - width),
+                (y + 1 < height).then(|| i + width),
+            ];
+            for neighbour in neighbours.into_iter().flatten() {
+                if !reached[neighbour] && passable(neighbour) {
+                    reached[neighbour] = true;
+                    queue.push_back(neighbour);
+                }
+            }
+        }
+
+        reached
+            .into_iter()
+            .zip(matches)
+            .map(|(reached, matches)| reached && matches)
+            .collect()
+    }
+}
+
+/// Indices of the pixels on the borders of a `width` × `height` image
+fn border(width: usize, height: usize) -> impl Iterator<Item = usize> {
+    (0..width * height).filter(move |&i| {
+        let (x, y) = (i % width, i / width);
+        x == 0 || y == 0 || x + 1 == width || y + 1 == height
+    })
+}
+
+/// Mean color of the most common group of similar colors on the image
+/// borders
+fn border_color(pic: &RgbImage, is_stitched: &impl Fn(usize) -> bool) -> Option<RgbColor> {
+    let mut groups: HashMap<[u8; 3], ([u32; 3], u32)> = HashMap::new();
+    let width = pic.width() as usize;
+    for i in border(width, pic.height() as usize) {
+        if !is_stitched(i) {
+            continue;
+        }
+        let Rgb([red, green, blue]) = *pic.get_pixel((i % width) as u32, (i / width) as u32);
+        let (sum, count) = groups
+            .entry([red >> 4, green >> 4, blue >> 4])
+            .or_insert(([0; 3], 0));
+        sum[0] += red as u32;
+        sum[1] += green as u32;
+        sum[2] += blue as u32;
+        *count += 1;
+    }
+
+    let (sum, count) = groups
+        .into_iter()
+        .max_by_key(|&(key, (_, count))| (count, key))?
+        .1;
+    let [red, green, blue] = sum.map(|channel| (channel as f32 / count as f32).round() as u8);
+    Some(RgbColor { red, green, blue })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use image::ImageBuffer;
+
+    const WHITE: Rgb<u8> = Rgb([255, 255, 255]);
+
+    /// White image with a red ring around a white center
+    fn ring() -> RgbImage {
+        ImageBuffer::from_fn(9, 9, |x, y| {
+            let distance = (x as i32 - 4).abs().max((y as i32 - 4).abs());
+            if distance == 2 || distance == 3 {
+                Rgb([200, 20, 20])
+            } else {
+                WHITE
+            }
+        })
+    }
+
+    fn count(mask: &[bool]) -> usize {
+        mask.iter().filter(|&&background| background).count()
+    }
+
+    #[test]
+    fn it_removes_fabric_color_everywhere() {
+        let background = Background {
+            color: Some(WHITE.into()),
+            flood_fill: false,
+            ..Default::default()
+        };
+        let mask = background.detect(&ring(), &[], ColorMetric::default());
+        // 32 border cells and 9 in the center
+        assert_eq!(count(&mask), 41);
+        assert!(mask[4 * 9 + 4]);
+    }
+
+    #[test]
+    fn it_flood_fills_from_borders() {
+        let background = Background::default();
+        let mask = background.detect(&ring(), &[], ColorMetric::default());
+        assert_eq!(count(&mask), 32);
+        assert!(mask[0]);
+        assert!(!mask[4 * 9 + 4]);
+    }
+
+    #[test]
+    fn it_keeps_colors_beyond_tolerance() {
+        let pic = ImageBuffer::from_fn(4, 4, |x, _| {
+            if x < 2 {
+                Rgb([250, 250, 245])
+            } else {
+                Rgb([200, 200, 190])
+            }
+        });
+        let background = Background {
+            color: Some(WHITE.into()),
+            tolerance: 5.0,
+            flood_fill: false,
+        };
+        let mask = background.detect(&pic, &[], ColorMetric::default());
+        assert_eq!(count(&mask), 8);
+    }
+
+    #[test]
+    fn it_ignores_unstitched_cells() {
+        let pic = ImageBuffer::from_pixel(4, 4, WHITE);
+        let stitched: Vec<bool> = (0..16).map(|i| i % 4 != 0).collect();
+        let mask = Background::default().detect(&pic, &stitched, ColorMetric::default());
+        assert_eq!(count(&mask), 12);
+        assert!(!mask[0]);
+    }
+}
@@ -0,0 +1,127 @@
+use lab::Lab;
+use serde::Serialize;
+
+use crate::embroidery::colors::{RgbColor, ThreadColor};
+use crate::embroidery::metric::ColorMetric;
+
+pub const DEFAULT_MAX_DIFFERENCE: f32 = 15.0;
+/// Blends a thread is part of at most, so a palette of n threads gets at most
+/// n blends
+pub const BLENDS_PER_THREAD: usize = 2;
+/// Strands of floss in the needle for a stitch of a single thread
+pub const STRANDS_PER_STITCH: u32 = 2;
+
+/// Two threads stitched with one strand each, giving a shade in between.
+#[derive(Clone, Copy, Serialize, PartialEq, Debug, Eq, Hash)]
+pub struct Blend {
+    pub threads: [ThreadColor; 2],
+    /// Approximate color of the mixed strands
+    pub rgb: RgbColor,
+}
+
+impl Blend {
+    pub fn new(first: ThreadColor, second: ThreadColor) -> Self {
+        let [first_lab, second_lab] =
+            [first, second].map(|thread| Lab::from_rgb(&thread.rgb.into()));
+        let mixed = Lab {
+            l: (first_lab.l + second_lab.l) / 2.0,
+            a: (first_lab.a + second_lab.a) / 2.0,
+            b: (first_lab.b + second_lab.b) / 2.0,
+        };
+        let [red, green, blue] = mixed.to_rgb();
+        Blend {
+            threads: [first, second],
+            rgb: RgbColor { red, green, blue },
+        }
+    }
+
+    /// Codes of both threads, e.g. `310+3865`
+    pub fn name(&self) -> String {
+        format!("{}+{}", self.threads[0].name, self.threads[1].name)
+    }
+}
+
+/// Blends of pairs of `threads` at most `max_difference` apart, closest pairs
+/// first and at most `BLENDS_PER_THREAD` per thread. Pairs further apart
+/// would look speckled rather than mixed. Blends with the color of one of the
+/// threads are skipped.
+pub fn blend_pairs(
+    threads: &[ThreadColor],
+    max_difference: f32,
+    metric: ColorMetric,
+) -> Vec<Blend> {
+    let labs: Vec<Lab> = threads
+        .iter()
+        .map(|thread| Lab::from_rgb(&thread.rgb.into()))
+        .collect();
+    let mut pairs: Vec<(f32, usize, usize)> = Vec::new();
+    for i in 0..threads.len() {
+        for j in i + 1..threads.len() {
+            let difference = metric.difference(labs[i], labs[j]);
+            if difference <= max_difference {
+                pairs.push((difference, i, j));
+            }
+        }
+    }
+    pairs.sort_by(|x, y| x.0.total_cmp(&y.0));
+
+    let mut blends = Vec::new();
+    let mut n_blends: Vec<usize> = vec![0; threads.len()];
+    for (_, i, j) in pairs {
+        if n_blends[i] >= BLENDS_PER_THREAD || n_blends[j] >= BLENDS_PER_THREAD {
+            continue;
+        }
+        let blend = Blend::new(threads[i], threads[j]);
+        let is_new = !threads.iter().any(|thread| thread.rgb == blend.rgb)
+            && !blends.iter().any(|other: &Blend| other.rgb == blend.rgb);
+        if is_new {
+            blends.push(blend);
+            n_blends[i] += 1;
+            n_blends[j] += 1;
+        }
+    }
+    blends
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::embroidery::catalog::ThreadCatalog;
+
+    #[test]
+    fn it_mixes_threads() {
+        let dmc = ThreadCatalog::dmc();
+        let blend = Blend::new(dmc.get("310").unwrap(), dmc.get("B5200").unwrap());
+        assert_eq!(blend.name(), "310+B5200");
+        // Half-way in lightness is a mid gray
+        assert!(blend.rgb.red > 100 && blend.rgb.red < 140);
+        assert_eq!(blend.rgb.red, blend.rgb.green);
+    }
+
+    #[test]
+    fn it_blends_close_threads_only() {
+        let dmc = ThreadCatalog::dmc();
+        let threads: Vec<ThreadColor> = ["310", "3799", "B5200"]
+            .iter()
+            .map(|code| dmc.get(code).unwrap())
+            .collect();
+        let blends = blend_pairs(&threads, DEFAULT_MAX_DIFFERENCE, ColorMetric::default());
+        assert_eq!(blends.len(), 1);
+        assert_eq!(blends[0].name(), "310+3799");
+    }
+
+    #[test]
+    fn it_limits_blends_per_thread() {
+        let threads: Vec<ThreadColor> = ThreadCatalog::dmc().threads().take(150).collect();
+        let blends = blend_pairs(&threads, f32::MAX, ColorMetric::default());
+        assert!(!blends.is_empty());
+        assert!(blends.len() <= threads.len());
+        for thread in &threads {
+            let n_blends = blends
+                .iter()
+                .filter(|blend| blend.threads.contains(thread))
+                .count();
+            assert!(n_blends <= BLENDS_PER_THREAD);
+        }
+    }
+}
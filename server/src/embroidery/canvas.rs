@@ -1,123 +1,611 @@
-use image::imageops::FilterType;
-use image::{ColorType, DynamicImage, GenericImage, GenericImageView, ImageReader, Pixel, Rgb};
+use image::{ColorType, DynamicImage, GenericImage, GenericImageView, Pixel, Rgb, RgbImage};
 use lab::Lab;
 use serde::Serialize;
 use std::cmp::Ordering;
 use std::{collections::HashMap, io::Cursor};
 
-use crate::embroidery::colors::{DmcColor, RgbColor};
-use crate::embroidery::image::ImagePalette;
+use crate::embroidery::background::Background;
+use crate::embroidery::blend::{blend_pairs, Blend, STRANDS_PER_STITCH};
+use crate::embroidery::catalog::ThreadCatalog;
+use crate::embroidery::cleanup::{merge_rare_colors, Cleanup};
+use crate::embroidery::colors::{RgbColor, ThreadColor, ThreadName};
+use crate::embroidery::decode::decode_image;
+use crate::embroidery::dither::{Cells, Dither};
+use crate::embroidery::image::{ImagePalette, ThreadSelection};
+use crate::embroidery::index::{ColorMatcher, LabIndex};
+use crate::embroidery::inventory::Inventory;
+use crate::embroidery::metric::ColorMetric;
+use crate::embroidery::preprocess::Preprocess;
+use crate::embroidery::quantizer::QuantizerKind;
+use crate::embroidery::sampling::Sampling;
+use crate::embroidery::size::{Dimensions, Fabric, TargetSize, MAX_CELLS};
+use crate::embroidery::symbols::SymbolAllocator;
 use crate::error::CanvasError;
 
+pub const DEFAULT_ALPHA_THRESHOLD: u8 = 128;
+pub const DEFAULT_N_COLORS: u8 = 20;
+pub const DEFAULT_FABRIC_COLOR: RgbColor = RgbColor {
+    red: 255,
+    green: 255,
+    blue: 255,
+};
+
 #[derive(Debug, Clone)]
 pub struct CanvasConfig {
     pub img: DynamicImage,
     width: u32,
     height: u32,
+    cell_width: f32,
     cell_height: f32,
     rows: u32,
     columns: u32,
+    cell_aspect_ratio: f32,
+    pub fabric: Fabric,
+    target_size: Option<TargetSize>,
     pub n_colors: u8,
+    pub catalog: &'static ThreadCatalog,
+    pub metric: ColorMetric,
+    pub quantizer: QuantizerKind,
+    pub sampling: Sampling,
+    pub dither: Dither,
+    pub alpha_threshold: u8,
+    pub fabric_color: RgbColor,
+    pub background: Option<Background>,
+    pub cleanup: Option<Cleanup>,
+    pub min_stitches_per_color: Option<u32>,
+    pub include_threads: Vec<ThreadColor>,
+    pub exclude_threads: Vec<ThreadColor>,
+    pub inventory: Option<Inventory>,
+    pub max_blend_difference: Option<f32>,
+    pub palette: Option<Vec<ThreadColor>>,
+    pub symbols: SymbolAllocator,
 }
 
 impl CanvasConfig {
     pub fn new(
         bytes: Vec<u8>,
-        n_cells_in_width: Option<u8>,
+        n_cells_in_width: Option<u32>,
         n_colors: Option<u8>,
     ) -> Result<Self, CanvasError> {
-        let img = ImageReader::new(Cursor::new(bytes))
-            .with_guessed_format()
-            .map_err(CanvasError::ImageFormat)?
-            .decode()?;
+        Ok(Self::from_image(
+            decode_image(bytes)?,
+            n_cells_in_width,
+            n_colors,
+        ))
+    }
+
+    /// Same as `new` for an already decoded image.
+    pub fn from_image(
+        img: DynamicImage,
+        n_cells_in_width: Option<u32>,
+        n_colors: Option<u8>,
+    ) -> Self {
         let (width, height) = img.dimensions();
 
-        let columns = n_cells_in_width.unwrap_or(32) as u32;
-        let cell_height = width as f32 / columns as f32;
+        let columns = n_cells_in_width.unwrap_or(32);
+        let cell_width = width as f32 / columns as f32;
+        let cell_height = cell_width;
         let rows = (height as f32 / cell_height).round() as u32;
 
-        Ok(CanvasConfig {
+        CanvasConfig {
+            cell_width,
             cell_height,
             img,
             width,
             height,
             columns,
             rows,
-            n_colors: n_colors.unwrap_or(20),
-        })
+            cell_aspect_ratio: 1.0,
+            fabric: Fabric::default(),
+            target_size: None,
+            n_colors: n_colors.unwrap_or(DEFAULT_N_COLORS),
+            catalog: ThreadCatalog::dmc(),
+            metric: ColorMetric::default(),
+            quantizer: QuantizerKind::default(),
+            sampling: Sampling::default(),
+            dither: Dither::default(),
+            alpha_threshold: DEFAULT_ALPHA_THRESHOLD,
+            fabric_color: DEFAULT_FABRIC_COLOR,
+            background: None,
+            cleanup: None,
+            min_stitches_per_color: None,
+            include_threads: Vec::new(),
+            exclude_threads: Vec::new(),
+            inventory: None,
+            max_blend_difference: None,
+            palette: None,
+            symbols: SymbolAllocator::default(),
+        }
+    }
+
+    /// Cells `aspect_ratio` times as tall as they are wide, for fabrics
+    /// with fewer rows than stitches per inch or the other way around.
+    pub fn with_cell_aspect_ratio(mut self, aspect_ratio: f32) -> Self {
+        self.cell_aspect_ratio = aspect_ratio;
+        self.update_grid();
+        self
+    }
+
+    pub fn with_fabric(mut self, fabric: Fabric) -> Self {
+        self.fabric = fabric;
+        self.update_grid();
+        self
+    }
+
+    /// Sizes the grid so the design fits in `target_size` on the fabric,
+    /// keeping the image proportions.
+    pub fn with_target_size(mut self, target_size: TargetSize) -> Self {
+        self.target_size = Some(target_size);
+        self.update_grid();
+        self
+    }
+
+    pub fn columns(&self) -> u32 {
+        self.columns
+    }
+
+    pub fn rows(&self) -> u32 {
+        self.rows
+    }
+
+    /// Finished size of the design and the fabric it needs.
+    pub fn dimensions(&self) -> Dimensions {
+        self.fabric
+            .dimensions(self.columns, self.rows, self.cell_aspect_ratio)
+    }
+
+    fn update_grid(&mut self) {
+        let (width, height) = (self.width as f32, self.height as f32);
+        if let Some(target) = self.target_size {
+            let fitting_width = target.width.map(|width| self.fabric.stitches(width));
+            // Rows are as many times longer as the cells are taller, which
+            // cancels out in the columns of the same height
+            let fitting_height = target
+                .height
+                .map(|target| self.fabric.stitches(target) * width / height);
+            let columns = match (fitting_width, fitting_height) {
+                (Some(x), Some(y)) => x.min(y),
+                (Some(columns), None) | (None, Some(columns)) => columns,
+                (None, None) => self.columns as f32,
+            };
+            self.columns = (columns.round() as u32).max(1);
+        }
+        self.cell_width = width / self.columns as f32;
+        self.cell_height = self.cell_width * self.cell_aspect_ratio;
+        self.rows = ((height / self.cell_height).round() as u32).max(1);
+    }
+
+    /// Edits the image before anything else, keeping the number of columns.
+    pub fn with_preprocess(mut self, preprocess: Preprocess) -> Result<Self, CanvasError> {
+        self.img = preprocess.apply(self.img)?;
+        (self.width, self.height) = self.img.dimensions();
+        self.update_grid();
+        Ok(self)
+    }
+
+    pub fn with_catalog(mut self, catalog: &'static ThreadCatalog) -> Self {
+        self.catalog = catalog;
+        self
+    }
+
+    pub fn with_metric(mut self, metric: ColorMetric) -> Self {
+        self.metric = metric;
+        self
+    }
+
+    pub fn with_quantizer(mut self, quantizer: QuantizerKind) -> Self {
+        self.quantizer = quantizer;
+        self
+    }
+
+    pub fn with_sampling(mut self, sampling: Sampling) -> Self {
+        self.sampling = sampling;
+        self
+    }
+
+    pub fn with_dither(mut self, dither: Dither) -> Self {
+        self.dither = dither;
+        self
+    }
+
+    /// Pixels with alpha below `alpha_threshold` are left unstitched.
+    pub fn with_alpha_threshold(mut self, alpha_threshold: u8) -> Self {
+        self.alpha_threshold = alpha_threshold;
+        self
+    }
+
+    pub fn with_fabric_color(mut self, fabric_color: RgbColor) -> Self {
+        self.fabric_color = fabric_color;
+        self
+    }
+
+    /// Leaves cells matching the background unstitched.
+    pub fn with_background(mut self, background: Background) -> Self {
+        self.background = Some(background);
+        self
+    }
+
+    /// Merges clusters of cells smaller than the cleanup allows into their
+    /// neighbours.
+    pub fn with_cleanup(mut self, cleanup: Cleanup) -> Self {
+        self.cleanup = Some(cleanup);
+        self
+    }
+
+    /// Threads used for fewer stitches are replaced with the closest of the
+    /// remaining ones. Included threads and the last color are kept anyway,
+    /// see `Canvas::rare_colors`.
+    pub fn with_min_stitches_per_color(mut self, min_stitches: u32) -> Self {
+        self.min_stitches_per_color = Some(min_stitches);
+        self
+    }
+
+    /// Threads always in the palette, counting towards `n_colors`.
+    pub fn with_include_threads(mut self, threads: Vec<ThreadColor>) -> Self {
+        self.include_threads = threads;
+        self
+    }
+
+    /// Threads never chosen for the palette.
+    pub fn with_exclude_threads(mut self, threads: Vec<ThreadColor>) -> Self {
+        self.exclude_threads = threads;
+        self
+    }
+
+    /// Only threads of the inventory are used, and the canvas reports how
+    /// far it drifted from the image because of it.
+    pub fn with_inventory(mut self, inventory: Inventory) -> Self {
+        self.inventory = Some(inventory);
+        self
+    }
+
+    /// Adds blends of palette threads at most `max_difference` apart as
+    /// colors of their own, no more blends than threads. Blends only pair
+    /// threads of the palette, so they add shades without adding skeins.
+    pub fn with_blends(mut self, max_difference: f32) -> Self {
+        self.max_blend_difference = Some(max_difference);
+        self
+    }
+
+    /// Stitches the image with exactly these threads instead of quantizing
+    /// it. `n_colors` and the thread selection are ignored.
+    pub fn with_palette(mut self, threads: Vec<ThreadColor>) -> Self {
+        self.palette = Some(threads);
+        self
+    }
+
+    /// Prints these symbols, keyed by `override_key`, instead of the allocated
+    /// ones.
+    pub fn with_symbol_overrides(mut self, overrides: HashMap<(String, String), String>) -> Self {
+        self.symbols = SymbolAllocator::new(overrides);
+        self
     }
 }
 
 #[derive(Serialize)]
 pub struct Canvas {
-    pub embroidery: Vec<Vec<RgbColor>>,
-    pub colors: Vec<DmcColor>,
+    /// Thread color of every cell, `None` for cells left unstitched
+    pub embroidery: Vec<Vec<Option<RgbColor>>>,
+    pub colors: Vec<ThreadColor>,
+    pub blends: Vec<Blend>,
     #[serde(skip)]
     config: CanvasConfig,
+    #[serde(skip)]
+    cleaned_cells: usize,
+    #[serde(skip)]
+    color_shortfall: u8,
+    #[serde(skip)]
+    rare_colors: usize,
+    #[serde(skip)]
+    drift: Option<Drift>,
 }
 
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Palette {
-    identifier: String,
-    color: DmcColor,
-    n_stitches: u32,
+    pub identifier: String,
+    /// Printed in the chart cells of the color
+    pub symbol: String,
+    pub color: PaletteColor,
+    pub n_stitches: u32,
+    /// Strands of every thread used for the stitches
+    pub strands: Vec<Strands>,
+}
+
+/// Thread, or blend of threads, cells are stitched with.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct PaletteColor {
+    pub brand: &'static str,
+    /// Thread code, or codes joined by `+` for blends
+    pub name: String,
+    /// Shade name given by the catalog file of the thread
+    #[serde(rename = "threadName", skip_serializing_if = "Option::is_none")]
+    pub thread_name: Option<&'static str>,
+    pub rgb: RgbColor,
+}
+
+impl From<ThreadColor> for PaletteColor {
+    fn from(thread: ThreadColor) -> Self {
+        PaletteColor {
+            brand: thread.brand,
+            name: thread.name.to_string(),
+            thread_name: ThreadCatalog::find(thread.brand)
+                .and_then(|catalog| catalog.thread_name(&thread.name.to_string())),
+            rgb: thread.rgb,
+        }
+    }
+}
+
+impl From<Blend> for PaletteColor {
+    fn from(blend: Blend) -> Self {
+        PaletteColor {
+            brand: blend.threads[0].brand,
+            name: blend.name(),
+            thread_name: None,
+            rgb: blend.rgb,
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Strands {
+    pub code: ThreadName,
+    pub n_strands: u32,
+}
+
+/// Cells per side of the regions drift is reported for, as many as between
+/// the bold lines of the charts
+pub const DRIFT_REGION_SIZE: usize = 10;
+
+/// Color difference between the threads of a restricted palette and the
+/// threads the whole catalog would have matched the same cells with.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Drift {
+    /// Mean difference over every stitched cell
+    pub mean_delta_e: f32,
+    /// Mean difference over the stitched cells of each region, row by row
+    pub regions: Vec<RegionDrift>,
+}
+
+/// Drift of the `DRIFT_REGION_SIZE` cells square starting at `column` and
+/// `row`, smaller on the right and bottom edges.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegionDrift {
+    pub column: usize,
+    pub row: usize,
+    pub n_stitches: u32,
+    pub mean_delta_e: f32,
 }
 
 impl Canvas {
     pub fn new(config: CanvasConfig) -> Result<Self, CanvasError> {
-        let colors = config.img.get_dmc_palette(config.n_colors)?;
+        if config.columns > MAX_CELLS || config.rows > MAX_CELLS {
+            return Err(CanvasError::GridTooLarge(config.columns, config.rows));
+        }
+        let resized = config
+            .sampling
+            .sample(&config.img, config.columns, config.rows);
+        let pic = resized.to_rgb8();
+        let mut stitched: Vec<bool> = resized
+            .to_rgba8()
+            .pixels()
+            .map(|pixel| pixel[3] >= config.alpha_threshold)
+            .collect();
+        if let Some(background) = config.background {
+            let is_background = background.detect(&pic, &stitched, config.metric);
+            for (stitched, is_background) in stitched.iter_mut().zip(is_background) {
+                *stitched &= !is_background;
+            }
+        }
 
-        let mut pic = config
+        // Only pixels of stitched cells make it into the palette
+        let (columns, rows) = pic.dimensions();
+        let cell = |x: u32, y: u32| {
+            let column = (x as u64 * columns as u64 / config.width as u64) as u32;
+            let row = (y as u64 * rows as u64 / config.height as u64) as u32;
+            (row.min(rows - 1) * columns + column.min(columns - 1)) as usize
+        };
+        let pixels: Vec<RgbColor> = config
             .img
-            .resize(config.columns, config.rows, FilterType::CatmullRom)
-            .to_rgb8();
-
-        let mut embroidery: Vec<Vec<RgbColor>> = Vec::with_capacity(config.rows as usize);
-        for y in 0..config.rows {
-            let mut row: Vec<RgbColor> = Vec::with_capacity(config.columns as usize);
-            for x in 0..config.columns {
-                let major_color = pic.get_pixel_mut(x, y).0;
-                let major_color_lab = Lab::from_rgb(&major_color);
-                let closest_color = colors
-                    .iter()
-                    .min_by_key(|&color| {
-                        let dmc_color_lab = Lab::from_rgb(&color.rgb.into());
-                        RgbColor::calculate_diff(major_color_lab, dmc_color_lab) as u32
-                    })
-                    .ok_or(CanvasError::DmcNotFound)?;
-                row.push(closest_color.rgb);
-            }
-            embroidery.push(row)
+            .to_rgba8()
+            .enumerate_pixels()
+            .filter(|&(x, y, pixel)| pixel[3] >= config.alpha_threshold && stitched[cell(x, y)])
+            .map(|(_, _, pixel)| pixel.to_rgb().into())
+            .collect();
+        let selection = ThreadSelection {
+            include: &config.include_threads,
+            exclude: &config.exclude_threads,
+            inventory: config.inventory.as_ref(),
+            ..ThreadSelection::new(config.catalog)
+        };
+        let colors = if let Some(palette) = &config.palette {
+            palette.clone()
+        } else if pixels.is_empty() {
+            config.include_threads.clone()
+        } else {
+            pixels.get_thread_palette(
+                config.n_colors,
+                &selection,
+                config.metric,
+                &config.quantizer,
+            )?
+        };
+
+        let blends: Vec<Blend> = config
+            .max_blend_difference
+            .map(|max_difference| blend_pairs(&colors, max_difference, config.metric))
+            .unwrap_or_default();
+        // Cells refer to the threads first, then to the blends
+        let rgbs: Vec<RgbColor> = colors
+            .iter()
+            .map(|color| color.rgb)
+            .chain(blends.iter().map(|blend| blend.rgb))
+            .collect();
+        let labs: Vec<Lab> = rgbs.iter().map(|&rgb| Lab::from_rgb(&rgb.into())).collect();
+        let index = LabIndex::new(labs.clone(), config.metric);
+        let mut cells = config
+            .dither
+            .assign(&pic, &stitched, &index)
+            .ok_or(CanvasError::DmcNotFound)?;
+        let cleaned_cells = config
+            .cleanup
+            .map_or(0, |cleanup| cleanup.apply(&mut cells, &labs, config.metric));
+        let color_shortfall = match config.palette {
+            Some(_) => 0,
+            None => (config.n_colors as usize).saturating_sub(colors.len()) as u8,
+        };
+
+        let included: Vec<bool> = colors
+            .iter()
+            .map(|color| config.include_threads.contains(color))
+            .chain(blends.iter().map(|_| false))
+            .collect();
+        let kept = config.min_stitches_per_color.map(|min_stitches| {
+            merge_rare_colors(
+                &mut cells,
+                &labs,
+                &included,
+                min_stitches as usize,
+                config.metric,
+            )
+        });
+
+        let rare_colors =
+            kept.as_ref()
+                .zip(config.min_stitches_per_color)
+                .map_or(0, |(kept, min_stitches)| {
+                    let mut stitches: Vec<usize> = vec![0; kept.len()];
+                    for &color in cells.iter().flatten().flatten() {
+                        stitches[color] += 1;
+                    }
+                    (0..kept.len())
+                        .filter(|&color| kept[color] && stitches[color] < min_stitches as usize)
+                        .count()
+                });
+
+        let drift = config.inventory.as_ref().map(|_| {
+            let catalog = config.catalog.lab_index(config.metric);
+            measure_drift(&pic, &cells, &labs, catalog, config.metric)
+        });
+
+        let embroidery: Vec<Vec<Option<RgbColor>>> = cells
+            .into_iter()
+            .map(|row| {
+                row.into_iter()
+                    .map(|cell| cell.map(|cell| rgbs[cell]))
+                    .collect()
+            })
+            .collect();
+        let mut colors = colors;
+        let mut blends = blends;
+        if let Some(kept) = kept {
+            let (kept_threads, kept_blends) = kept.split_at(colors.len());
+            let mut kept_blends = kept_blends.iter();
+            blends.retain(|_| kept_blends.next().copied().unwrap_or(false));
+            // Threads of the remaining blends are still needed
+            let mut kept_threads = kept_threads.iter();
+            colors.retain(|color| {
+                kept_threads.next().copied().unwrap_or(false)
+                    || blends.iter().any(|blend| blend.threads.contains(color))
+            });
         }
 
         Ok(Canvas {
             config,
             embroidery,
             colors,
+            blends,
+            cleaned_cells,
+            color_shortfall,
+            rare_colors,
+            drift,
         })
     }
 
+    /// Canvas of cells already stitched, e.g. read from a chart file, on the
+    /// grid of `config`. Cells should only hold colors of `colors` and
+    /// `blends`.
+    pub fn from_cells(
+        config: CanvasConfig,
+        embroidery: Vec<Vec<Option<RgbColor>>>,
+        colors: Vec<ThreadColor>,
+        blends: Vec<Blend>,
+    ) -> Self {
+        Canvas {
+            config,
+            embroidery,
+            colors,
+            blends,
+            cleaned_cells: 0,
+            color_shortfall: 0,
+            rare_colors: 0,
+            drift: None,
+        }
+    }
+
+    /// Number of requested colors the image could not provide distinct
+    /// threads for.
+    pub fn color_shortfall(&self) -> u8 {
+        self.color_shortfall
+    }
+
+    /// Number of colors left with fewer stitches than the minimum per color,
+    /// as they are included threads or the last color.
+    pub fn rare_colors(&self) -> usize {
+        self.rare_colors
+    }
+
+    /// Number of cells whose color was changed by the confetti cleanup.
+    pub fn cleaned_cells(&self) -> usize {
+        self.cleaned_cells
+    }
+
+    pub fn dimensions(&self) -> Dimensions {
+        self.config.dimensions()
+    }
+
+    pub fn fabric(&self) -> Fabric {
+        self.config.fabric
+    }
+
+    pub fn fabric_color(&self) -> RgbColor {
+        self.config.fabric_color
+    }
+
+    /// Drift caused by restricting the threads to an inventory, `None`
+    /// without one.
+    pub fn drift(&self) -> Option<&Drift> {
+        self.drift.as_ref()
+    }
+
     pub fn get_bytes(&self) -> Result<Vec<u8>, CanvasError> {
-        let width = self.config.width;
-        let height = self.config.height;
+        // Grids finer than the image are rendered larger, at a pixel per cell
+        // at least
+        let scale = (1.0 / self.config.cell_width.min(self.config.cell_height))
+            .ceil()
+            .max(1.0);
+        let width = self.config.width * scale as u32;
+        let height = self.config.height * scale as u32;
+        let cell_width = self.config.cell_width * scale;
+        let cell_height = self.config.cell_height * scale;
         let mut image = DynamicImage::new(width, height, ColorType::Rgb8);
 
         for (n_row, row) in self.embroidery.iter().enumerate() {
             let n_row = n_row as f32;
-            let y_start = (n_row * self.config.cell_height).ceil() as u32;
-            let current_row_limit =
-                (((n_row + 1.0) * self.config.cell_height).ceil() as u32).min(height);
+            let y_start = (n_row * cell_height).ceil() as u32;
+            let current_row_limit = (((n_row + 1.0) * cell_height).ceil() as u32).min(height);
 
             for (n_cell, cell) in row.iter().enumerate() {
                 let n_cell = n_cell as f32;
-                let x_start = (n_cell * self.config.cell_height).ceil() as u32;
-                let cell_limit =
-                    (((n_cell + 1.0) * self.config.cell_height).ceil() as u32).min(width);
+                let x_start = (n_cell * cell_width).ceil() as u32;
+                let cell_limit = (((n_cell + 1.0) * cell_width).ceil() as u32).min(width);
 
+                let color: Rgb<u8> = cell.unwrap_or(self.config.fabric_color).into();
                 for y in y_start..current_row_limit {
                     for x in x_start..cell_limit {
-                        let color: Rgb<u8> = (*cell).into();
                         image.put_pixel(x, y, color.to_rgba())
                     }
                 }
@@ -128,11 +616,20 @@ impl Canvas {
         Ok(bytes)
     }
 
-    pub fn get_dmc_palette(&self) -> Vec<Palette> {
+    pub fn get_thread_palette(&self) -> Vec<Palette> {
         let mut palette: Vec<Palette> = Vec::with_capacity(self.colors.len());
         let threads: HashMap<RgbColor, u32> = Self::calculate_stitches(self);
-        let mut colors = self.colors.clone();
-        colors.sort_by(|color_1, color_2| {
+        // Codes of the threads in the needle and strands of each per stitch
+        let mut colors: Vec<(PaletteColor, Vec<(ThreadName, u32)>)> = self
+            .colors
+            .iter()
+            .map(|&thread| (thread.into(), vec![(thread.name, STRANDS_PER_STITCH)]))
+            .chain(self.blends.iter().map(|&blend| {
+                let strands = blend.threads.map(|thread| (thread.name, 1)).to_vec();
+                (blend.into(), strands)
+            }))
+            .collect();
+        colors.sort_by(|(color_1, _), (color_2, _)| {
             let lab_1 = Lab::from_rgb(&color_1.rgb.into());
             let lab_2 = Lab::from_rgb(&color_2.rgb.into());
 
@@ -141,16 +638,30 @@ impl Canvas {
                 .unwrap_or(Ordering::Equal)
         });
 
-        let mut identifier: u8 = 1;
-        for &color in colors.iter() {
-            if let Some(thread) = threads.get(&color.rgb) {
-                palette.push(Palette {
-                    identifier: format!("{:02}", identifier),
-                    color,
-                    n_stitches: *thread,
-                });
-                identifier += 1;
-            }
+        let used = colors.into_iter().filter_map(|(color, strands)| {
+            let &n_stitches = threads.get(&color.rgb)?;
+            Some((color, strands, n_stitches))
+        });
+        for (index, (color, strands, n_stitches)) in used.enumerate() {
+            palette.push(Palette {
+                identifier: format!("{:02}", index + 1),
+                symbol: String::new(),
+                color,
+                n_stitches,
+                strands: strands
+                    .into_iter()
+                    .map(|(code, n_strands)| Strands {
+                        code,
+                        n_strands: n_strands * n_stitches,
+                    })
+                    .collect(),
+            });
+        }
+
+        let colors: Vec<PaletteColor> = palette.iter().map(|entry| entry.color.clone()).collect();
+        let symbols = self.config.symbols.allocate(&colors, self.config.metric);
+        for (entry, symbol) in palette.iter_mut().zip(symbols) {
+            entry.symbol = symbol;
         }
         palette
     }
@@ -158,7 +669,7 @@ impl Canvas {
     fn calculate_stitches(&self) -> HashMap<RgbColor, u32> {
         let mut stitches: HashMap<RgbColor, u32> = HashMap::with_capacity(self.colors.len());
         for row in &self.embroidery {
-            for color in row {
+            for color in row.iter().flatten() {
                 stitches
                     .entry(*color)
                     .and_modify(|count| *count += 1)
@@ -169,10 +680,66 @@ impl Canvas {
     }
 }
 
+/// Drift of every cell from the thread of `catalog` closest to its pixel to
+/// the color it was stitched with.
+fn measure_drift(
+    pic: &RgbImage,
+    cells: &Cells,
+    labs: &[Lab],
+    catalog: &LabIndex,
+    metric: ColorMetric,
+) -> Drift {
+    let mut matcher = ColorMatcher::new(catalog);
+    let columns = cells.first().map_or(0, |row| row.len());
+    let regions_per_row = columns.div_ceil(DRIFT_REGION_SIZE);
+    let n_regions = regions_per_row * cells.len().div_ceil(DRIFT_REGION_SIZE);
+    let mut totals: Vec<(u32, f32)> = vec![(0, 0.0); n_regions];
+    for (y, row) in cells.iter().enumerate() {
+        for (x, cell) in row.iter().enumerate() {
+            let Some(color) = *cell else {
+                continue;
+            };
+            let pixel = pic.get_pixel(x as u32, y as u32);
+            let Some(unrestricted) = matcher.closest(RgbColor::from(*pixel)) else {
+                continue;
+            };
+            let region = y / DRIFT_REGION_SIZE * regions_per_row + x / DRIFT_REGION_SIZE;
+            let total = &mut totals[region];
+            total.0 += 1;
+            total.1 += metric.difference(catalog.lab(unrestricted), labs[color]);
+        }
+    }
+
+    let (n_stitches, sum) = totals.iter().fold((0, 0.0), |(count, sum), total| {
+        (count + total.0, sum + total.1)
+    });
+    Drift {
+        mean_delta_e: if n_stitches > 0 {
+            sum / n_stitches as f32
+        } else {
+            0.0
+        },
+        regions: totals
+            .into_iter()
+            .enumerate()
+            .filter(|(_, (n_stitches, _))| *n_stitches > 0)
+            .map(|(region, (n_stitches, sum))| RegionDrift {
+                column: region % regions_per_row * DRIFT_REGION_SIZE,
+                row: region / regions_per_row * DRIFT_REGION_SIZE,
+                n_stitches,
+                mean_delta_e: sum / n_stitches as f32,
+            })
+            .collect(),
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
-    use image::ImageBuffer;
+    use crate::embroidery::cleanup::Connectivity;
+    use crate::embroidery::dither::DitherMode;
+    use crate::embroidery::preprocess::{Crop, Rotation};
+    use image::{ImageBuffer, ImageReader, Rgba};
 
     fn generate_image_bytes(width: Option<u32>, height: Option<u32>) -> Vec<u8> {
         let image_buffer =
@@ -195,31 +762,580 @@ mod test {
     #[test]
     fn it_creates_config_invalid_format() {
         let bytes = vec![123, 200, 1];
-        let n_cells_in_width: u8 = 10;
+        let n_cells_in_width: u32 = 10;
         let n_colors: u8 = 5;
 
         let err = CanvasConfig::new(bytes, Some(n_cells_in_width), Some(n_colors)).unwrap_err();
-        assert_eq!(err.to_string(), "The image format could not be determined");
+        assert!(matches!(err, CanvasError::UnsupportedFormat));
     }
 
     #[test]
     fn it_gets_canvas() {
         let bytes = generate_image_bytes(None, None);
-        let n_cells_in_width: u8 = 10;
+        let n_cells_in_width: u32 = 10;
         let n_colors: u8 = 5;
 
         let config = CanvasConfig::new(bytes, Some(n_cells_in_width), Some(n_colors)).unwrap();
         let canvas = Canvas::new(config).unwrap();
-        let canvas_palette = canvas.get_dmc_palette();
+        let canvas_palette = canvas.get_thread_palette();
 
         assert_eq!(canvas.embroidery[0].len(), n_cells_in_width as usize);
         assert_eq!(canvas_palette.len(), n_colors as usize);
     }
 
+    #[test]
+    fn it_gets_canvas_of_brand() {
+        let bytes = generate_image_bytes(None, None);
+        let catalog = ThreadCatalog::find("Madeira").unwrap();
+
+        let config = CanvasConfig::new(bytes, Some(10), Some(5))
+            .unwrap()
+            .with_catalog(catalog);
+        let canvas = Canvas::new(config).unwrap();
+
+        assert!(canvas.colors.iter().all(|color| color.brand == "Madeira"));
+        assert!(canvas
+            .get_thread_palette()
+            .iter()
+            .all(|palette| palette.color.brand == "Madeira"));
+    }
+
+    #[test]
+    fn it_gets_dithered_canvas() {
+        let bytes = generate_image_bytes(Some(100), Some(100));
+
+        for mode in DitherMode::ALL {
+            let config = CanvasConfig::new(bytes.clone(), Some(40), Some(6))
+                .unwrap()
+                .with_dither(Dither::new(mode, 0.8));
+            let canvas = Canvas::new(config).unwrap();
+
+            assert_eq!(canvas.embroidery.len(), 40);
+            assert!(canvas
+                .embroidery
+                .iter()
+                .flatten()
+                .all(|cell| cell
+                    .is_some_and(|cell| canvas.colors.iter().any(|color| color.rgb == cell))));
+        }
+    }
+
+    fn generate_transparent_image_bytes() -> Vec<u8> {
+        // Left half is transparent black, right half an opaque gradient
+        let image_buffer = ImageBuffer::from_fn(20, 20, |x, y| {
+            if x < 10 {
+                Rgba([0, 0, 0, 0])
+            } else {
+                Rgba([(x * 12) as u8, (y * 12) as u8, 200, 255])
+            }
+        });
+        let mut bytes = Vec::new();
+        DynamicImage::ImageRgba8(image_buffer)
+            .write_to(&mut Cursor::new(&mut bytes), image::ImageFormat::Png)
+            .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn it_leaves_transparent_cells_empty() {
+        let bytes = generate_transparent_image_bytes();
+        let config = CanvasConfig::new(bytes, Some(10), Some(4)).unwrap();
+        let canvas = Canvas::new(config).unwrap();
+
+        for row in &canvas.embroidery {
+            assert!(row[..4].iter().all(Option::is_none));
+            assert!(row[6..].iter().all(Option::is_some));
+        }
+        assert!(canvas.colors.iter().all(|color| color.name != "310"));
+
+        let n_stitched = canvas.embroidery.iter().flatten().flatten().count() as u32;
+        let n_stitches: u32 = canvas
+            .get_thread_palette()
+            .iter()
+            .map(|palette| palette.n_stitches)
+            .sum();
+        assert_eq!(n_stitches, n_stitched);
+    }
+
+    #[test]
+    fn it_renders_empty_cells_as_fabric() {
+        let bytes = generate_transparent_image_bytes();
+        let fabric_color = RgbColor {
+            red: 240,
+            green: 230,
+            blue: 200,
+        };
+        let config = CanvasConfig::new(bytes, Some(10), Some(4))
+            .unwrap()
+            .with_fabric_color(fabric_color);
+        let canvas_bytes = Canvas::new(config).unwrap().get_bytes().unwrap();
+
+        let image = image::load_from_memory(&canvas_bytes).unwrap().to_rgb8();
+        assert_eq!(RgbColor::from(*image.get_pixel(0, 0)), fabric_color);
+        assert_eq!(RgbColor::from(*image.get_pixel(5, 19)), fabric_color);
+    }
+
+    #[test]
+    fn it_stitches_transparent_cells_without_threshold() {
+        let bytes = generate_transparent_image_bytes();
+        let config = CanvasConfig::new(bytes, Some(10), Some(4))
+            .unwrap()
+            .with_alpha_threshold(0);
+        let canvas = Canvas::new(config).unwrap();
+
+        assert!(canvas.embroidery.iter().flatten().all(Option::is_some));
+    }
+
+    fn generate_framed_image_bytes() -> Vec<u8> {
+        // White background around a gradient square
+        let image_buffer = ImageBuffer::from_fn(40, 40, |x, y| {
+            if (10..30).contains(&x) && (10..30).contains(&y) {
+                Rgb([(x * 6) as u8, (y * 6) as u8, 60])
+            } else {
+                Rgb([255, 255, 255])
+            }
+        });
+        let mut bytes = Vec::new();
+        DynamicImage::ImageRgb8(image_buffer)
+            .write_to(&mut Cursor::new(&mut bytes), image::ImageFormat::Png)
+            .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn it_leaves_fabric_cells_unstitched() {
+        let white = RgbColor {
+            red: 255,
+            green: 255,
+            blue: 255,
+        };
+        for flood_fill in [false, true] {
+            let config = CanvasConfig::new(generate_framed_image_bytes(), Some(10), Some(4))
+                .unwrap()
+                .with_background(Background {
+                    color: Some(white),
+                    flood_fill,
+                    ..Default::default()
+                });
+            let canvas = Canvas::new(config).unwrap();
+
+            assert!(canvas.embroidery[0].iter().all(Option::is_none));
+            assert!(canvas.embroidery[5][3..7].iter().all(Option::is_some));
+
+            let n_stitched = canvas.embroidery.iter().flatten().flatten().count() as u32;
+            let n_stitches: u32 = canvas
+                .get_thread_palette()
+                .iter()
+                .map(|palette| palette.n_stitches)
+                .sum();
+            assert_eq!(n_stitches, n_stitched);
+            assert!(n_stitched <= 64);
+        }
+    }
+
+    #[test]
+    fn it_detects_background() {
+        let config = CanvasConfig::new(generate_framed_image_bytes(), Some(10), Some(4))
+            .unwrap()
+            .with_background(Background::default());
+        let canvas = Canvas::new(config).unwrap();
+
+        assert!(canvas.embroidery[9].iter().all(Option::is_none));
+        assert!(canvas.embroidery[4][4].is_some());
+    }
+
+    #[test]
+    fn it_cleans_up_confetti() {
+        let bytes = generate_image_bytes(Some(100), Some(100));
+        let dither = Dither::new(DitherMode::FloydSteinberg, 1.0);
+
+        let config = CanvasConfig::new(bytes.clone(), Some(30), Some(8))
+            .unwrap()
+            .with_dither(dither);
+        let canvas = Canvas::new(config).unwrap();
+        assert_eq!(canvas.cleaned_cells(), 0);
+
+        let config = CanvasConfig::new(bytes, Some(30), Some(8))
+            .unwrap()
+            .with_dither(dither)
+            .with_cleanup(Cleanup::new(3, Connectivity::Four));
+        let cleaned = Canvas::new(config).unwrap();
+        assert!(cleaned.cleaned_cells() > 0);
+
+        let changed = canvas
+            .embroidery
+            .iter()
+            .flatten()
+            .zip(cleaned.embroidery.iter().flatten())
+            .filter(|(before, after)| before != after)
+            .count();
+        assert_eq!(changed, cleaned.cleaned_cells());
+    }
+
+    #[test]
+    fn it_merges_rarely_used_threads() {
+        let bytes = generate_image_bytes(Some(100), Some(100));
+
+        for min_stitches in [1, 20, 80, 150] {
+            let config = CanvasConfig::new(bytes.clone(), Some(30), Some(12))
+                .unwrap()
+                .with_dither(Dither::new(DitherMode::Atkinson, 1.0))
+                .with_min_stitches_per_color(min_stitches);
+            let canvas = Canvas::new(config).unwrap();
+            let palette = canvas.get_thread_palette();
+
+            assert!(palette
+                .iter()
+                .all(|palette| palette.n_stitches >= min_stitches));
+            assert_eq!(palette.len(), canvas.colors.len());
+            let identifiers: Vec<String> =
+                (1..=palette.len()).map(|i| format!("{:02}", i)).collect();
+            assert_eq!(
+                palette
+                    .iter()
+                    .map(|palette| palette.identifier.clone())
+                    .collect::<Vec<String>>(),
+                identifiers
+            );
+        }
+    }
+
+    #[test]
+    fn it_reports_color_shortfall() {
+        let image_buffer = ImageBuffer::from_fn(20, 20, |x, _| {
+            if x < 10 {
+                Rgb([0, 0, 0])
+            } else {
+                Rgb([255, 255, 255])
+            }
+        });
+        let mut bytes = Vec::new();
+        DynamicImage::ImageRgb8(image_buffer)
+            .write_to(&mut Cursor::new(&mut bytes), image::ImageFormat::Png)
+            .unwrap();
+
+        let config = CanvasConfig::new(bytes, Some(10), Some(5)).unwrap();
+        let canvas = Canvas::new(config).unwrap();
+
+        assert_eq!(canvas.colors.len(), 2);
+        assert_eq!(canvas.color_shortfall(), 3);
+    }
+
+    #[test]
+    fn it_restricts_threads_to_inventory() {
+        let bytes = generate_image_bytes(Some(50), Some(50));
+        let dmc = ThreadCatalog::dmc();
+        let inventory = Inventory::new(
+            ["310", "B5200", "321", "3865", "798", "699"]
+                .iter()
+                .map(|code| dmc.get(code).unwrap())
+                .collect(),
+        );
+
+        let unrestricted =
+            Canvas::new(CanvasConfig::new(bytes.clone(), Some(20), Some(5)).unwrap()).unwrap();
+        assert!(unrestricted.drift().is_none());
+
+        let config = CanvasConfig::new(bytes, Some(20), Some(5))
+            .unwrap()
+            .with_inventory(inventory.clone());
+        let canvas = Canvas::new(config).unwrap();
+        assert!(canvas.colors.iter().all(|color| inventory.contains(color)));
+
+        let drift = canvas.drift().unwrap();
+        let n_stitches: u32 = drift.regions.iter().map(|region| region.n_stitches).sum();
+        assert_eq!(n_stitches, 400);
+        assert!(drift.mean_delta_e > 0.0);
+        let corners: Vec<(usize, usize)> = drift
+            .regions
+            .iter()
+            .map(|region| (region.column, region.row))
+            .collect();
+        assert_eq!(corners, [(0, 0), (10, 0), (0, 10), (10, 10)]);
+    }
+
+    #[test]
+    fn it_measures_drift_from_the_whole_catalog() {
+        let dmc = ThreadCatalog::dmc();
+        let red = dmc.get("666").unwrap();
+        let image_buffer = ImageBuffer::from_pixel(20, 20, Rgb::from(red.rgb));
+        let mut bytes = Vec::new();
+        DynamicImage::ImageRgb8(image_buffer)
+            .write_to(&mut Cursor::new(&mut bytes), image::ImageFormat::Png)
+            .unwrap();
+        let drift = |codes: &[&str]| {
+            let inventory =
+                Inventory::new(codes.iter().map(|code| dmc.get(code).unwrap()).collect());
+            let config = CanvasConfig::new(bytes.clone(), Some(20), Some(3))
+                .unwrap()
+                .with_inventory(inventory);
+            Canvas::new(config).unwrap().drift().cloned().unwrap()
+        };
+
+        // The closest thread is owned
+        let owned = drift(&["666", "310"]);
+        assert_eq!(owned.mean_delta_e, 0.0);
+
+        let missing = drift(&["310", "B5200"]);
+        let expected = ["310", "B5200"]
+            .map(|code| {
+                let lab = Lab::from_rgb(&dmc.get(code).unwrap().rgb.into());
+                ColorMetric::default().difference(Lab::from_rgb(&red.rgb.into()), lab)
+            })
+            .into_iter()
+            .fold(f32::INFINITY, f32::min);
+        assert!((missing.mean_delta_e - expected).abs() < 1e-3);
+        assert_eq!(missing.regions.len(), 4);
+        assert!(missing.regions.iter().all(
+            |region| region.n_stitches == 100 && (region.mean_delta_e - expected).abs() < 1e-3
+        ));
+    }
+
+    #[test]
+    fn it_bounds_palette_with_blends() {
+        let bytes = generate_image_bytes(Some(100), Some(100));
+        let config = CanvasConfig::new(bytes, Some(60), Some(60))
+            .unwrap()
+            .with_blends(f32::MAX);
+        let canvas = Canvas::new(config).unwrap();
+
+        assert!(!canvas.blends.is_empty());
+        assert!(canvas.blends.len() <= canvas.colors.len());
+        assert!(canvas.get_thread_palette().len() <= 2 * 60);
+    }
+
+    #[test]
+    fn it_stitches_blends() {
+        let image_buffer = ImageBuffer::from_fn(40, 10, |x, _| {
+            let value = (x * 255 / 39) as u8;
+            Rgb([value, value, value])
+        });
+        let mut bytes = Vec::new();
+        DynamicImage::ImageRgb8(image_buffer)
+            .write_to(&mut Cursor::new(&mut bytes), image::ImageFormat::Png)
+            .unwrap();
+
+        let config = CanvasConfig::new(bytes, Some(40), Some(3))
+            .unwrap()
+            .with_blends(60.0);
+        let canvas = Canvas::new(config).unwrap();
+        assert!(!canvas.blends.is_empty());
+
+        let palette = canvas.get_thread_palette();
+        let blended: Vec<&Palette> = palette
+            .iter()
+            .filter(|palette| palette.strands.len() == 2)
+            .collect();
+        assert!(!blended.is_empty());
+        for palette in blended {
+            assert!(palette.color.name.contains('+'));
+            assert!(palette
+                .strands
+                .iter()
+                .all(|strands| strands.n_strands == palette.n_stitches));
+        }
+        for palette in palette.iter().filter(|palette| palette.strands.len() == 1) {
+            assert_eq!(
+                palette.strands[0].n_strands,
+                palette.n_stitches * STRANDS_PER_STITCH
+            );
+        }
+    }
+
+    #[test]
+    fn it_numbers_more_than_255_threads() {
+        let bytes = generate_image_bytes(Some(20), Some(15));
+        let config = CanvasConfig::new(bytes, Some(20), Some(10)).unwrap();
+        let threads: Vec<ThreadColor> = ThreadCatalog::dmc().threads().take(300).collect();
+        let embroidery = threads
+            .chunks(20)
+            .map(|row| row.iter().map(|thread| Some(thread.rgb)).collect())
+            .collect();
+        let canvas = Canvas::from_cells(config, embroidery, threads, Vec::new());
+
+        let palette = canvas.get_thread_palette();
+        assert_eq!(palette.len(), 300);
+        assert_eq!(palette[0].identifier, "01");
+        assert_eq!(palette[255].identifier, "256");
+        assert_eq!(palette[299].identifier, "300");
+    }
+
+    #[test]
+    fn it_uses_custom_palette() {
+        let bytes = generate_image_bytes(Some(50), Some(50));
+        let dmc = ThreadCatalog::dmc();
+        let threads: Vec<ThreadColor> = ["310", "B5200", "321", "3865"]
+            .iter()
+            .map(|code| dmc.get(code).unwrap())
+            .collect();
+
+        let config = CanvasConfig::new(bytes, Some(20), Some(10))
+            .unwrap()
+            .with_palette(threads.clone());
+        let canvas = Canvas::new(config).unwrap();
+
+        assert_eq!(canvas.colors, threads);
+        assert_eq!(canvas.color_shortfall(), 0);
+        assert!(canvas
+            .embroidery
+            .iter()
+            .flatten()
+            .flatten()
+            .all(|rgb| threads.iter().any(|thread| thread.rgb == *rgb)));
+    }
+
+    #[test]
+    fn it_samples_cells() {
+        let bytes = generate_image_bytes(Some(50), Some(40));
+        for sampling in Sampling::ALL {
+            let config = CanvasConfig::new(bytes.clone(), Some(10), Some(5))
+                .unwrap()
+                .with_sampling(sampling);
+            let canvas = Canvas::new(config).unwrap();
+            assert_eq!(canvas.embroidery.len(), 8, "{sampling}");
+            assert!(canvas.embroidery.iter().all(|row| row.len() == 10));
+        }
+    }
+
+    #[test]
+    fn it_uses_cell_aspect_ratio() {
+        let bytes = generate_image_bytes(Some(40), Some(40));
+        for sampling in Sampling::ALL {
+            let config = CanvasConfig::new(bytes.clone(), Some(10), Some(5))
+                .unwrap()
+                .with_sampling(sampling)
+                .with_cell_aspect_ratio(2.0);
+            let canvas = Canvas::new(config).unwrap();
+            assert_eq!(canvas.embroidery.len(), 5, "{sampling}");
+            assert!(canvas.embroidery.iter().all(|row| row.len() == 10));
+
+            // Cells are rendered 4 pixels wide and 8 pixels tall
+            let canvas_bytes = canvas.get_bytes().unwrap();
+            let image = image::load_from_memory(&canvas_bytes).unwrap().to_rgb8();
+            assert_eq!(image.dimensions(), (40, 40));
+            for (n_row, row) in canvas.embroidery.iter().enumerate() {
+                for (n_cell, cell) in row.iter().enumerate() {
+                    let expected: Rgb<u8> = cell.unwrap().into();
+                    let (x, y) = (n_cell as u32 * 4, n_row as u32 * 8);
+                    assert_eq!(*image.get_pixel(x, y), expected);
+                    assert_eq!(*image.get_pixel(x + 3, y + 7), expected);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn it_sizes_grid_by_fabric() {
+        let bytes = generate_image_bytes(Some(100), Some(50));
+        let config = CanvasConfig::new(bytes, None, Some(5))
+            .unwrap()
+            .with_fabric(Fabric::default())
+            .with_target_size(TargetSize {
+                width: Some(20.0),
+                height: None,
+            });
+        assert_eq!((config.columns(), config.rows()), (280, 140));
+
+        let dimensions = config.dimensions();
+        assert_eq!(dimensions.finished_width, 20.0);
+        assert_eq!(dimensions.finished_height, 10.0);
+        assert_eq!(dimensions.fabric_width, 26.0);
+
+        let canvas = Canvas::new(config).unwrap();
+        assert_eq!(canvas.embroidery.len(), 140);
+        assert_eq!(canvas.embroidery[0].len(), 280);
+        // Cells smaller than a pixel are rendered larger
+        let image = image::load_from_memory(&canvas.get_bytes().unwrap()).unwrap();
+        assert_eq!(image.dimensions(), (300, 150));
+    }
+
+    #[test]
+    fn it_fits_target_size() {
+        let bytes = generate_image_bytes(Some(100), Some(50));
+        let config = CanvasConfig::new(bytes.clone(), None, Some(5))
+            .unwrap()
+            .with_target_size(TargetSize {
+                width: Some(10.0),
+                height: Some(2.0),
+            });
+        assert_eq!((config.columns(), config.rows()), (56, 28));
+
+        let config = CanvasConfig::new(bytes, None, Some(5))
+            .unwrap()
+            .with_target_size(TargetSize {
+                width: Some(100.0),
+                height: None,
+            });
+        assert!(matches!(
+            Canvas::new(config),
+            Err(CanvasError::GridTooLarge(1400, 700))
+        ));
+    }
+
+    #[test]
+    fn it_preprocesses_image() {
+        let image_buffer = ImageBuffer::from_fn(60, 30, |x, _| {
+            if x < 20 {
+                Rgb([20, 20, 20])
+            } else {
+                Rgb([200, 200, 200])
+            }
+        });
+        let mut bytes = Vec::new();
+        DynamicImage::ImageRgb8(image_buffer)
+            .write_to(&mut Cursor::new(&mut bytes), image::ImageFormat::Png)
+            .unwrap();
+
+        let config = CanvasConfig::new(bytes, Some(10), Some(3))
+            .unwrap()
+            .with_preprocess(Preprocess {
+                crop: Some(Crop {
+                    x: 0,
+                    y: 0,
+                    width: 40,
+                    height: 30,
+                }),
+                rotation: Rotation::Quarter,
+                auto_levels: true,
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!((config.columns(), config.rows()), (10, 13));
+
+        let canvas = Canvas::new(config).unwrap();
+        // The dark half ends up at the top, stretched to black
+        let top = canvas.embroidery[0][5].unwrap();
+        let bottom = canvas.embroidery[12][5].unwrap();
+        assert!(top.red < 30);
+        assert!(bottom.red > 230);
+    }
+
+    #[test]
+    fn it_includes_and_excludes_threads() {
+        let bytes = generate_image_bytes(Some(50), Some(50));
+        let dmc = ThreadCatalog::dmc();
+        let include = vec![dmc.get("310").unwrap(), dmc.get("B5200").unwrap()];
+        let exclude = vec![dmc.get("3865").unwrap()];
+
+        let config = CanvasConfig::new(bytes, Some(20), Some(6))
+            .unwrap()
+            .with_include_threads(include.clone())
+            .with_exclude_threads(exclude.clone())
+            .with_min_stitches_per_color(1000);
+        let canvas = Canvas::new(config).unwrap();
+
+        assert!(include.iter().all(|thread| canvas.colors.contains(thread)));
+        assert!(!canvas.colors.contains(&exclude[0]));
+        assert!(canvas.colors.len() <= 6);
+        // 400 cells can't give 1000 stitches to any thread kept
+        assert!(canvas.rare_colors() >= include.len());
+        assert_eq!(canvas.rare_colors(), canvas.colors.len());
+    }
+
     #[test]
     fn it_gets_canvas_bytes() {
         let bytes = generate_image_bytes(Some(10), Some(10));
-        let n_cells_in_width: u8 = 10;
+        let n_cells_in_width: u32 = 10;
         let n_colors: u8 = 5;
 
         let config = CanvasConfig::new(bytes, Some(n_cells_in_width), Some(n_colors)).unwrap();
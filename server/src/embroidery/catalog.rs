@@ -0,0 +1,649 @@
+use lab::Lab;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use crate::embroidery::colors::{RgbColor, ThreadColor, ThreadName, RGB_TO_DMC};
+use crate::embroidery::index::LabIndex;
+use crate::embroidery::metric::ColorMetric;
+use crate::error::CatalogError;
+
+pub const DEFAULT_BRAND: &str = "DMC";
+
+static CATALOGS: OnceLock<Vec<ThreadCatalog>> = OnceLock::new();
+
+fn built_in_catalogs() -> Vec<ThreadCatalog> {
+    let dmc = ThreadCatalog::new(DEFAULT_BRAND, RGB_TO_DMC.to_vec());
+    let anchor = dmc.cross_reference("Anchor", &ANCHOR_TO_DMC);
+    let madeira = dmc.cross_reference("Madeira", &MADEIRA_TO_DMC);
+    let cosmo = dmc.cross_reference("Cosmo", &COSMO_TO_DMC);
+    vec![dmc, anchor, madeira, cosmo]
+}
+
+fn catalogs() -> &'static Vec<ThreadCatalog> {
+    CATALOGS.get_or_init(built_in_catalogs)
+}
+
+/// Adds catalogs next to the built-in ones. A catalog whose brand matches a
+/// built-in one replaces it. Must be called before the first lookup.
+pub fn register(extra: Vec<ThreadCatalog>) -> Result<(), CatalogError> {
+    CATALOGS
+        .set(merge_with_built_ins(extra))
+        .map_err(|_| CatalogError::AlreadyRegistered)
+}
+
+fn merge_with_built_ins(extra: Vec<ThreadCatalog>) -> Vec<ThreadCatalog> {
+    let mut registered = built_in_catalogs();
+    for mut catalog in extra {
+        match registered
+            .iter_mut()
+            .find(|existing| existing.brand.eq_ignore_ascii_case(catalog.brand))
+        {
+            Some(existing) => {
+                catalog.brand = existing.brand;
+                *existing = catalog;
+            }
+            None => registered.push(catalog),
+        }
+    }
+    registered
+}
+
+#[derive(Debug, Clone)]
+pub struct ThreadCatalog {
+    brand: &'static str,
+    threads: Vec<(RgbColor, Lab, &'static str)>,
+    /// Shade names by thread code, for catalogs loaded from files
+    names: HashMap<&'static str, &'static str>,
+    indices: [OnceLock<LabIndex>; ColorMetric::ALL.len()],
+}
+
+impl ThreadCatalog {
+    pub fn new(brand: &'static str, threads: Vec<(RgbColor, Lab, &'static str)>) -> Self {
+        ThreadCatalog {
+            brand,
+            threads,
+            names: HashMap::new(),
+            indices: Default::default(),
+        }
+    }
+
+    pub fn with_names(mut self, names: HashMap<&'static str, &'static str>) -> Self {
+        self.names = names;
+        self
+    }
+
+    pub fn dmc() -> &'static ThreadCatalog {
+        Self::find(DEFAULT_BRAND).expect("DMC catalog is built in")
+    }
+
+    /// Looks up a registered catalog by brand name, ignoring case.
+    pub fn find(brand: &str) -> Option<&'static ThreadCatalog> {
+        catalogs()
+            .iter()
+            .find(|catalog| catalog.brand.eq_ignore_ascii_case(brand))
+    }
+
+    pub fn brands() -> Vec<&'static str> {
+        catalogs().iter().map(|catalog| catalog.brand).collect()
+    }
+
+    pub fn brand(&self) -> &'static str {
+        self.brand
+    }
+
+    pub fn len(&self) -> usize {
+        self.threads.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.threads.is_empty()
+    }
+
+    pub fn threads(&self) -> impl Iterator<Item = ThreadColor> + '_ {
+        self.threads
+            .iter()
+            .map(|&(rgb, _, name)| self.thread(name, rgb))
+    }
+
+    pub fn get(&self, code: &str) -> Option<ThreadColor> {
+        self.threads
+            .iter()
+            .find(|(.., name)| name.eq_ignore_ascii_case(code))
+            .map(|&(rgb, _, name)| self.thread(name, rgb))
+    }
+
+    /// Shade name of the thread `code`, such as "Black, Deep", if the
+    /// catalog has one.
+    pub fn thread_name(&self, code: &str) -> Option<&'static str> {
+        self.get(code).and_then(|thread| match thread.name {
+            ThreadName::Code(code) => self.names.get(code).copied(),
+            ThreadName::Custom(_) => None,
+        })
+    }
+
+    pub fn find_closest(&self, color: &RgbColor, metric: ColorMetric) -> ThreadColor {
+        let index = self
+            .lab_index(metric)
+            .closest(Lab::from_rgb(&(*color).into()))
+            .expect("Thread catalog should not be empty");
+        let (rgb, _, name) = self.threads[index];
+        self.thread(name, rgb)
+    }
+
+    /// Nearest-thread index for `metric`, built on first use
+    pub fn lab_index(&self, metric: ColorMetric) -> &LabIndex {
+        let position = ColorMetric::ALL
+            .iter()
+            .position(|&m| m == metric)
+            .unwrap_or_default();
+        self.indices[position].get_or_init(|| {
+            LabIndex::new(
+                self.threads.iter().map(|&(_, lab, _)| lab).collect(),
+                metric,
+            )
+        })
+    }
+
+    /// Builds a catalog of another brand whose shades are taken from their
+    /// closest equivalents in this catalog.
+    fn cross_reference(&self, brand: &'static str, equivalents: &[(&'static str, &str)]) -> Self {
+        let threads = equivalents
+            .iter()
+            .filter_map(|&(code, equivalent)| {
+                self.threads
+                    .iter()
+                    .find(|(.., name)| *name == equivalent)
+                    .map(|&(rgb, lab, _)| (rgb, lab, code))
+            })
+            .collect();
+        ThreadCatalog::new(brand, threads)
+    }
+
+    fn thread(&self, name: &'static str, rgb: RgbColor) -> ThreadColor {
+        ThreadColor {
+            brand: self.brand,
+            name: ThreadName::Code(name),
+            rgb,
+        }
+    }
+}
+
+impl FromStr for &'static ThreadCatalog {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ThreadCatalog::find(s.trim()).ok_or_else(|| {
+            format!(
+                "Value should be one of: {}",
+                ThreadCatalog::brands().join(", ")
+            )
+        })
+    }
+}
+
+/// Anchor stranded cotton codes paired with their DMC equivalents.
+static ANCHOR_TO_DMC: [(&str, &str); 244] = [
+    ("1", "B5200"),
+    ("2", "3865"),
+    ("403", "310"),
+    ("9046", "321"),
+    ("46", "666"),
+    ("43", "815"),
+    ("1005", "816"),
+    ("13", "349"),
+    ("19", "817"),
+    ("45", "814"),
+    ("11", "350"),
+    ("10", "351"),
+    ("9", "352"),
+    ("8", "353"),
+    ("35", "3705"),
+    ("33", "3706"),
+    ("31", "3708"),
+    ("1098", "3801"),
+    ("1020", "3713"),
+    ("1021", "761"),
+    ("1022", "760"),
+    ("1023", "3712"),
+    ("1024", "3328"),
+    ("1025", "347"),
+    ("23", "818"),
+    ("24", "776"),
+    ("27", "899"),
+    ("36", "3326"),
+    ("38", "335"),
+    ("271", "819"),
+    ("28", "893"),
+    ("25", "3716"),
+    ("50", "957"),
+    ("40", "956"),
+    ("42", "309"),
+    ("74", "3354"),
+    ("75", "3733"),
+    ("76", "3731"),
+    ("49", "3689"),
+    ("66", "3688"),
+    ("68", "3687"),
+    ("69", "3803"),
+    ("1028", "3685"),
+    ("1094", "605"),
+    ("55", "604"),
+    ("62", "603"),
+    ("63", "602"),
+    ("59", "600"),
+    ("85", "3609"),
+    ("86", "3608"),
+    ("87", "3607"),
+    ("88", "718"),
+    ("89", "917"),
+    ("1029", "915"),
+    ("1026", "225"),
+    ("893", "224"),
+    ("895", "223"),
+    ("1027", "3722"),
+    ("896", "3721"),
+    ("897", "221"),
+    ("968", "778"),
+    ("969", "3727"),
+    ("1017", "316"),
+    ("1018", "3726"),
+    ("1019", "315"),
+    ("96", "554"),
+    ("98", "553"),
+    ("99", "552"),
+    ("102", "550"),
+    ("342", "211"),
+    ("108", "210"),
+    ("109", "209"),
+    ("110", "208"),
+    ("117", "341"),
+    ("118", "340"),
+    ("119", "333"),
+    ("175", "794"),
+    ("176", "793"),
+    ("941", "792"),
+    ("144", "800"),
+    ("130", "809"),
+    ("136", "799"),
+    ("131", "798"),
+    ("132", "797"),
+    ("133", "796"),
+    ("134", "820"),
+    ("161", "813"),
+    ("128", "775"),
+    ("129", "3325"),
+    ("140", "3755"),
+    ("433", "996"),
+    ("410", "995"),
+    ("1089", "3843"),
+    ("1090", "3846"),
+    ("148", "311"),
+    ("979", "312"),
+    ("978", "322"),
+    ("977", "334"),
+    ("150", "336"),
+    ("152", "823"),
+    ("928", "3761"),
+    ("1039", "518"),
+    ("1038", "519"),
+    ("167", "3766"),
+    ("168", "807"),
+    ("169", "806"),
+    ("851", "924"),
+    ("850", "926"),
+    ("849", "927"),
+    ("274", "928"),
+    ("779", "3768"),
+    ("1035", "930"),
+    ("1034", "931"),
+    ("1033", "932"),
+    ("1036", "3750"),
+    ("1032", "3752"),
+    ("1031", "3753"),
+    ("185", "964"),
+    ("186", "959"),
+    ("187", "958"),
+    ("188", "3812"),
+    ("203", "954"),
+    ("204", "913"),
+    ("209", "912"),
+    ("205", "911"),
+    ("230", "910"),
+    ("206", "564"),
+    ("208", "563"),
+    ("210", "562"),
+    ("683", "500"),
+    ("878", "501"),
+    ("877", "502"),
+    ("876", "503"),
+    ("214", "368"),
+    ("215", "320"),
+    ("217", "367"),
+    ("218", "319"),
+    ("242", "989"),
+    ("243", "988"),
+    ("244", "987"),
+    ("246", "986"),
+    ("264", "3348"),
+    ("266", "3347"),
+    ("267", "3346"),
+    ("268", "3345"),
+    ("226", "702"),
+    ("227", "701"),
+    ("228", "700"),
+    ("923", "699"),
+    ("238", "703"),
+    ("256", "704"),
+    ("255", "907"),
+    ("257", "905"),
+    ("258", "904"),
+    ("888", "3045"),
+    ("887", "3046"),
+    ("852", "3047"),
+    ("842", "3013"),
+    ("844", "3012"),
+    ("846", "3011"),
+    ("890", "729"),
+    ("901", "680"),
+    ("891", "676"),
+    ("886", "677"),
+    ("373", "3828"),
+    ("374", "420"),
+    ("944", "869"),
+    ("943", "422"),
+    ("292", "3078"),
+    ("288", "445"),
+    ("289", "307"),
+    ("290", "444"),
+    ("297", "973"),
+    ("298", "972"),
+    ("293", "727"),
+    ("295", "726"),
+    ("305", "725"),
+    ("300", "745"),
+    ("301", "744"),
+    ("302", "743"),
+    ("303", "742"),
+    ("304", "741"),
+    ("316", "740"),
+    ("330", "947"),
+    ("332", "946"),
+    ("333", "900"),
+    ("334", "606"),
+    ("328", "3341"),
+    ("329", "3340"),
+    ("323", "722"),
+    ("324", "721"),
+    ("326", "720"),
+    ("1003", "922"),
+    ("1004", "920"),
+    ("340", "919"),
+    ("341", "918"),
+    ("1010", "951"),
+    ("347", "3856"),
+    ("1011", "948"),
+    ("1012", "754"),
+    ("9575", "758"),
+    ("1013", "3778"),
+    ("5975", "356"),
+    ("1014", "355"),
+    ("1015", "3777"),
+    ("868", "3779"),
+    ("881", "945"),
+    ("1009", "3770"),
+    ("1047", "402"),
+    ("1048", "3776"),
+    ("1049", "301"),
+    ("351", "400"),
+    ("352", "300"),
+    ("4146", "950"),
+    ("778", "3774"),
+    ("883", "3064"),
+    ("1007", "3772"),
+    ("936", "632"),
+    ("914", "407"),
+    ("1008", "3773"),
+    ("386", "3823"),
+    ("311", "3855"),
+    ("313", "3854"),
+    ("926", "712"),
+    ("387", "739"),
+    ("361", "738"),
+    ("362", "437"),
+    ("1045", "436"),
+    ("1046", "435"),
+    ("310", "434"),
+    ("358", "433"),
+    ("359", "801"),
+    ("360", "898"),
+    ("381", "938"),
+    ("382", "3371"),
+    ("390", "822"),
+    ("933", "543"),
+    ("376", "3864"),
+    ("380", "838"),
+    ("378", "841"),
+    ("379", "840"),
+    ("388", "842"),
+    ("903", "3032"),
+    ("391", "3033"),
+];
+
+/// Madeira stranded cotton codes paired with their DMC equivalents.
+static MADEIRA_TO_DMC: [(&str, &str); 101] = [
+    ("2402", "B5200"),
+    ("2401", "3865"),
+    ("2400", "310"),
+    ("0510", "321"),
+    ("0210", "666"),
+    ("0511", "304"),
+    ("0513", "815"),
+    ("0512", "816"),
+    ("0213", "350"),
+    ("0214", "351"),
+    ("0303", "352"),
+    ("0304", "353"),
+    ("0410", "3705"),
+    ("0409", "3706"),
+    ("0408", "3708"),
+    ("0704", "600"),
+    ("0703", "601"),
+    ("0702", "602"),
+    ("0701", "603"),
+    ("0614", "604"),
+    ("0613", "605"),
+    ("0711", "554"),
+    ("0712", "553"),
+    ("0713", "552"),
+    ("0714", "550"),
+    ("0801", "211"),
+    ("0802", "210"),
+    ("0803", "209"),
+    ("0804", "208"),
+    ("0901", "341"),
+    ("0902", "340"),
+    ("0903", "333"),
+    ("0905", "792"),
+    ("0906", "793"),
+    ("0907", "794"),
+    ("0908", "800"),
+    ("0909", "809"),
+    ("0910", "799"),
+    ("0911", "798"),
+    ("0912", "797"),
+    ("0913", "796"),
+    ("0904", "820"),
+    ("1013", "813"),
+    ("1014", "3755"),
+    ("1001", "775"),
+    ("1102", "995"),
+    ("1103", "996"),
+    ("1113", "959"),
+    ("1114", "958"),
+    ("1303", "699"),
+    ("1304", "700"),
+    ("1305", "701"),
+    ("1306", "702"),
+    ("1307", "703"),
+    ("1308", "704"),
+    ("1410", "907"),
+    ("1411", "906"),
+    ("1412", "905"),
+    ("1413", "904"),
+    ("1401", "989"),
+    ("1402", "988"),
+    ("1403", "987"),
+    ("1406", "3345"),
+    ("1407", "3346"),
+    ("1408", "3347"),
+    ("1409", "3348"),
+    ("1310", "368"),
+    ("1311", "320"),
+    ("1312", "367"),
+    ("1313", "319"),
+    ("0104", "307"),
+    ("0105", "444"),
+    ("0108", "725"),
+    ("0109", "726"),
+    ("0110", "727"),
+    ("0111", "745"),
+    ("0112", "744"),
+    ("0113", "743"),
+    ("0114", "742"),
+    ("0201", "741"),
+    ("0202", "740"),
+    ("0205", "947"),
+    ("0207", "946"),
+    ("0208", "900"),
+    ("2004", "3371"),
+    ("2005", "938"),
+    ("2006", "898"),
+    ("2007", "801"),
+    ("2008", "433"),
+    ("2009", "434"),
+    ("2010", "435"),
+    ("2011", "436"),
+    ("2012", "437"),
+    ("2013", "738"),
+    ("2014", "739"),
+    ("2101", "712"),
+    ("1713", "413"),
+    ("1714", "317"),
+    ("1801", "414"),
+    ("1802", "318"),
+    ("1803", "415"),
+];
+
+/// Cosmo (Lecien) stranded cotton codes paired with their DMC equivalents.
+static COSMO_TO_DMC: [(&str, &str); 40] = [
+    ("100", "B5200"),
+    ("2500", "3865"),
+    ("600", "310"),
+    ("346", "321"),
+    ("345", "666"),
+    ("347", "815"),
+    ("344", "817"),
+    ("343", "349"),
+    ("342", "350"),
+    ("341", "351"),
+    ("111", "353"),
+    ("114", "3706"),
+    ("113", "3708"),
+    ("2114", "600"),
+    ("2113", "602"),
+    ("2112", "603"),
+    ("2111", "604"),
+    ("665", "553"),
+    ("664", "554"),
+    ("555", "208"),
+    ("553", "209"),
+    ("552", "210"),
+    ("2175", "797"),
+    ("2174", "798"),
+    ("2173", "799"),
+    ("2172", "800"),
+    ("2535", "699"),
+    ("2536", "700"),
+    ("2537", "701"),
+    ("2538", "702"),
+    ("2024", "986"),
+    ("2023", "987"),
+    ("2022", "988"),
+    ("2021", "989"),
+    ("2299", "740"),
+    ("2298", "741"),
+    ("2297", "742"),
+    ("2296", "743"),
+    ("2156", "3799"),
+    ("2155", "413"),
+];
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_finds_catalog_by_brand() {
+        let catalog = ThreadCatalog::find("anchor").unwrap();
+        assert_eq!(catalog.brand(), "Anchor");
+        assert!(ThreadCatalog::find("unknown").is_none());
+    }
+
+    #[test]
+    fn it_merges_catalogs_with_built_ins() {
+        let white = RgbColor {
+            red: 255,
+            green: 255,
+            blue: 255,
+        };
+        let extra = vec![
+            ThreadCatalog::new("anchor", vec![(white, Lab::from_rgb(&white.into()), "1")]),
+            ThreadCatalog::new("Sulky", vec![(white, Lab::from_rgb(&white.into()), "1001")]),
+        ];
+
+        let catalogs = merge_with_built_ins(extra);
+        let brands: Vec<&str> = catalogs.iter().map(|catalog| catalog.brand()).collect();
+        assert_eq!(brands, vec!["DMC", "Anchor", "Madeira", "Cosmo", "Sulky"]);
+        assert_eq!(catalogs[1].len(), 1);
+    }
+
+    #[test]
+    fn it_has_unique_codes() {
+        for brand in ThreadCatalog::brands() {
+            let catalog = ThreadCatalog::find(brand).unwrap();
+            let mut codes: Vec<String> = catalog
+                .threads()
+                .map(|thread| thread.name.to_string())
+                .collect();
+            codes.sort();
+            codes.dedup();
+            assert_eq!(codes.len(), catalog.len(), "{brand} has duplicate codes");
+        }
+    }
+
+    #[test]
+    fn it_resolves_every_cross_reference() {
+        let tables: [(&str, &[(&str, &str)]); 3] = [
+            ("Anchor", &ANCHOR_TO_DMC),
+            ("Madeira", &MADEIRA_TO_DMC),
+            ("Cosmo", &COSMO_TO_DMC),
+        ];
+        for (brand, table) in tables {
+            assert_eq!(ThreadCatalog::find(brand).unwrap().len(), table.len());
+        }
+    }
+
+    #[test]
+    fn it_finds_closest_thread_in_brand() {
+        let catalog = ThreadCatalog::find("Anchor").unwrap();
+        let thread = RgbColor {
+            red: 0,
+            green: 0,
+            blue: 0,
+        }
+        .find_thread(catalog, ColorMetric::default());
+        assert_eq!(thread.brand, "Anchor");
+        assert_eq!(thread.name, "403");
+    }
+}
@@ -0,0 +1,343 @@
+use lab::Lab;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::embroidery::dither::Cells;
+use crate::embroidery::metric::ColorMetric;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Connectivity {
+    /// Cells sharing an edge
+    #[default]
+    Four,
+    /// Cells sharing an edge or a corner
+    Eight,
+}
+
+impl Connectivity {
+    fn offsets(&self) -> &'static [(isize, isize)] {
+        match self {
+            Connectivity::Four => &[(-1, 0), (1, 0), (0, -1), (0, 1)],
+            Connectivity::Eight => &[
+                (-1, -1),
+                (0, -1),
+                (1, -1),
+                (-1, 0),
+                (1, 0),
+                (-1, 1),
+                (0, 1),
+                (1, 1),
+            ],
+        }
+    }
+}
+
+impl fmt::Display for Connectivity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Connectivity::Four => f.write_str("4"),
+            Connectivity::Eight => f.write_str("8"),
+        }
+    }
+}
+
+impl FromStr for Connectivity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "4" => Ok(Connectivity::Four),
+            "8" => Ok(Connectivity::Eight),
+            _ => Err("Value should be one of: 4, 8".into()),
+        }
+    }
+}
+
+/// Removes confetti: clusters of same-colored cells smaller than
+/// `min_cluster_size` are merged into a neighbouring color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cleanup {
+    pub min_cluster_size: usize,
+    pub connectivity: Connectivity,
+}
+
+impl Cleanup {
+    pub fn new(min_cluster_size: usize, connectivity: Connectivity) -> Self {
+        Cleanup {
+            min_cluster_size,
+            connectivity,
+        }
+    }
+
+    /// Reassigns every small cluster of `cells` to the neighbouring color
+    /// closest to its own, with `labs` holding the color of every index.
+    /// Clusters without stitched neighbours are kept. Returns the number of
+    /// cells that changed color.
+    pub fn apply(&self, cells: &mut Cells, labs: &[Lab], metric: ColorMetric) -> usize {
+        let original = cells.clone();
+        // Every merge joins two clusters, so this ends once no small cluster
+        // has stitched neighbours left
+        while self.merge_clusters(cells, labs, metric) {}
+
+        original
+            .iter()
+            .flatten()
+            .zip(cells.iter().flatten())
+            .filter(|(before, after)| before != after)
+            .count()
+    }
+
+    fn merge_clusters(&self, cells: &mut Cells, labs: &[Lab], metric: ColorMetric) -> bool {
+        let mut merged = false;
+        // Clusters next to a merged one may have grown, so they wait for the
+        // next pass
+        let mut touched: Vec<Vec<bool>> = cells.iter().map(|row| vec![false; row.len()]).collect();
+        for cluster in self.clusters(cells) {
+            if cluster.len() >= self.min_cluster_size || cluster.iter().any(|&(x, y)| touched[y][x])
+            {
+                continue;
+            }
+            let (x, y) = cluster[0];
+            let Some(color) = cells[y][x] else {
+                continue;
+            };
+
+            let mut borders: HashMap<usize, usize> = HashMap::new();
+            for &(x, y) in &cluster {
+                for (nx, ny) in self.neighbours(cells, x, y) {
+                    match cells[ny][nx] {
+                        Some(neighbour) if neighbour != color => {
+                            *borders.entry(neighbour).or_insert(0) += 1
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            // Closest color wins, the longest shared border breaks ties
+            let best = borders.into_iter().min_by(|x, y| {
+                metric
+                    .difference(labs[color], labs[x.0])
+                    .total_cmp(&metric.difference(labs[color], labs[y.0]))
+                    .then(y.1.cmp(&x.1))
+                    .then(x.0.cmp(&y.0))
+            });
+            if let Some((neighbour, _)) = best {
+                for &(x, y) in &cluster {
+                    cells[y][x] = Some(neighbour);
+                    touched[y][x] = true;
+                    for (nx, ny) in self.neighbours(cells, x, y) {
+                        touched[ny][nx] = true;
+                    }
+                }
+                merged = true;
+            }
+        }
+        merged
+    }
+
+    /// Groups stitched cells into clusters of connected cells of one color
+    fn clusters(&self, cells: &Cells) -> Vec<Vec<(usize, usize)>> {
+        let mut visited: Vec<Vec<bool>> = cells.iter().map(|row| vec![false; row.len()]).collect();
+        let mut clusters = Vec::new();
+        for (y, row) in cells.iter().enumerate() {
+            for (x, cell) in row.iter().enumerate() {
+                if visited[y][x] || cell.is_none() {
+                    continue;
+                }
+                visited[y][x] = true;
+                let mut cluster = vec![(x, y)];
+                let mut next = 0;
+                while next < cluster.len() {
+                    let (cx, cy) = cluster[next];
+                    next += 1;
+                    for (nx, ny) in self.neighbours(cells, cx, cy) {
+                        if !visited[ny][nx] && cells[ny][nx] == *cell {
+                            visited[ny][nx] = true;
+                            cluster.push((nx, ny));
+                        }
+                    }
+                }
+                clusters.push(cluster);
+            }
+        }
+        clusters
+    }
+
+    fn neighbours<'a>(
+        &self,
+        cells: &'a Cells,
+        x: usize,
+        y: usize,
+    ) -> impl Iterator<Item = (usize, usize)> + 'a {
+        self.connectivity
+            .offsets()
+            .iter()
+            .filter_map(move |&(dx, dy)| {
+                let nx = x.checked_add_signed(dx)?;
+                let ny = y.checked_add_signed(dy)?;
+                cells.get(ny)?.get(nx)?;
+                Some((nx, ny))
+            })
+    }
+}
+
+/// Removes colors used by fewer than `min_stitches` cells, least used first,
+/// remapping their cells to the closest remaining color. The last color and
+/// `locked` colors are always kept. Returns which colors are still in use.
+pub fn merge_rare_colors(
+    cells: &mut Cells,
+    labs: &[Lab],
+    locked: &[bool],
+    min_stitches: usize,
+    metric: ColorMetric,
+) -> Vec<bool> {
+    let mut stitches: Vec<usize> = vec![0; labs.len()];
+    for &color in cells.iter().flatten().flatten() {
+        stitches[color] += 1;
+    }
+    let is_locked = |color: usize| locked.get(color).copied().unwrap_or(false);
+    let mut kept: Vec<bool> = (0..labs.len())
+        .map(|color| stitches[color] > 0 || is_locked(color))
+        .collect();
+
+    while kept.iter().filter(|&&kept| kept).count() > 1 {
+        let Some(rarest) = (0..labs.len())
+            .filter(|&color| kept[color] && !is_locked(color) && stitches[color] < min_stitches)
+            .min_by_key(|&color| (stitches[color], color))
+        else {
+            break;
+        };
+        kept[rarest] = false;
+        let Some(closest) = (0..labs.len())
+            .filter(|&color| kept[color])
+            .min_by(|&x, &y| {
+                metric
+                    .difference(labs[rarest], labs[x])
+                    .total_cmp(&metric.difference(labs[rarest], labs[y]))
+            })
+        else {
+            break;
+        };
+
+        for cell in cells.iter_mut().flatten() {
+            if *cell == Some(rarest) {
+                *cell = Some(closest);
+            }
+        }
+        stitches[closest] += stitches[rarest];
+        stitches[rarest] = 0;
+    }
+    kept
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn labs() -> Vec<Lab> {
+        [[255, 255, 255], [0, 0, 0], [250, 240, 240], [200, 0, 0]]
+            .iter()
+            .map(Lab::from_rgb)
+            .collect()
+    }
+
+    fn cells(rows: &[&str]) -> Cells {
+        rows.iter()
+            .map(|row| {
+                row.chars()
+                    .map(|c| c.to_digit(10).map(|digit| digit as usize))
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn it_removes_confetti() {
+        let mut embroidery = cells(&["0000", "0100", "0000", "0001"]);
+        let changed = Cleanup::new(2, Connectivity::Four).apply(
+            &mut embroidery,
+            &labs(),
+            ColorMetric::default(),
+        );
+        assert_eq!(changed, 2);
+        assert_eq!(embroidery, cells(&["0000", "0000", "0000", "0000"]));
+    }
+
+    #[test]
+    fn it_merges_into_closest_neighbour() {
+        let mut embroidery = cells(&["1113", "1023", "1113"]);
+        let changed = Cleanup::new(2, Connectivity::Four).apply(
+            &mut embroidery,
+            &labs(),
+            ColorMetric::default(),
+        );
+        assert_eq!(changed, 1);
+        assert_eq!(embroidery, cells(&["1113", "1223", "1113"]));
+    }
+
+    #[test]
+    fn it_uses_connectivity() {
+        let diagonal = cells(&["100", "010", "001"]);
+
+        let mut four = diagonal.clone();
+        let changed =
+            Cleanup::new(3, Connectivity::Four).apply(&mut four, &labs(), ColorMetric::default());
+        assert_eq!(changed, 3);
+        assert!(four.iter().flatten().all(|&cell| cell == Some(0)));
+
+        let mut eight = diagonal.clone();
+        let changed =
+            Cleanup::new(3, Connectivity::Eight).apply(&mut eight, &labs(), ColorMetric::default());
+        assert_eq!(changed, 0);
+        assert_eq!(eight, diagonal);
+    }
+
+    #[test]
+    fn it_keeps_clusters_surrounded_by_fabric() {
+        let mut embroidery = cells(&["---", "-1-", "---"]);
+        let changed = Cleanup::new(4, Connectivity::Eight).apply(
+            &mut embroidery,
+            &labs(),
+            ColorMetric::default(),
+        );
+        assert_eq!(changed, 0);
+        assert_eq!(embroidery[1][1], Some(1));
+    }
+
+    #[test]
+    fn it_merges_rare_colors() {
+        let mut embroidery = cells(&["0000", "0002", "1113", "1111"]);
+        let kept = merge_rare_colors(&mut embroidery, &labs(), &[], 3, ColorMetric::default());
+        assert_eq!(kept, vec![true, true, false, false]);
+        // The pink stitch goes to white, the red one to black
+        assert_eq!(embroidery, cells(&["0000", "0000", "1111", "1111"]));
+    }
+
+    #[test]
+    fn it_keeps_last_color() {
+        let mut embroidery = cells(&["0-", "-1"]);
+        let kept = merge_rare_colors(&mut embroidery, &labs(), &[], 5, ColorMetric::default());
+        assert_eq!(kept.iter().filter(|&&kept| kept).count(), 1);
+        assert_eq!(embroidery[0][0], embroidery[1][1]);
+        assert_eq!(embroidery[0][1], None);
+    }
+
+    #[test]
+    fn it_keeps_locked_colors() {
+        let mut embroidery = cells(&["0000", "0002", "1111", "1111"]);
+        let locked = [false, false, true, true];
+        let kept = merge_rare_colors(&mut embroidery, &labs(), &locked, 3, ColorMetric::default());
+        assert_eq!(kept, vec![true, true, true, true]);
+        assert_eq!(embroidery[1][3], Some(2));
+    }
+
+    #[test]
+    fn it_parses_connectivity() {
+        assert_eq!("8".parse::<Connectivity>(), Ok(Connectivity::Eight));
+        assert_eq!(
+            "6".parse::<Connectivity>().unwrap_err(),
+            "Value should be one of: 4, 8"
+        );
+    }
+}
@@ -1,7 +1,13 @@
 use image::Rgb;
 use lab::Lab;
 use serde::{ser::SerializeSeq, Serialize, Serializer};
-use std::f32::consts::PI;
+use std::fmt;
+
+use crate::embroidery::catalog::ThreadCatalog;
+use crate::embroidery::metric::ColorMetric;
+
+/// Brand of the threads given by their color instead of a catalog code
+pub const CUSTOM_BRAND: &str = "Custom";
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct RgbColor {
@@ -46,139 +52,101 @@ impl From<RgbColor> for [u8; 3] {
 }
 
 #[derive(Clone, Copy, Serialize, PartialEq, Debug, Eq, Hash)]
-pub struct DmcColor {
-    pub name: &'static str,
+pub struct ThreadColor {
+    pub brand: &'static str,
+    pub name: ThreadName,
     pub rgb: RgbColor,
 }
 
-impl RgbColor {
-    pub fn find_dmc(&self) -> DmcColor {
-        let mut color_diffs: Vec<(i32, DmcColor)> = Vec::with_capacity(RGB_TO_DMC.len());
-        let lab = Lab::from_rgb(&(*self).into());
-        for &(rgb, lab_2, name) in RGB_TO_DMC.iter() {
-            let dmc_color = DmcColor { name, rgb };
-            if rgb == *self {
-                return dmc_color;
-            }
-            let diff = Self::calculate_diff(lab, lab_2) as i32;
-
-            color_diffs.push((diff, dmc_color));
-        }
+/// Code of a thread in its catalog, or the color of a custom thread, written
+/// as `#RRGGBB` when formatted.
+#[derive(Clone, Copy, PartialEq, Debug, Eq, Hash)]
+pub enum ThreadName {
+    Code(&'static str),
+    Custom(RgbColor),
+}
 
-        let (.., closest_dmc_color) = color_diffs.iter().min_by_key(|x| x.0).unwrap();
-        DmcColor {
-            name: closest_dmc_color.name,
-            rgb: closest_dmc_color.rgb,
+impl fmt::Display for ThreadName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ThreadName::Code(code) => f.write_str(code),
+            ThreadName::Custom(color) => f.write_str(&color.to_hex()),
         }
     }
+}
 
-    pub fn calculate_diff(lab: Lab, lab_2: Lab) -> f32 {
-        let c1 = (lab.a.powi(2) + lab.b.powi(2)).sqrt();
-        let c2 = (lab_2.a.powi(2) + lab_2.b.powi(2)).sqrt();
-        let c_bar = (c1 + c2) / 2.0;
-
-        let delta_l_prime = lab_2.l - lab.l;
-        let l_bar = (lab.l + lab_2.l) / 2.0;
-        let a_prime_1 = Self::get_a_prime(lab.a, c_bar);
-        let a_prime_2 = Self::get_a_prime(lab_2.a, c_bar);
-        let c_prime_1 = (a_prime_1.powf(2.0) + lab.b.powf(2.0)).sqrt();
-        let c_prime_2 = (a_prime_2.powf(2.0) + lab_2.b.powf(2.0)).sqrt();
-        let c_bar_prime = (c_prime_1 + c_prime_2) / 2.0;
-        let delta_c_prime = c_prime_2 - c_prime_1;
-        let s_sub_l =
-            1.0 + (0.015 * (l_bar - 50.0).powf(2.0)) / (20.0 + (l_bar - 50.0).powf(2.0)).sqrt();
-        let s_sub_c = 1.0 + 0.045 * c_bar_prime;
-
-        let h_prime_1 = Self::get_h_prime_fn(lab.b, a_prime_1);
-        let h_prime_2 = Self::get_h_prime_fn(lab_2.b, a_prime_2);
-
-        let delta_h_prime = Self::get_delta_h_prime(c1, c2, h_prime_1, h_prime_2);
-
-        let delta_uppercase_h_prime = 2.0
-            * (c_prime_1 * c_prime_2).sqrt()
-            * (Self::degrees_to_radians(delta_h_prime) / 2.0).sin();
-
-        let uppercase_h_bar_prime = Self::get_uppercase_h_bar_prime(h_prime_1, h_prime_2);
-
-        let uppercase_t = Self::get_uppercase_t(uppercase_h_bar_prime);
-
-        let s_sub_uppercase_h = 1.0 + 0.015 * c_bar_prime * uppercase_t;
-
-        let r_sub_t = Self::get_r_sub_t(c_bar_prime, uppercase_h_bar_prime);
-
-        let lightness: f32 = delta_l_prime / (1.0 * s_sub_l);
-
-        let chroma: f32 = delta_c_prime / (1.0 * s_sub_c);
-
-        let hue: f32 = delta_uppercase_h_prime / (1.0 * s_sub_uppercase_h);
-
-        (lightness.powi(2) + chroma.powi(2) + hue.powi(2) + r_sub_t * chroma * hue).sqrt()
+impl Serialize for ThreadName {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
     }
+}
 
-    fn get_h_prime_fn(x: f32, y: f32) -> f32 {
-        if x == 0.0 && y == 0.0 {
-            return 0.0;
-        }
-
-        let mut hue_angle = Self::radians_to_degrees(x.atan2(y));
-        if hue_angle < 0.0 {
-            hue_angle += 360.0;
+impl PartialEq<str> for ThreadName {
+    fn eq(&self, other: &str) -> bool {
+        match self {
+            ThreadName::Code(code) => *code == other,
+            ThreadName::Custom(color) => color.to_hex() == other,
         }
-        hue_angle
     }
+}
 
-    fn get_a_prime(a: f32, c_bar: f32) -> f32 {
-        a + a / 2.0 * (1.0 - (c_bar.powi(7) / (c_bar.powi(7) + 25_f32.powi(7))).sqrt())
+impl PartialEq<&str> for ThreadName {
+    fn eq(&self, other: &&str) -> bool {
+        self == *other
     }
+}
 
-    fn get_delta_h_prime(c1: f32, c2: f32, h_prime_1: f32, h_prime_2: f32) -> f32 {
-        if 0.0 == c1 || 0.0 == c2 {
-            return 0.0;
-        }
-
-        if (h_prime_1 - h_prime_2).abs() <= 180.0 {
-            return h_prime_2 - h_prime_1;
-        }
-
-        if h_prime_2 <= h_prime_1 {
-            return h_prime_2 - h_prime_1 + 360.0;
+impl RgbColor {
+    /// Parses a `#RRGGBB` or `RRGGBB` hex string.
+    pub fn from_hex(hex: &str) -> Option<RgbColor> {
+        let hex = hex.trim();
+        let hex = hex.strip_prefix('#').unwrap_or(hex);
+        if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
         }
-        h_prime_2 - h_prime_1 - 360.0
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+        Some(RgbColor {
+            red: channel(0)?,
+            green: channel(2)?,
+            blue: channel(4)?,
+        })
     }
 
-    fn get_uppercase_h_bar_prime(h_prime_1: f32, h_prime_2: f32) -> f32 {
-        if (h_prime_1 - h_prime_2).abs() > 180.0 {
-            return (h_prime_1 + h_prime_2 + 360.0) / 2.0;
-        }
-
-        (h_prime_1 + h_prime_2) / 2.0
+    /// Formats the color as `#RRGGBB`.
+    pub fn to_hex(&self) -> String {
+        format!("#{:02X}{:02X}{:02X}", self.red, self.green, self.blue)
     }
 
-    fn get_uppercase_t(uppercase_h_bar_prime: f32) -> f32 {
-        1.0 - 0.17 * Self::degrees_to_radians(uppercase_h_bar_prime - 30.0).cos()
-            + 0.24 * Self::degrees_to_radians(2.0 * uppercase_h_bar_prime).cos()
-            + 0.32 * Self::degrees_to_radians(3.0 * uppercase_h_bar_prime + 6.0).cos()
-            - 0.20 * Self::degrees_to_radians(4.0 * uppercase_h_bar_prime - 63.0).cos()
+    /// Whether black text would be harder to read on the color than white.
+    pub fn is_dark(&self) -> bool {
+        let luma =
+            0.2126 * self.red as f32 + 0.7152 * self.green as f32 + 0.0722 * self.blue as f32;
+        luma < 128.0
     }
 
-    fn get_r_sub_t(c_bar_prime: f32, uppercase_h_bar_prime: f32) -> f32 {
-        -2.0 * (c_bar_prime.powi(7) / (c_bar_prime.powi(7) + 25f32.powi(7))).sqrt()
-            * Self::degrees_to_radians(
-                60.0 * (-((uppercase_h_bar_prime - 275.0) / 25.0).powi(2)).exp(),
-            )
-            .sin()
+    /// Thread of exactly this color, named by its hex code.
+    pub fn custom_thread(&self) -> ThreadColor {
+        ThreadColor {
+            brand: CUSTOM_BRAND,
+            name: ThreadName::Custom(*self),
+            rgb: *self,
+        }
     }
 
-    fn radians_to_degrees(radians: f32) -> f32 {
-        radians * (180.0 / PI)
+    pub fn find_dmc(&self) -> ThreadColor {
+        self.find_thread(ThreadCatalog::dmc(), ColorMetric::default())
     }
 
-    fn degrees_to_radians(degrees: f32) -> f32 {
-        degrees * (PI / 180.0)
+    pub fn find_thread(&self, catalog: &ThreadCatalog, metric: ColorMetric) -> ThreadColor {
+        catalog.find_closest(self, metric)
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::items_after_test_module)]
 mod tests {
     #[test]
     fn it_gets_dmc_color() {
@@ -187,12 +155,59 @@ mod tests {
             green: 29,
             blue: 30,
         };
-        let super::DmcColor { rgb, .. } = color.find_dmc();
+        let super::ThreadColor { rgb, .. } = color.find_dmc();
         assert_eq!(rgb.red, 204);
         assert_eq!(rgb.green, 63);
         assert_eq!(rgb.blue, 24);
     }
 
+    #[test]
+    fn it_parses_hex_color() {
+        let color = super::RgbColor::from_hex("#FF1d1E").unwrap();
+        assert_eq!(
+            color,
+            super::RgbColor {
+                red: 255,
+                green: 29,
+                blue: 30,
+            }
+        );
+        assert!(super::RgbColor::from_hex("ff1d1e").is_some());
+        assert!(super::RgbColor::from_hex("#ff1d1").is_none());
+        assert!(super::RgbColor::from_hex("#gg1d1e").is_none());
+        assert_eq!(color.to_hex(), "#FF1D1E");
+    }
+
+    #[test]
+    fn it_gets_custom_thread() {
+        let color = super::RgbColor::from_hex("#ff1d1e").unwrap();
+        let thread = color.custom_thread();
+        assert_eq!(thread.brand, super::CUSTOM_BRAND);
+        assert_eq!(thread.name, "#FF1D1E");
+        assert_eq!(thread.name.to_string(), "#FF1D1E");
+        assert_eq!(thread.rgb, color);
+        assert_eq!(thread, color.custom_thread());
+    }
+
+    #[test]
+    fn it_names_custom_threads_without_storing_them() {
+        // Every upload of a custom palette may bring new colors, so their
+        // names are formatted from the color on use instead of kept around
+        for value in (0..=0xFF_FFFF).step_by(0x3FF) {
+            let color = super::RgbColor {
+                red: (value >> 16) as u8,
+                green: (value >> 8) as u8,
+                blue: value as u8,
+            };
+            let thread = color.custom_thread();
+            assert_eq!(thread.name, super::ThreadName::Custom(color));
+            assert_eq!(
+                serde_json::to_value(thread).unwrap()["name"],
+                color.to_hex()
+            );
+        }
+    }
+
     #[test]
     fn it_gets_existing_color() {
         let color = super::RgbColor {
@@ -200,7 +215,7 @@ mod tests {
             green: 255,
             blue: 255,
         };
-        let super::DmcColor { rgb, .. } = color.find_dmc();
+        let super::ThreadColor { rgb, .. } = color.find_dmc();
         assert_eq!(rgb.red, 255);
         assert_eq!(rgb.green, 255);
         assert_eq!(rgb.blue, 255);
@@ -0,0 +1,118 @@
+use image::{DynamicImage, ImageDecoder, ImageError, ImageReader};
+use qcms::{DataType, Intent, Profile, Transform};
+use std::io::Cursor;
+
+use crate::error::CanvasError;
+
+/// Decodes an uploaded image upright and in sRGB, the way a viewer shows it:
+/// the EXIF orientation is applied and an embedded ICC profile is converted.
+pub fn decode_image(bytes: Vec<u8>) -> Result<DynamicImage, CanvasError> {
+    let mut decoder = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .map_err(CanvasError::ImageFormat)?
+        .into_decoder()
+        .map_err(|err| match err {
+            ImageError::Unsupported(_) => CanvasError::UnsupportedFormat,
+            err => err.into(),
+        })?;
+    let orientation = decoder.orientation()?;
+    let icc_profile = decoder.icc_profile()?;
+    let mut img = DynamicImage::from_decoder(decoder)?;
+
+    if let Some(icc_profile) = icc_profile {
+        img = to_srgb(img, &icc_profile);
+    }
+    img.apply_orientation(orientation);
+    Ok(img)
+}
+
+/// Converts `img` from `icc_profile` to sRGB. Profiles that cannot be read
+/// are ignored, the image then being taken as sRGB.
+fn to_srgb(img: DynamicImage, icc_profile: &[u8]) -> DynamicImage {
+    let Some(profile) = Profile::new_from_slice(icc_profile, false) else {
+        return img;
+    };
+    let mut srgb = Profile::new_sRGB();
+    srgb.precache_output_transform();
+
+    let has_alpha = img.color().has_alpha();
+    let data_type = if has_alpha {
+        DataType::RGBA8
+    } else {
+        DataType::RGB8
+    };
+    let Some(transform) = Transform::new(&profile, &srgb, data_type, Intent::default()) else {
+        return img;
+    };
+    if has_alpha {
+        let mut pixels = img.into_rgba8();
+        transform.apply(&mut pixels);
+        DynamicImage::ImageRgba8(pixels)
+    } else {
+        let mut pixels = img.into_rgb8();
+        transform.apply(&mut pixels);
+        DynamicImage::ImageRgb8(pixels)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use image::{GenericImageView, Rgba};
+
+    /// Same 24×16 image stored for every EXIF orientation: red top-left
+    /// quarter, blue top-right quarter and white bottom half
+    const ORIENTATIONS: [&[u8]; 8] = [
+        include_bytes!("../../tests/fixtures/orientation-1.jpg"),
+        include_bytes!("../../tests/fixtures/orientation-2.jpg"),
+        include_bytes!("../../tests/fixtures/orientation-3.jpg"),
+        include_bytes!("../../tests/fixtures/orientation-4.jpg"),
+        include_bytes!("../../tests/fixtures/orientation-5.jpg"),
+        include_bytes!("../../tests/fixtures/orientation-6.jpg"),
+        include_bytes!("../../tests/fixtures/orientation-7.jpg"),
+        include_bytes!("../../tests/fixtures/orientation-8.jpg"),
+    ];
+
+    fn is_close(pixel: Rgba<u8>, rgb: [u8; 3]) -> bool {
+        (0..3).all(|channel| pixel[channel].abs_diff(rgb[channel]) < 20)
+    }
+
+    #[test]
+    fn it_applies_exif_orientation() {
+        for (index, bytes) in ORIENTATIONS.iter().enumerate() {
+            let img = decode_image(bytes.to_vec()).unwrap();
+            let orientation = index + 1;
+            assert_eq!(img.dimensions(), (24, 16), "orientation {orientation}");
+            assert!(
+                is_close(img.get_pixel(5, 3), [220, 30, 30]),
+                "orientation {orientation}"
+            );
+            assert!(
+                is_close(img.get_pixel(18, 3), [30, 30, 220]),
+                "orientation {orientation}"
+            );
+            assert!(
+                is_close(img.get_pixel(5, 12), [255, 255, 255]),
+                "orientation {orientation}"
+            );
+        }
+    }
+
+    #[test]
+    fn it_converts_icc_profile_to_srgb() {
+        // Filled with (100, 150, 200) in Display P3
+        let bytes = include_bytes!("../../tests/fixtures/display-p3.png");
+        let img = decode_image(bytes.to_vec()).unwrap();
+        let pixel = img.get_pixel(0, 0);
+        assert!(pixel[0].abs_diff(83) <= 2, "{pixel:?}");
+        assert!(pixel[1].abs_diff(152) <= 2, "{pixel:?}");
+        assert!(pixel[2].abs_diff(205) <= 2, "{pixel:?}");
+    }
+
+    #[test]
+    fn it_ignores_invalid_icc_profile() {
+        let img = DynamicImage::new_rgb8(2, 2);
+        let converted = to_srgb(img.clone(), b"not a profile");
+        assert_eq!(converted, img);
+    }
+}
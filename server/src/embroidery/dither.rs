@@ -0,0 +1,390 @@
+use image::RgbImage;
+use lab::Lab;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::embroidery::index::{ColorMatcher, LabIndex};
+
+/// Error diffusion kernels as `(dx, dy, weight)`, with weights summing up to
+/// the divisor (less for Atkinson, which drops a quarter of the error)
+const FLOYD_STEINBERG: (&[(i32, i32, f32)], f32) =
+    (&[(1, 0, 7.0), (-1, 1, 3.0), (0, 1, 5.0), (1, 1, 1.0)], 16.0);
+const ATKINSON: (&[(i32, i32, f32)], f32) = (
+    &[
+        (1, 0, 1.0),
+        (2, 0, 1.0),
+        (-1, 1, 1.0),
+        (0, 1, 1.0),
+        (1, 1, 1.0),
+        (0, 2, 1.0),
+    ],
+    8.0,
+);
+const SIERRA: (&[(i32, i32, f32)], f32) = (
+    &[
+        (1, 0, 5.0),
+        (2, 0, 3.0),
+        (-2, 1, 2.0),
+        (-1, 1, 4.0),
+        (0, 1, 5.0),
+        (1, 1, 4.0),
+        (2, 1, 2.0),
+        (-1, 2, 2.0),
+        (0, 2, 3.0),
+        (1, 2, 2.0),
+    ],
+    32.0,
+);
+
+const BAYER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DitherMode {
+    #[default]
+    None,
+    FloydSteinberg,
+    Atkinson,
+    Sierra,
+    Bayer,
+}
+
+impl DitherMode {
+    pub const ALL: [DitherMode; 5] = [
+        DitherMode::None,
+        DitherMode::FloydSteinberg,
+        DitherMode::Atkinson,
+        DitherMode::Sierra,
+        DitherMode::Bayer,
+    ];
+}
+
+impl fmt::Display for DitherMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            DitherMode::None => "none",
+            DitherMode::FloydSteinberg => "floyd_steinberg",
+            DitherMode::Atkinson => "atkinson",
+            DitherMode::Sierra => "sierra",
+            DitherMode::Bayer => "bayer",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for DitherMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        DitherMode::ALL
+            .into_iter()
+            .find(|mode| mode.to_string().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| {
+                let names: Vec<String> = DitherMode::ALL.iter().map(|m| m.to_string()).collect();
+                format!("Value should be one of: {}", names.join(", "))
+            })
+    }
+}
+
+/// Dithering mode with its strength between 0 (no dithering) and 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Dither {
+    pub mode: DitherMode,
+    pub strength: f32,
+}
+
+impl Default for Dither {
+    fn default() -> Self {
+        Dither {
+            mode: DitherMode::None,
+            strength: 1.0,
+        }
+    }
+}
+
+impl Dither {
+    pub fn new(mode: DitherMode, strength: f32) -> Self {
+        Dither {
+            mode,
+            strength: strength.clamp(0.0, 1.0),
+        }
+    }
+
+    /// Assigns every pixel of `pic` to the index of a color in `index`.
+    /// Pixels that are not `stitched`, given in row-major order, are left
+    /// empty. An empty `stitched` stitches every pixel. Returns `None` if a
+    /// pixel has to be stitched and the index is empty.
+    pub fn assign(&self, pic: &RgbImage, stitched: &[bool], index: &LabIndex) -> Option<Cells> {
+        let pixels = Pixels { pic, stitched };
+        if self.strength <= 0.0 {
+            return assign_closest(pixels, index);
+        }
+        match self.mode {
+            DitherMode::None => assign_closest(pixels, index),
+            DitherMode::FloydSteinberg => {
+                diffuse_error(pixels, index, FLOYD_STEINBERG, self.strength)
+            }
+            DitherMode::Atkinson => diffuse_error(pixels, index, ATKINSON, self.strength),
+            DitherMode::Sierra => diffuse_error(pixels, index, SIERRA, self.strength),
+            DitherMode::Bayer => order(pixels, index, self.strength),
+        }
+    }
+}
+
+/// Rows of cells holding the index of their color, `None` for empty cells
+pub type Cells = Vec<Vec<Option<usize>>>;
+
+#[derive(Clone, Copy)]
+struct Pixels<'a> {
+    pic: &'a RgbImage,
+    stitched: &'a [bool],
+}
+
+impl Pixels<'_> {
+    fn is_stitched(&self, x: usize, y: usize) -> bool {
+        self.stitched
+            .get(y * self.pic.width() as usize + x)
+            .copied()
+            .unwrap_or(true)
+    }
+}
+
+fn assign_closest(pixels: Pixels, index: &LabIndex) -> Option<Cells> {
+    let mut matcher = ColorMatcher::new(index);
+    pixels
+        .pic
+        .rows()
+        .enumerate()
+        .map(|(y, row)| {
+            row.enumerate()
+                .map(|(x, pixel)| match pixels.is_stitched(x, y) {
+                    true => matcher.closest((*pixel).into()).map(Some),
+                    false => Some(None),
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Error diffusion in Lab. Rows are scanned in alternating directions to
+/// avoid the error drifting to one side. Empty cells neither take nor pass
+/// on any error.
+fn diffuse_error(
+    pixels: Pixels,
+    index: &LabIndex,
+    (kernel, divisor): (&[(i32, i32, f32)], f32),
+    strength: f32,
+) -> Option<Cells> {
+    let (width, height) = (pixels.pic.width() as i32, pixels.pic.height() as i32);
+    let mut labs: Vec<Option<[f32; 3]>> = pixels
+        .pic
+        .enumerate_pixels()
+        .map(|(x, y, pixel)| {
+            let lab = Lab::from_rgb(&pixel.0);
+            pixels
+                .is_stitched(x as usize, y as usize)
+                .then_some([lab.l, lab.a, lab.b])
+        })
+        .collect();
+
+    let mut cells: Cells = Vec::with_capacity(height as usize);
+    for y in 0..height {
+        let mut row: Vec<Option<usize>> = vec![None; width as usize];
+        let reversed = y % 2 == 1;
+        for step in 0..width {
+            let x = if reversed { width - 1 - step } else { step };
+            let Some([l, a, b]) = labs[(y * width + x) as usize] else {
+                continue;
+            };
+            let lab = Lab {
+                l: l.clamp(0.0, 100.0),
+                a: a.clamp(-128.0, 127.0),
+                b: b.clamp(-128.0, 127.0),
+            };
+            let closest = index.closest(lab)?;
+            row[x as usize] = Some(closest);
+
+            let chosen = index.lab(closest);
+            let error = [
+                (lab.l - chosen.l) * strength / divisor,
+                (lab.a - chosen.a) * strength / divisor,
+                (lab.b - chosen.b) * strength / divisor,
+            ];
+            for &(dx, dy, weight) in kernel {
+                let (nx, ny) = (if reversed { x - dx } else { x + dx }, y + dy);
+                if nx < 0 || nx >= width || ny >= height {
+                    continue;
+                }
+                if let Some(neighbour) = &mut labs[(ny * width + nx) as usize] {
+                    for (channel, error) in neighbour.iter_mut().zip(error) {
+                        *channel += error * weight;
+                    }
+                }
+            }
+        }
+        cells.push(row);
+    }
+    Some(cells)
+}
+
+/// Ordered dithering with a 4×4 Bayer matrix. A pixel lying between its
+/// closest color and the next one is given the next one whenever its
+/// position along the way exceeds the matrix threshold.
+fn order(pixels: Pixels, index: &LabIndex, strength: f32) -> Option<Cells> {
+    let mut matcher = ColorMatcher::new(index);
+    pixels
+        .pic
+        .rows()
+        .enumerate()
+        .map(|(y, row)| {
+            row.enumerate()
+                .map(|(x, pixel)| {
+                    if !pixels.is_stitched(x, y) {
+                        return Some(None);
+                    }
+                    let closest = matcher.closest((*pixel).into())?;
+                    let lab = Lab::from_rgb(&pixel.0);
+                    let Some((next, ratio)) = next_color(lab, closest, index) else {
+                        return Some(Some(closest));
+                    };
+                    let threshold = (BAYER[y % 4][x % 4] as f32 + 0.5) / 16.0;
+                    Some(Some(if ratio * strength > threshold {
+                        next
+                    } else {
+                        closest
+                    }))
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Finds the color other than `closest` nearest to `lab` and how far `lab`
+/// lies from `closest` towards it, between 0 and 1.
+fn next_color(lab: Lab, closest: usize, index: &LabIndex) -> Option<(usize, f32)> {
+    let origin = index.lab(closest);
+    let next = (0..index.len())
+        .filter(|&i| i != closest)
+        .min_by(|&x, &y| {
+            lab.squared_distance(&index.lab(x))
+                .total_cmp(&lab.squared_distance(&index.lab(y)))
+        })?;
+    let target = index.lab(next);
+
+    let direction = [
+        target.l - origin.l,
+        target.a - origin.a,
+        target.b - origin.b,
+    ];
+    let offset = [lab.l - origin.l, lab.a - origin.a, lab.b - origin.b];
+    let length: f32 = direction.iter().map(|value| value.powi(2)).sum();
+    if length == 0.0 {
+        return None;
+    }
+    let projection: f32 = direction.iter().zip(offset).map(|(d, o)| d * o).sum();
+    Some((next, (projection / length).clamp(0.0, 1.0)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::embroidery::metric::ColorMetric;
+    use image::{ImageBuffer, Rgb};
+
+    fn black_and_white() -> LabIndex {
+        LabIndex::new(
+            vec![Lab::from_rgb(&[0, 0, 0]), Lab::from_rgb(&[255, 255, 255])],
+            ColorMetric::default(),
+        )
+    }
+
+    fn count_white(cells: &Cells) -> usize {
+        cells
+            .iter()
+            .flatten()
+            .filter(|&&cell| cell == Some(1))
+            .count()
+    }
+
+    #[test]
+    fn it_dithers_flat_gray() {
+        let index = black_and_white();
+        // L* of 50 lies halfway between black and white
+        let [r, g, b] = Lab::to_rgb(&Lab {
+            l: 50.0,
+            a: 0.0,
+            b: 0.0,
+        });
+        let gray = Rgb([r, g, b]);
+        let pic = ImageBuffer::from_pixel(16, 16, gray);
+
+        for mode in DitherMode::ALL {
+            let cells = Dither::new(mode, 1.0).assign(&pic, &[], &index).unwrap();
+            let white = count_white(&cells);
+            match mode {
+                DitherMode::None => assert!(white == 0 || white == 256),
+                DitherMode::Atkinson => assert!(white > 64 && white < 192, "{mode}: {white}"),
+                _ => assert!(white.abs_diff(128) <= 16, "{mode}: {white}"),
+            }
+        }
+    }
+
+    #[test]
+    fn it_scales_with_strength() {
+        let index = black_and_white();
+        let gray = Rgb([90, 90, 90]);
+        let pic = ImageBuffer::from_pixel(16, 16, gray);
+
+        let none = Dither::new(DitherMode::Bayer, 0.0).assign(&pic, &[], &index);
+        assert_eq!(none, Dither::default().assign(&pic, &[], &index));
+        let half = count_white(
+            &Dither::new(DitherMode::Bayer, 0.5)
+                .assign(&pic, &[], &index)
+                .unwrap(),
+        );
+        let full = count_white(
+            &Dither::new(DitherMode::Bayer, 1.0)
+                .assign(&pic, &[], &index)
+                .unwrap(),
+        );
+        assert!(half < full);
+    }
+
+    #[test]
+    fn it_leaves_unstitched_pixels_empty() {
+        let index = black_and_white();
+        let pic = ImageBuffer::from_pixel(16, 16, Rgb([128, 128, 128]));
+        let stitched: Vec<bool> = (0..256).map(|i| i % 16 >= 8).collect();
+
+        for mode in DitherMode::ALL {
+            let cells = Dither::new(mode, 1.0)
+                .assign(&pic, &stitched, &index)
+                .unwrap();
+            for row in cells {
+                assert!(row[..8].iter().all(Option::is_none), "{mode}");
+                assert!(row[8..].iter().all(Option::is_some), "{mode}");
+            }
+        }
+    }
+
+    #[test]
+    fn it_assigns_unstitched_image_without_colors() {
+        let index = LabIndex::new(vec![], ColorMetric::default());
+        let pic = ImageBuffer::from_pixel(4, 4, Rgb([0, 0, 0]));
+        let cells = Dither::default()
+            .assign(&pic, &[false; 16], &index)
+            .unwrap();
+        assert!(cells.iter().flatten().all(Option::is_none));
+    }
+
+    #[test]
+    fn it_parses_dither_mode() {
+        assert_eq!(
+            "Floyd_Steinberg".parse::<DitherMode>(),
+            Ok(DitherMode::FloydSteinberg)
+        );
+        assert_eq!(
+            "random".parse::<DitherMode>().unwrap_err(),
+            "Value should be one of: none, floyd_steinberg, atkinson, sierra, bayer"
+        );
+    }
+}
@@ -0,0 +1,63 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// File a canvas is exported to.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ExportFormat {
+    /// Picture of the stitches at the size of the uploaded image
+    #[default]
+    Png,
+    /// Printable chart with symbols, split across pages
+    Pdf,
+    /// Chart as vector cells, to edit or print at any size
+    Svg,
+    /// Open Cross Stitch chart, to open in other chart editors
+    Oxs,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 4] = [
+        ExportFormat::Png,
+        ExportFormat::Pdf,
+        ExportFormat::Svg,
+        ExportFormat::Oxs,
+    ];
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Png => "image/png",
+            ExportFormat::Pdf => "application/pdf",
+            ExportFormat::Svg => "image/svg+xml",
+            ExportFormat::Oxs => "application/xml",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Png => "png",
+            ExportFormat::Pdf => "pdf",
+            ExportFormat::Svg => "svg",
+            ExportFormat::Oxs => "oxs",
+        }
+    }
+}
+
+impl fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.extension())
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ExportFormat::ALL
+            .into_iter()
+            .find(|format| format.to_string().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| {
+                let names: Vec<String> = ExportFormat::ALL.iter().map(|f| f.to_string()).collect();
+                format!("Value should be one of: {}", names.join(", "))
+            })
+    }
+}
@@ -1,70 +1,180 @@
 use image::DynamicImage;
-use palette_extract::{get_palette_with_options, MaxColors, PixelEncoding, PixelFilter, Quality};
+use lab::Lab;
 use std::collections::HashSet;
 use std::io::{Error, ErrorKind};
 
-use crate::embroidery::colors::{DmcColor, RgbColor};
+use crate::embroidery::catalog::ThreadCatalog;
+use crate::embroidery::colors::{RgbColor, ThreadColor};
+use crate::embroidery::inventory::Inventory;
+use crate::embroidery::metric::ColorMetric;
+use crate::embroidery::quantizer::Quantizer;
 
 pub trait ImagePalette {
-    fn get_rgb_palette(&self, n_colors: u8) -> Result<Vec<RgbColor>, Error>;
-    fn get_dmc_palette(&self, n_colors: u8) -> Result<Vec<DmcColor>, Error>;
+    fn get_rgb_palette(
+        &self,
+        n_colors: u8,
+        quantizer: &dyn Quantizer,
+    ) -> Result<Vec<RgbColor>, Error>;
+
+    /// Included threads replace the colors closest to them, the remaining
+    /// colors are matched to the other threads of the selection.
+    fn get_thread_palette(
+        &self,
+        n_colors: u8,
+        selection: &ThreadSelection,
+        metric: ColorMetric,
+        quantizer: &dyn Quantizer,
+    ) -> Result<Vec<ThreadColor>, Error> {
+        let mut colors = self.get_rgb_palette(n_colors, quantizer)?;
+        for thread in selection.include {
+            let lab = Lab::from_rgb(&thread.rgb.into());
+            let closest = colors
+                .iter()
+                .map(|color| metric.difference(lab, Lab::from_rgb(&(*color).into())))
+                .enumerate()
+                .min_by(|x, y| x.1.total_cmp(&y.1))
+                .map(|(index, _)| index);
+            if let Some(index) = closest {
+                colors.remove(index);
+            }
+        }
+
+        let mut threads = selection.include.to_vec();
+        threads.extend(convert_rgb_to_threads(
+            &colors,
+            &selection.candidates(),
+            metric,
+        ));
+        Ok(threads)
+    }
 }
 
-impl ImagePalette for DynamicImage {
-    fn get_rgb_palette(&self, n_colors: u8) -> Result<Vec<RgbColor>, Error> {
+/// Threads of a catalog the palette is chosen from.
+#[derive(Debug, Clone, Copy)]
+pub struct ThreadSelection<'a> {
+    pub catalog: &'a ThreadCatalog,
+    /// Threads always in the palette
+    pub include: &'a [ThreadColor],
+    /// Threads never in the palette
+    pub exclude: &'a [ThreadColor],
+    /// Only threads owned are matched when set
+    pub inventory: Option<&'a Inventory>,
+}
+
+impl<'a> ThreadSelection<'a> {
+    pub fn new(catalog: &'a ThreadCatalog) -> Self {
+        ThreadSelection {
+            catalog,
+            include: &[],
+            exclude: &[],
+            inventory: None,
+        }
+    }
+
+    /// Threads colors can be matched to, apart from the included ones and
+    /// the threads of their colors
+    fn candidates(&self) -> Vec<ThreadColor> {
+        self.catalog
+            .threads()
+            .filter(|thread| {
+                !self.include.iter().any(|other| other.rgb == thread.rgb)
+                    && !self.exclude.contains(thread)
+            })
+            .filter(|thread| match self.inventory {
+                Some(inventory) => inventory.contains(thread),
+                None => true,
+            })
+            .collect()
+    }
+}
+
+impl ImagePalette for [RgbColor] {
+    fn get_rgb_palette(
+        &self,
+        n_colors: u8,
+        quantizer: &dyn Quantizer,
+    ) -> Result<Vec<RgbColor>, Error> {
         if n_colors <= 2 {
             return Err(Error::new(
                 ErrorKind::InvalidInput,
                 "Number of colors should be bigger than 2",
             ));
         }
-        // palette_extract library gives wrong number of colors depending on input
-        // to fix this, modification done below
-        let mut n_colors: u8 = n_colors;
-        if n_colors > 7 {
-            n_colors += 1;
-        } else if n_colors < 4 {
-            n_colors -= 1
-        }
-
-        let pixels = self.to_rgb8();
-        let mut colors = get_palette_with_options(
-            &pixels,
-            PixelEncoding::Rgb,
-            Quality::new(10),
-            MaxColors::new(n_colors),
-            PixelFilter::None,
-        );
-
-        Ok(colors
-            .iter_mut()
-            .map(|val| RgbColor {
-                red: val.r,
-                green: val.g,
-                blue: val.b,
-            })
-            .collect())
+        Ok(quantizer.quantize(self, n_colors))
     }
+}
 
-    fn get_dmc_palette(&self, n_colors: u8) -> Result<Vec<DmcColor>, Error> {
-        let colors = self.get_rgb_palette(n_colors).unwrap();
-        Ok(convert_rgb_to_dmc(&colors))
+impl ImagePalette for DynamicImage {
+    fn get_rgb_palette(
+        &self,
+        n_colors: u8,
+        quantizer: &dyn Quantizer,
+    ) -> Result<Vec<RgbColor>, Error> {
+        let pixels: Vec<RgbColor> = self
+            .to_rgb8()
+            .pixels()
+            .map(|&pixel| RgbColor::from(pixel))
+            .collect();
+        pixels.get_rgb_palette(n_colors, quantizer)
     }
 }
 
-fn convert_rgb_to_dmc(colors: &Vec<RgbColor>) -> Vec<DmcColor> {
-    let mut dmc_colors: HashSet<DmcColor> = HashSet::new();
-    for color in colors {
-        let dmc_color = color.find_dmc();
-        dmc_colors.insert(dmc_color);
+/// Snaps colors to threads of distinct colors. Threads go to the colors
+/// closest to them first, so a color that loses its closest thread falls back
+/// to the next best unused one. Repeated colors are only matched once.
+fn convert_rgb_to_threads(
+    colors: &[RgbColor],
+    threads: &[ThreadColor],
+    metric: ColorMetric,
+) -> Vec<ThreadColor> {
+    let mut seen: HashSet<RgbColor> = HashSet::with_capacity(colors.len());
+    let labs: Vec<Lab> = colors
+        .iter()
+        .filter(|&&color| seen.insert(color))
+        .map(|color| Lab::from_rgb(&(*color).into()))
+        .collect();
+    let threads: Vec<(ThreadColor, Lab)> = threads
+        .iter()
+        .map(|&thread| (thread, Lab::from_rgb(&thread.rgb.into())))
+        .collect();
+
+    let mut pairs: Vec<(f32, usize, usize)> = Vec::with_capacity(labs.len() * threads.len());
+    for (i, &lab) in labs.iter().enumerate() {
+        for (j, &(_, thread_lab)) in threads.iter().enumerate() {
+            pairs.push((metric.difference(lab, thread_lab), i, j));
+        }
+    }
+    pairs.sort_by(|x, y| x.0.total_cmp(&y.0));
+
+    let mut assigned: Vec<Option<usize>> = vec![None; labs.len()];
+    let mut used: Vec<bool> = vec![false; threads.len()];
+    let mut remaining = labs.len().min(threads.len());
+    for (_, color, thread) in pairs {
+        if remaining == 0 {
+            break;
+        }
+        if assigned[color].is_none() && !used[thread] {
+            assigned[color] = Some(thread);
+            // Threads of the same color, like DMC 776 and 33, go together
+            let rgb = threads[thread].0.rgb;
+            for (used, &(other, _)) in used.iter_mut().zip(&threads) {
+                *used |= other.rgb == rgb;
+            }
+            remaining -= 1;
+        }
     }
 
-    dmc_colors.into_iter().collect()
+    assigned
+        .into_iter()
+        .flatten()
+        .map(|thread| threads[thread].0)
+        .collect()
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::embroidery::quantizer::{KMeans, PaletteExtract};
     use image::{ImageBuffer, Rgb};
     use lab::Lab;
     use std::cmp::Ordering;
@@ -80,17 +190,21 @@ mod test {
         DynamicImage::ImageRgb8(image_buffer)
     }
 
+    fn dmc_threads() -> Vec<ThreadColor> {
+        ThreadCatalog::dmc().threads().collect()
+    }
+
     #[test]
     fn it_gets_rgb_palette() {
         let image = generate_image();
-        let palette = image.get_rgb_palette(3).unwrap();
+        let palette = image.get_rgb_palette(3, &KMeans::default()).unwrap();
         assert_eq!(palette.len(), 3)
     }
 
     #[test]
     fn it_gets_rgb_palette_invalid_input() {
         let image = generate_image();
-        let err = image.get_rgb_palette(2).unwrap_err();
+        let err = image.get_rgb_palette(2, &KMeans::default()).unwrap_err();
         assert_eq!(err.to_string(), "Number of colors should be bigger than 2");
         assert_eq!(err.kind(), ErrorKind::InvalidInput);
     }
@@ -98,7 +212,14 @@ mod test {
     #[test]
     fn it_gets_dmc_palette() {
         let image = generate_image();
-        let mut colors = image.get_dmc_palette(3).unwrap();
+        let mut colors = image
+            .get_thread_palette(
+                3,
+                &ThreadSelection::new(ThreadCatalog::dmc()),
+                ColorMetric::default(),
+                &PaletteExtract,
+            )
+            .unwrap();
         colors.sort_by(|color_1, color_2| {
             let lab_1 = Lab::from_rgb(&color_1.rgb.into());
             let lab_2 = Lab::from_rgb(&color_2.rgb.into());
@@ -109,7 +230,170 @@ mod test {
         });
         assert_eq!(colors.len(), 3);
         assert_eq!(colors[0].name, "310");
-        assert_eq!(colors[1].name, "13");
+        // "13" back when differences were truncated to integers: the middle
+        // color is 14.36 from DMC 13 and 14.21 from DMC 14, a tie that went
+        // to 13 as it comes first in the catalog
+        assert_eq!(colors[1].name, "14");
         assert_eq!(colors[2].name, "B5200");
     }
+
+    #[test]
+    fn it_gets_thread_palette_of_brand() {
+        let image = generate_image();
+        let catalog = ThreadCatalog::find("Anchor").unwrap();
+        let colors = image
+            .get_thread_palette(
+                3,
+                &ThreadSelection::new(catalog),
+                ColorMetric::default(),
+                &KMeans::default(),
+            )
+            .unwrap();
+        assert!(colors.iter().all(|color| color.brand == "Anchor"));
+        assert!(colors.iter().any(|color| color.name == "403"));
+    }
+
+    #[test]
+    fn it_gets_distinct_threads_for_close_colors() {
+        let colors = vec![
+            RgbColor {
+                red: 255,
+                green: 29,
+                blue: 30,
+            },
+            RgbColor {
+                red: 254,
+                green: 30,
+                blue: 30,
+            },
+        ];
+        let threads = convert_rgb_to_threads(&colors, &dmc_threads(), ColorMetric::default());
+        assert_eq!(threads.len(), 2);
+        assert_ne!(threads[0], threads[1]);
+    }
+
+    #[test]
+    fn it_matches_repeated_colors_once() {
+        let black = RgbColor {
+            red: 0,
+            green: 0,
+            blue: 0,
+        };
+        let threads = convert_rgb_to_threads(
+            &[black, black, black],
+            &dmc_threads(),
+            ColorMetric::default(),
+        );
+        assert_eq!(threads.len(), 1);
+        assert_eq!(threads[0].name, "310");
+    }
+
+    #[test]
+    fn it_matches_threads_of_one_color_once() {
+        let dmc = ThreadCatalog::dmc();
+        let threads = [dmc.get("776").unwrap(), dmc.get("33").unwrap()];
+        assert_eq!(threads[0].rgb, threads[1].rgb);
+        let colors = [
+            RgbColor {
+                red: 130,
+                green: 74,
+                blue: 126,
+            },
+            RgbColor {
+                red: 128,
+                green: 75,
+                blue: 126,
+            },
+        ];
+        let matched = convert_rgb_to_threads(&colors, &threads, ColorMetric::default());
+        assert_eq!(matched.len(), 1);
+    }
+
+    #[test]
+    fn it_gets_requested_number_of_threads() {
+        let image = DynamicImage::ImageRgb8(ImageBuffer::from_fn(64, 64, |x, y| {
+            Rgb([(x * 4) as u8, (y * 4) as u8, 128])
+        }));
+        for n_colors in [5, 20, 40] {
+            let threads = image
+                .get_thread_palette(
+                    n_colors,
+                    &ThreadSelection::new(ThreadCatalog::dmc()),
+                    ColorMetric::default(),
+                    &KMeans::default(),
+                )
+                .unwrap();
+            assert_eq!(threads.len(), n_colors as usize);
+        }
+    }
+
+    #[test]
+    fn it_includes_threads() {
+        let image = generate_image();
+        let dmc = ThreadCatalog::dmc();
+        let include = [dmc.get("310").unwrap()];
+        let selection = ThreadSelection {
+            include: &include,
+            ..ThreadSelection::new(dmc)
+        };
+        let threads = image
+            .get_thread_palette(3, &selection, ColorMetric::default(), &PaletteExtract)
+            .unwrap();
+        let names: Vec<String> = threads
+            .iter()
+            .map(|thread| thread.name.to_string())
+            .collect();
+        // Black is replaced by the included thread instead of matched twice
+        assert_eq!(names, vec!["310", "B5200", "14"]);
+    }
+
+    #[test]
+    fn it_leaves_out_threads_of_included_colors() {
+        let dmc = ThreadCatalog::dmc();
+        let include = [dmc.get("776").unwrap()];
+        let selection = ThreadSelection {
+            include: &include,
+            ..ThreadSelection::new(dmc)
+        };
+        let candidates = selection.candidates();
+        assert!(!candidates.contains(&include[0]));
+        assert!(!candidates.contains(&dmc.get("33").unwrap()));
+    }
+
+    #[test]
+    fn it_excludes_threads() {
+        let image = generate_image();
+        let dmc = ThreadCatalog::dmc();
+        let exclude = [dmc.get("310").unwrap()];
+        let selection = ThreadSelection {
+            exclude: &exclude,
+            ..ThreadSelection::new(dmc)
+        };
+        let threads = image
+            .get_thread_palette(3, &selection, ColorMetric::default(), &PaletteExtract)
+            .unwrap();
+        assert_eq!(threads.len(), 3);
+        assert!(threads.iter().all(|thread| thread.name != "310"));
+    }
+
+    #[test]
+    fn it_matches_inventory_threads_only() {
+        let image = generate_image();
+        let dmc = ThreadCatalog::dmc();
+        let inventory = Inventory::new(vec![
+            dmc.get("321").unwrap(),
+            dmc.get("310").unwrap(),
+            dmc.get("3865").unwrap(),
+            dmc.get("666").unwrap(),
+        ]);
+        let selection = ThreadSelection {
+            inventory: Some(&inventory),
+            ..ThreadSelection::new(dmc)
+        };
+        let threads = image
+            .get_thread_palette(3, &selection, ColorMetric::default(), &PaletteExtract)
+            .unwrap();
+        assert_eq!(threads.len(), 3);
+        assert!(threads.iter().all(|thread| inventory.contains(thread)));
+    }
 }
@@ -0,0 +1,280 @@
+use lab::Lab;
+use std::collections::HashMap;
+
+use crate::embroidery::colors::RgbColor;
+use crate::embroidery::metric::ColorMetric;
+
+/// Number of nearest neighbours checked with the metric before the search
+/// radius that is guaranteed to contain the closest color is known.
+const REFINEMENT_CANDIDATES: usize = 8;
+
+/// k-d tree over colors for nearest lookups under one metric. The tree is
+/// stored implicitly: the median of every range of `order` is the node
+/// splitting that range on the `depth % 3` axis.
+#[derive(Debug, Clone)]
+pub struct LabIndex {
+    metric: ColorMetric,
+    labs: Vec<Lab>,
+    points: Vec<[f32; 3]>,
+    order: Vec<usize>,
+}
+
+impl LabIndex {
+    pub fn new(labs: Vec<Lab>, metric: ColorMetric) -> Self {
+        let points: Vec<[f32; 3]> = labs.iter().map(|&lab| metric.coordinates(lab)).collect();
+        let mut order: Vec<usize> = (0..labs.len()).collect();
+        Self::build(&points, &mut order, 0);
+        LabIndex {
+            metric,
+            labs,
+            points,
+            order,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.labs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labs.is_empty()
+    }
+
+    pub fn lab(&self, index: usize) -> Lab {
+        self.labs[index]
+    }
+
+    /// Returns the index of the color closest to `lab`. Candidates found by
+    /// Euclidean distance are re-ranked with the metric, then the search is
+    /// widened to the radius that is guaranteed to hold the closest color.
+    pub fn closest(&self, lab: Lab) -> Option<usize> {
+        let point = self.metric.coordinates(lab);
+        if self.metric.is_euclidean() {
+            return self.nearest(point, 1).first().map(|&(_, index)| index);
+        }
+
+        let candidates = self.nearest(point, REFINEMENT_CANDIDATES);
+        let closest = self.closest_of(lab, candidates.iter().map(|&(_, i)| i), None)?;
+        if candidates.len() < REFINEMENT_CANDIDATES {
+            return Some(closest.1);
+        }
+        let searched = candidates[candidates.len() - 1].0;
+        let closest = match self.metric.search_radius(lab, closest.0) {
+            Some(radius) if radius.powi(2) <= searched => closest,
+            Some(radius) => {
+                let mut within = Vec::new();
+                self.search_within(point, radius.powi(2), 0, self.order.len(), 0, &mut within);
+                self.closest_of(lab, within.into_iter(), Some(closest))?
+            }
+            None => self.closest_of(lab, 0..self.len(), Some(closest))?,
+        };
+        Some(closest.1)
+    }
+
+    /// Returns up to `k` colors closest to `point` by squared Euclidean
+    /// distance, nearest first.
+    fn nearest(&self, point: [f32; 3], k: usize) -> Vec<(f32, usize)> {
+        let mut found: Vec<(f32, usize)> = Vec::with_capacity(k + 1);
+        if k > 0 {
+            self.search(point, k, 0, self.order.len(), 0, &mut found);
+        }
+        found
+    }
+
+    /// Returns the color of `indices` closest to `lab` under the metric, or
+    /// `closest` if none is closer. Colors whose lower bound is already no
+    /// closer than the best found are skipped.
+    fn closest_of(
+        &self,
+        lab: Lab,
+        indices: impl Iterator<Item = usize>,
+        mut closest: Option<(f32, usize)>,
+    ) -> Option<(f32, usize)> {
+        for index in indices {
+            let other = self.labs[index];
+            if closest.is_some_and(|(best, _)| self.metric.lower_bound(lab, other) >= best) {
+                continue;
+            }
+            let difference = self.metric.difference(lab, other);
+            if closest.is_none_or(|(best, _)| difference < best) {
+                closest = Some((difference, index));
+            }
+        }
+        closest
+    }
+
+    fn build(points: &[[f32; 3]], order: &mut [usize], depth: usize) {
+        if order.len() <= 1 {
+            return;
+        }
+        let mid = order.len() / 2;
+        let axis = depth % 3;
+        order.select_nth_unstable_by(mid, |&x, &y| points[x][axis].total_cmp(&points[y][axis]));
+        let (left, right) = order.split_at_mut(mid);
+        Self::build(points, left, depth + 1);
+        Self::build(points, &mut right[1..], depth + 1);
+    }
+
+    fn search(
+        &self,
+        point: [f32; 3],
+        k: usize,
+        start: usize,
+        end: usize,
+        depth: usize,
+        found: &mut Vec<(f32, usize)>,
+    ) {
+        if start >= end {
+            return;
+        }
+        let mid = start + (end - start) / 2;
+        let index = self.order[mid];
+        let distance = squared_distance(point, self.points[index]);
+        if found.len() < k || distance < found[found.len() - 1].0 {
+            let position = found.partition_point(|&(d, _)| d <= distance);
+            found.insert(position, (distance, index));
+            found.truncate(k);
+        }
+
+        let offset = point[depth % 3] - self.points[index][depth % 3];
+        let (near, far) = if offset < 0.0 {
+            ((start, mid), (mid + 1, end))
+        } else {
+            ((mid + 1, end), (start, mid))
+        };
+        self.search(point, k, near.0, near.1, depth + 1, found);
+        if found.len() < k || offset.powi(2) < found[found.len() - 1].0 {
+            self.search(point, k, far.0, far.1, depth + 1, found);
+        }
+    }
+
+    fn search_within(
+        &self,
+        point: [f32; 3],
+        squared_radius: f32,
+        start: usize,
+        end: usize,
+        depth: usize,
+        found: &mut Vec<usize>,
+    ) {
+        if start >= end {
+            return;
+        }
+        let mid = start + (end - start) / 2;
+        let index = self.order[mid];
+        if squared_distance(point, self.points[index]) <= squared_radius {
+            found.push(index);
+        }
+
+        let offset = point[depth % 3] - self.points[index][depth % 3];
+        if offset < 0.0 || offset.powi(2) <= squared_radius {
+            self.search_within(point, squared_radius, start, mid, depth + 1, found);
+        }
+        if offset >= 0.0 || offset.powi(2) <= squared_radius {
+            self.search_within(point, squared_radius, mid + 1, end, depth + 1, found);
+        }
+    }
+}
+
+fn squared_distance(point: [f32; 3], point_2: [f32; 3]) -> f32 {
+    (point[0] - point_2[0]).powi(2)
+        + (point[1] - point_2[1]).powi(2)
+        + (point[2] - point_2[2]).powi(2)
+}
+
+/// Nearest color lookup that remembers the answer for every RGB value it has
+/// already seen.
+pub struct ColorMatcher<'a> {
+    index: &'a LabIndex,
+    cache: HashMap<RgbColor, usize>,
+}
+
+impl<'a> ColorMatcher<'a> {
+    pub fn new(index: &'a LabIndex) -> Self {
+        ColorMatcher {
+            index,
+            cache: HashMap::new(),
+        }
+    }
+
+    pub fn closest(&mut self, color: RgbColor) -> Option<usize> {
+        if let Some(&index) = self.cache.get(&color) {
+            return Some(index);
+        }
+        let index = self.index.closest(Lab::from_rgb(&color.into()))?;
+        self.cache.insert(color, index);
+        Some(index)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::embroidery::colors::RGB_TO_DMC;
+
+    fn catalog_index(metric: ColorMetric) -> LabIndex {
+        LabIndex::new(RGB_TO_DMC.iter().map(|&(_, lab, _)| lab).collect(), metric)
+    }
+
+    fn sample_colors() -> impl Iterator<Item = Lab> {
+        (0..=255u8)
+            .step_by(15)
+            .flat_map(|r| (0..=255u8).step_by(15).map(move |g| (r, g)))
+            .flat_map(|(r, g)| (0..=255u8).step_by(51).map(move |b| [r, g, b]))
+            .map(|rgb| Lab::from_rgb(&rgb))
+    }
+
+    #[test]
+    fn it_finds_euclidean_neighbours() {
+        let index = catalog_index(ColorMetric::Cie76);
+        for lab in sample_colors() {
+            let point = [lab.l, lab.a, lab.b];
+            let mut expected: Vec<f32> = (0..index.len())
+                .map(|i| lab.squared_distance(&index.lab(i)))
+                .collect();
+            expected.sort_by(f32::total_cmp);
+            let found: Vec<f32> = index.nearest(point, 5).iter().map(|&(d, _)| d).collect();
+            assert_eq!(found, expected[..5]);
+        }
+    }
+
+    #[test]
+    fn it_matches_linear_scan() {
+        for metric in ColorMetric::ALL {
+            let index = catalog_index(metric);
+            for lab in sample_colors() {
+                let closest = index.closest(lab).unwrap();
+                let diff = metric.difference(lab, index.lab(closest));
+                let expected = (0..index.len())
+                    .map(|i| metric.difference(lab, index.lab(i)))
+                    .min_by(f32::total_cmp)
+                    .unwrap();
+                assert!(
+                    diff - expected < 1e-4,
+                    "{metric} missed the closest color for {lab:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn it_handles_empty_index() {
+        let index = LabIndex::new(vec![], ColorMetric::default());
+        assert!(index.closest(Lab::from_rgb(&[0, 0, 0])).is_none());
+    }
+
+    #[test]
+    fn it_caches_matches() {
+        let index = catalog_index(ColorMetric::default());
+        let mut matcher = ColorMatcher::new(&index);
+        let color = RgbColor {
+            red: 255,
+            green: 29,
+            blue: 30,
+        };
+        let closest = matcher.closest(color);
+        assert_eq!(matcher.cache.len(), 1);
+        assert_eq!(matcher.closest(color), closest);
+        assert_eq!(matcher.cache.len(), 1);
+    }
+}
@@ -0,0 +1,122 @@
+use lab::Lab;
+
+use crate::embroidery::colors::{RgbColor, ThreadColor};
+use crate::embroidery::metric::ColorMetric;
+
+/// Threads a user owns. Patterns made with an inventory only use these.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Inventory {
+    threads: Vec<ThreadColor>,
+}
+
+impl Inventory {
+    pub fn new(threads: Vec<ThreadColor>) -> Self {
+        let mut unique: Vec<ThreadColor> = Vec::with_capacity(threads.len());
+        for thread in threads {
+            if !unique.contains(&thread) {
+                unique.push(thread);
+            }
+        }
+        Inventory { threads: unique }
+    }
+
+    pub fn threads(&self) -> &[ThreadColor] {
+        &self.threads
+    }
+
+    pub fn contains(&self, thread: &ThreadColor) -> bool {
+        self.threads.contains(thread)
+    }
+
+    pub fn len(&self) -> usize {
+        self.threads.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.threads.is_empty()
+    }
+
+    /// Owned thread closest to `color`, `None` for an empty inventory
+    pub fn find_closest(&self, color: &RgbColor, metric: ColorMetric) -> Option<ThreadColor> {
+        let lab = Lab::from_rgb(&(*color).into());
+        self.threads.iter().copied().min_by(|x, y| {
+            metric
+                .difference(lab, Lab::from_rgb(&x.rgb.into()))
+                .total_cmp(&metric.difference(lab, Lab::from_rgb(&y.rgb.into())))
+        })
+    }
+}
+
+/// Reads the thread codes of an inventory CSV. Codes are taken from the
+/// `code` column when the file has a header, otherwise from the first one.
+pub fn parse_csv_codes(content: &str) -> Result<Vec<String>, csv::Error> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(content.as_bytes());
+    let mut records = reader.records();
+
+    let mut codes = Vec::new();
+    let mut column = 0;
+    if let Some(first) = records.next().transpose()? {
+        match first
+            .iter()
+            .position(|field| field.eq_ignore_ascii_case("code"))
+        {
+            Some(position) => column = position,
+            None => codes.extend(first.get(0).map(String::from)),
+        }
+    }
+    for record in records {
+        codes.extend(record?.get(column).map(String::from));
+    }
+    codes.retain(|code| !code.is_empty());
+    Ok(codes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::embroidery::catalog::ThreadCatalog;
+
+    #[test]
+    fn it_finds_closest_owned_thread() {
+        let dmc = ThreadCatalog::dmc();
+        let inventory = Inventory::new(vec![dmc.get("310").unwrap(), dmc.get("321").unwrap()]);
+        let white = RgbColor {
+            red: 255,
+            green: 255,
+            blue: 255,
+        };
+        let black = RgbColor {
+            red: 0,
+            green: 0,
+            blue: 0,
+        };
+        assert_eq!(
+            inventory.find_closest(&black, ColorMetric::default()),
+            dmc.get("310")
+        );
+        assert_ne!(
+            inventory.find_closest(&white, ColorMetric::default()),
+            dmc.get("B5200")
+        );
+        assert_eq!(
+            Inventory::new(Vec::new()).find_closest(&white, ColorMetric::default()),
+            None
+        );
+    }
+
+    #[test]
+    fn it_parses_csv_with_header() {
+        let codes = parse_csv_codes("name,code,skeins\nBlack,310,2\nWhite, B5200 ,1\n").unwrap();
+        assert_eq!(codes, vec!["310", "B5200"]);
+    }
+
+    #[test]
+    fn it_parses_csv_without_header() {
+        let codes = parse_csv_codes("310,2\n666\n\nB5200,1\n").unwrap();
+        assert_eq!(codes, vec!["310", "666", "B5200"]);
+    }
+}
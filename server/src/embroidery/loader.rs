@@ -0,0 +1,314 @@
+use lab::Lab;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::embroidery::catalog::ThreadCatalog;
+use crate::embroidery::colors::RgbColor;
+use crate::error::CatalogError;
+
+/// Thread of a catalog file. The name may be left empty.
+#[derive(Deserialize)]
+struct ThreadRecord {
+    code: String,
+    name: String,
+    hex: String,
+}
+
+/// Loads every `.csv` and `.json` file in `dir` as a thread catalog named
+/// after its file stem. Other files are ignored, and two files of the same
+/// brand are rejected.
+pub fn load_catalogs(dir: &Path) -> Result<Vec<ThreadCatalog>, CatalogError> {
+    let read_error = |err| CatalogError::Read(dir.to_path_buf(), err);
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+        .map_err(read_error)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()
+        .map_err(read_error)?;
+    paths.retain(|path| path.is_file() && get_extension(path).is_some());
+    paths.sort();
+
+    let mut catalogs: Vec<ThreadCatalog> = Vec::with_capacity(paths.len());
+    for path in &paths {
+        let catalog = load_catalog(path)?;
+        // Catalogs line up with the paths loaded so far
+        if let Some(position) = catalogs
+            .iter()
+            .position(|other| other.brand().eq_ignore_ascii_case(catalog.brand()))
+        {
+            return Err(CatalogError::DuplicateBrand(
+                path.to_path_buf(),
+                paths[position].to_path_buf(),
+            ));
+        }
+        catalogs.push(catalog);
+    }
+    Ok(catalogs)
+}
+
+pub fn load_catalog(path: &Path) -> Result<ThreadCatalog, CatalogError> {
+    let brand = path
+        .file_stem()
+        .and_then(OsStr::to_str)
+        .filter(|stem| !stem.trim().is_empty())
+        .ok_or_else(|| CatalogError::UnsupportedFile(path.to_path_buf()))?;
+    let content =
+        fs::read_to_string(path).map_err(|err| CatalogError::Read(path.to_path_buf(), err))?;
+
+    let records = match get_extension(path).as_deref() {
+        Some("csv") => parse_csv(path, &content)?,
+        Some("json") => parse_json(path, &content)?,
+        _ => return Err(CatalogError::UnsupportedFile(path.to_path_buf())),
+    };
+    if records.is_empty() {
+        return Err(CatalogError::Malformed(
+            path.to_path_buf(),
+            "Catalog contains no threads".into(),
+        ));
+    }
+
+    let mut codes: HashSet<String> = HashSet::with_capacity(records.len());
+    let mut threads: Vec<(RgbColor, Lab, &'static str)> = Vec::with_capacity(records.len());
+    let mut names: HashMap<&'static str, &'static str> = HashMap::new();
+    for (row, record) in records {
+        let malformed =
+            |reason: String| CatalogError::MalformedRow(path.to_path_buf(), row, reason);
+        let code = record.code.trim();
+        if code.is_empty() {
+            return Err(malformed("Thread code is empty".into()));
+        }
+        if !codes.insert(code.to_ascii_lowercase()) {
+            return Err(malformed(format!("Thread code '{code}' is duplicated")));
+        }
+        let rgb = RgbColor::from_hex(&record.hex).ok_or_else(|| {
+            malformed(format!(
+                "Thread '{code}' has invalid hex color '{}'",
+                record.hex
+            ))
+        })?;
+
+        // Catalogs are loaded once at startup and live for the whole process
+        let code: &'static str = Box::leak(code.to_owned().into_boxed_str());
+        threads.push((rgb, Lab::from_rgb(&rgb.into()), code));
+        let name = record.name.trim();
+        if !name.is_empty() {
+            names.insert(code, Box::leak(name.to_owned().into_boxed_str()));
+        }
+    }
+
+    let brand: &'static str = Box::leak(brand.trim().to_owned().into_boxed_str());
+    Ok(ThreadCatalog::new(brand, threads).with_names(names))
+}
+
+fn get_extension(path: &Path) -> Option<String> {
+    path.extension()
+        .and_then(OsStr::to_str)
+        .map(str::to_ascii_lowercase)
+        .filter(|extension| extension == "csv" || extension == "json")
+}
+
+fn parse_csv(path: &Path, content: &str) -> Result<Vec<(usize, ThreadRecord)>, CatalogError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(content.as_bytes());
+    let headers = reader
+        .headers()
+        .map_err(|err| CatalogError::Malformed(path.to_path_buf(), err.to_string()))?
+        .clone();
+    let mut records = Vec::new();
+    for result in reader.records() {
+        let record = result.map_err(|err| {
+            let row = err.position().map_or(0, |position| position.line());
+            CatalogError::MalformedRow(path.to_path_buf(), row as usize, err.to_string())
+        })?;
+        let row = record.position().map_or(0, |position| position.line()) as usize;
+        let thread = record
+            .deserialize(Some(&headers))
+            .map_err(|err| CatalogError::MalformedRow(path.to_path_buf(), row, err.to_string()))?;
+        records.push((row, thread));
+    }
+    Ok(records)
+}
+
+fn parse_json(path: &Path, content: &str) -> Result<Vec<(usize, ThreadRecord)>, CatalogError> {
+    let values: Vec<serde_json::Value> = serde_json::from_str(content).map_err(|err| {
+        CatalogError::Malformed(
+            path.to_path_buf(),
+            format!("Expected an array of threads. {err}"),
+        )
+    })?;
+    values
+        .into_iter()
+        .enumerate()
+        .map(|(index, value)| {
+            serde_json::from_value(value)
+                .map(|record| (index + 1, record))
+                .map_err(|err| {
+                    CatalogError::MalformedRow(path.to_path_buf(), index + 1, err.to_string())
+                })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Write;
+
+    fn write_file(dir: &Path, filename: &str, content: &str) -> PathBuf {
+        let path = dir.join(filename);
+        fs::File::create(&path)
+            .unwrap()
+            .write_all(content.as_bytes())
+            .unwrap();
+        path
+    }
+
+    #[test]
+    fn it_loads_csv_catalog() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_file(
+            dir.path(),
+            "Sulky.csv",
+            "code,name,hex\n942-1001,White,#FFFFFF\n942-1005,\"Black, Deep\",000000\n",
+        );
+
+        let catalog = load_catalog(&path).unwrap();
+        assert_eq!(catalog.brand(), "Sulky");
+        assert_eq!(catalog.len(), 2);
+        let thread = catalog.get("942-1005").unwrap();
+        assert_eq!(thread.brand, "Sulky");
+        assert_eq!(catalog.thread_name("942-1005"), Some("Black, Deep"));
+        assert_eq!(
+            thread.rgb,
+            RgbColor {
+                red: 0,
+                green: 0,
+                blue: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn it_loads_json_catalog() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_file(
+            dir.path(),
+            "Anchor.json",
+            r##"[{"code": "1", "name": "White", "hex": "#FFFFFF"}]"##,
+        );
+
+        let catalog = load_catalog(&path).unwrap();
+        assert_eq!(catalog.brand(), "Anchor");
+        assert_eq!(catalog.get("1").unwrap().rgb.red, 255);
+        assert_eq!(catalog.thread_name("1"), Some("White"));
+    }
+
+    #[test]
+    fn it_loads_catalogs_from_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        write_file(dir.path(), "b.csv", "code,name,hex\n1,White,FFFFFF\n");
+        write_file(
+            dir.path(),
+            "a.json",
+            r#"[{"code": "1", "name": "Black", "hex": "000000"}]"#,
+        );
+        write_file(dir.path(), "README.md", "Thread catalogs");
+
+        let catalogs = load_catalogs(dir.path()).unwrap();
+        let brands: Vec<&str> = catalogs.iter().map(|catalog| catalog.brand()).collect();
+        assert_eq!(brands, vec!["a", "b"]);
+    }
+
+    #[test]
+    fn it_loads_empty_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_file(dir.path(), "brand.csv", "code,name,hex\n1,,FFFFFF\n");
+
+        let catalog = load_catalog(&path).unwrap();
+        assert_eq!(catalog.get("1").unwrap().rgb.green, 255);
+        assert_eq!(catalog.thread_name("1"), None);
+    }
+
+    #[test]
+    fn it_rejects_catalog_without_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_file(dir.path(), "brand.csv", "code,hex\n1,FFFFFF\n");
+
+        let err = load_catalog(&path).unwrap_err();
+        assert!(matches!(err, CatalogError::MalformedRow(_, 2, _)));
+    }
+
+    #[test]
+    fn it_rejects_duplicated_brand() {
+        let dir = tempfile::tempdir().unwrap();
+        write_file(dir.path(), "acme.csv", "code,name,hex\n1,White,FFFFFF\n");
+        write_file(
+            dir.path(),
+            "Acme.json",
+            r#"[{"code": "1", "name": "Black", "hex": "000000"}]"#,
+        );
+
+        let err = load_catalogs(dir.path()).unwrap_err();
+        assert!(matches!(err, CatalogError::DuplicateBrand(..)));
+    }
+
+    #[test]
+    fn it_rejects_invalid_hex() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_file(
+            dir.path(),
+            "brand.csv",
+            "code,name,hex\n1,White,FFFFFF\n2,Black,#00000G\n",
+        );
+
+        let err = load_catalog(&path).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "Malformed row 3 in thread catalog '{}'. Thread '2' has invalid hex color '#00000G'",
+                path.display()
+            )
+        );
+    }
+
+    #[test]
+    fn it_rejects_missing_column() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_file(dir.path(), "brand.csv", "code,name,hex\n1,White\n");
+
+        let err = load_catalog(&path).unwrap_err();
+        assert!(matches!(err, CatalogError::MalformedRow(_, 2, _)));
+    }
+
+    #[test]
+    fn it_rejects_duplicated_code() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_file(
+            dir.path(),
+            "brand.json",
+            r#"[{"code": "1", "name": "White", "hex": "FFFFFF"},
+                {"code": "1", "name": "Black", "hex": "000000"}]"#,
+        );
+
+        let err = load_catalog(&path).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "Malformed row 2 in thread catalog '{}'. Thread code '1' is duplicated",
+                path.display()
+            )
+        );
+    }
+
+    #[test]
+    fn it_rejects_empty_catalog() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_file(dir.path(), "brand.csv", "code,name,hex\n");
+
+        let err = load_catalog(&path).unwrap_err();
+        assert!(matches!(err, CatalogError::Malformed(..)));
+    }
+}
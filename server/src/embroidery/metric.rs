@@ -0,0 +1,492 @@
+use lab::Lab;
+use std::f32::consts::{FRAC_1_SQRT_2, PI};
+use std::fmt;
+use std::str::FromStr;
+
+// D65 reference white, matching the one used by the `lab` crate
+const WHITE_X: f32 = 0.950_449_2;
+const WHITE_Z: f32 = 1.088_916_6;
+const KAPPA: f32 = 24389.0 / 27.0;
+const EPSILON: f32 = 216.0 / 24389.0;
+/// Direction of hue 275°, where the CIEDE2000 rotation term peaks
+const ROTATION_HUE: (f32, f32) = (0.087_155_74, -0.996_194_7);
+/// sin(60°), the peak of sin(2Δθ) in the CIEDE2000 rotation term
+const MAX_ROTATION: f32 = 0.866_025_4;
+/// Upper bounds of sin(2Δθ) by the cosine of the least angle between H̄' and
+/// 275°: 60°, 45°, 30° and 15°
+const ROTATION_BOUNDS: [(f32, f32); 4] = [
+    (0.5, 0.0033),
+    (FRAC_1_SQRT_2, 0.0411),
+    (0.866, 0.2456),
+    (0.9659, 0.6674),
+];
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ColorMetric {
+    Cie76,
+    Cie94,
+    #[default]
+    Ciede2000,
+    /// Euclidean distance in OKLab, scaled by 100 to be on par with ΔE*
+    Oklab,
+}
+
+impl ColorMetric {
+    pub const ALL: [ColorMetric; 4] = [
+        ColorMetric::Cie76,
+        ColorMetric::Cie94,
+        ColorMetric::Ciede2000,
+        ColorMetric::Oklab,
+    ];
+
+    pub fn difference(&self, lab: Lab, lab_2: Lab) -> f32 {
+        match self {
+            ColorMetric::Cie76 => cie76(lab, lab_2),
+            ColorMetric::Cie94 => cie94(lab, lab_2),
+            ColorMetric::Ciede2000 => ciede2000(lab, lab_2),
+            ColorMetric::Oklab => {
+                let [l_1, a_1, b_1] = lab_to_oklab(lab);
+                let [l_2, a_2, b_2] = lab_to_oklab(lab_2);
+                100.0 * ((l_1 - l_2).powi(2) + (a_1 - a_2).powi(2) + (b_1 - b_2).powi(2)).sqrt()
+            }
+        }
+    }
+}
+
+impl ColorMetric {
+    /// Coordinates in which this metric is searched spatially. Every metric
+    /// but OKLab is searched in Lab.
+    pub(crate) fn coordinates(&self, lab: Lab) -> [f32; 3] {
+        match self {
+            ColorMetric::Oklab => lab_to_oklab(lab).map(|value| value * 100.0),
+            _ => [lab.l, lab.a, lab.b],
+        }
+    }
+
+    /// Returns whether the metric is the plain Euclidean distance between
+    /// its coordinates.
+    pub(crate) fn is_euclidean(&self) -> bool {
+        matches!(self, ColorMetric::Cie76 | ColorMetric::Oklab)
+    }
+
+    /// Value never above the difference between both colors that is much
+    /// cheaper to compute, or 0 if the metric is cheap enough already.
+    pub(crate) fn lower_bound(&self, lab: Lab, lab_2: Lab) -> f32 {
+        match self {
+            ColorMetric::Ciede2000 => ciede2000_lower_bound(lab, lab_2),
+            _ => 0.0,
+        }
+    }
+
+    /// Euclidean distance from `lab` beyond which no color can be closer
+    /// than `difference` under this metric, or `None` if there is no bound.
+    pub(crate) fn search_radius(&self, lab: Lab, difference: f32) -> Option<f32> {
+        let chroma = (lab.a.powi(2) + lab.b.powi(2)).sqrt();
+        match self {
+            ColorMetric::Cie76 | ColorMetric::Oklab => Some(difference),
+            // S_C >= S_H >= 1 and both only depend on the reference color
+            ColorMetric::Cie94 => Some(difference * (1.0 + 0.045 * chroma)),
+            // With d the CIE76 distance: S_L <= 1.75, the rotation term takes
+            // away at most 87% of the chroma and hue part, S_C >= S_H and
+            // C' <= 1.5 * C, so ΔE00 >= d * min(1 / 1.75, 0.366 / S_C) where
+            // S_C <= 1 + 0.0675 * C + 0.03375 * d
+            ColorMetric::Ciede2000 => {
+                let denominator = 0.366 - 0.03375 * difference;
+                if denominator <= 0.0 {
+                    return None;
+                }
+                let chroma_radius = difference * (1.0 + 0.0675 * chroma) / denominator;
+                Some(chroma_radius.max(1.75 * difference))
+            }
+        }
+    }
+}
+
+impl fmt::Display for ColorMetric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ColorMetric::Cie76 => "cie76",
+            ColorMetric::Cie94 => "cie94",
+            ColorMetric::Ciede2000 => "ciede2000",
+            ColorMetric::Oklab => "oklab",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for ColorMetric {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ColorMetric::ALL
+            .into_iter()
+            .find(|metric| metric.to_string().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| {
+                let names: Vec<String> = ColorMetric::ALL.iter().map(|m| m.to_string()).collect();
+                format!("Value should be one of: {}", names.join(", "))
+            })
+    }
+}
+
+fn cie76(lab: Lab, lab_2: Lab) -> f32 {
+    lab.squared_distance(&lab_2).sqrt()
+}
+
+/// CIE94 with the graphic arts weighting factors
+fn cie94(lab: Lab, lab_2: Lab) -> f32 {
+    let c1 = (lab.a.powi(2) + lab.b.powi(2)).sqrt();
+    let c2 = (lab_2.a.powi(2) + lab_2.b.powi(2)).sqrt();
+
+    let delta_l = lab.l - lab_2.l;
+    let delta_c = c1 - c2;
+    let delta_h_squared =
+        ((lab.a - lab_2.a).powi(2) + (lab.b - lab_2.b).powi(2) - delta_c.powi(2)).max(0.0);
+
+    let s_sub_c = 1.0 + 0.045 * c1;
+    let s_sub_h = 1.0 + 0.015 * c1;
+
+    (delta_l.powi(2) + (delta_c / s_sub_c).powi(2) + delta_h_squared / s_sub_h.powi(2)).sqrt()
+}
+
+fn ciede2000(lab: Lab, lab_2: Lab) -> f32 {
+    let c1 = (lab.a.powi(2) + lab.b.powi(2)).sqrt();
+    let c2 = (lab_2.a.powi(2) + lab_2.b.powi(2)).sqrt();
+    let c_bar = (c1 + c2) / 2.0;
+
+    let delta_l_prime = lab_2.l - lab.l;
+    let l_bar = (lab.l + lab_2.l) / 2.0;
+    let a_prime_1 = get_a_prime(lab.a, c_bar);
+    let a_prime_2 = get_a_prime(lab_2.a, c_bar);
+    let c_prime_1 = (a_prime_1.powf(2.0) + lab.b.powf(2.0)).sqrt();
+    let c_prime_2 = (a_prime_2.powf(2.0) + lab_2.b.powf(2.0)).sqrt();
+    let c_bar_prime = (c_prime_1 + c_prime_2) / 2.0;
+    let delta_c_prime = c_prime_2 - c_prime_1;
+    let s_sub_l =
+        1.0 + (0.015 * (l_bar - 50.0).powf(2.0)) / (20.0 + (l_bar - 50.0).powf(2.0)).sqrt();
+    let s_sub_c = 1.0 + 0.045 * c_bar_prime;
+
+    let h_prime_1 = get_h_prime_fn(lab.b, a_prime_1);
+    let h_prime_2 = get_h_prime_fn(lab_2.b, a_prime_2);
+
+    let delta_h_prime = get_delta_h_prime(c_prime_1, c_prime_2, h_prime_1, h_prime_2);
+
+    let delta_uppercase_h_prime =
+        2.0 * (c_prime_1 * c_prime_2).sqrt() * (degrees_to_radians(delta_h_prime) / 2.0).sin();
+
+    let uppercase_h_bar_prime =
+        get_uppercase_h_bar_prime(c_prime_1, c_prime_2, h_prime_1, h_prime_2);
+
+    let uppercase_t = get_uppercase_t(uppercase_h_bar_prime);
+
+    let s_sub_uppercase_h = 1.0 + 0.015 * c_bar_prime * uppercase_t;
+
+    let r_sub_t = get_r_sub_t(c_bar_prime, uppercase_h_bar_prime);
+
+    let lightness: f32 = delta_l_prime / (1.0 * s_sub_l);
+
+    let chroma: f32 = delta_c_prime / (1.0 * s_sub_c);
+
+    let hue: f32 = delta_uppercase_h_prime / (1.0 * s_sub_uppercase_h);
+
+    (lightness.powi(2) + chroma.powi(2) + hue.powi(2) + r_sub_t * chroma * hue).sqrt()
+}
+
+/// CIEDE2000 without hue angles. S_H <= S_C and ΔC'² + ΔH'² is the squared
+/// distance between both a'b' points, while the rotation term takes away at
+/// most |R_T| / 2 of the chroma and hue part.
+fn ciede2000_lower_bound(lab: Lab, lab_2: Lab) -> f32 {
+    let c1 = (lab.a.powi(2) + lab.b.powi(2)).sqrt();
+    let c2 = (lab_2.a.powi(2) + lab_2.b.powi(2)).sqrt();
+    let c_bar = (c1 + c2) / 2.0;
+    let a_prime_1 = get_a_prime(lab.a, c_bar);
+    let a_prime_2 = get_a_prime(lab_2.a, c_bar);
+    // C' <= (1 + G) * C
+    let c_bar_prime = c_bar * (1.0 + get_g(c_bar));
+    // t² / sqrt(20 + t²) <= |t|
+    let s_sub_l = 1.0 + 0.015 * ((lab.l + lab_2.l) / 2.0 - 50.0).abs();
+    let s_sub_c = 1.0 + 0.045 * c_bar_prime;
+    let r_sub_t = 2.0
+        * get_chroma_weight(c_bar_prime)
+        * get_rotation_bound([a_prime_1, lab.b], [a_prime_2, lab_2.b]);
+
+    let lightness = (lab_2.l - lab.l) / s_sub_l;
+    let chroma_hue_squared = (a_prime_2 - a_prime_1).powi(2) + (lab_2.b - lab.b).powi(2);
+    (lightness.powi(2) + (1.0 - r_sub_t / 2.0) * chroma_hue_squared / s_sub_c.powi(2)).sqrt()
+}
+
+/// Upper bound of sin(2Δθ) for two a'b' points. H̄' is on the shorter arc
+/// between their hues, which is never closer to 275° than its closer end
+/// unless it goes through 275°.
+fn get_rotation_bound(point: [f32; 2], point_2: [f32; 2]) -> f32 {
+    let dot = |[a, b]: [f32; 2]| a * ROTATION_HUE.0 + b * ROTATION_HUE.1;
+    let side = |[a, b]: [f32; 2]| a * ROTATION_HUE.1 - b * ROTATION_HUE.0;
+    let squared_chroma = |[a, b]: [f32; 2]| a.powi(2) + b.powi(2);
+    let (dot_1, dot_2) = (dot(point), dot(point_2));
+    let (chroma_1, chroma_2) = (squared_chroma(point), squared_chroma(point_2));
+
+    // The arc goes through 275° when the hues are on both sides of the
+    // 95°-275° line and their mean direction leans towards 275°
+    if side(point) * side(point_2) < 0.0 && dot_1 * chroma_2.sqrt() + dot_2 * chroma_1.sqrt() >= 0.0
+    {
+        return MAX_ROTATION;
+    }
+    let beyond =
+        |dot: f32, chroma: f32, cos: f32| dot <= 0.0 || dot.powi(2) <= cos.powi(2) * chroma;
+    ROTATION_BOUNDS
+        .iter()
+        .find(|&&(cos, _)| beyond(dot_1, chroma_1, cos) && beyond(dot_2, chroma_2, cos))
+        .map_or(MAX_ROTATION, |&(_, sin)| sin)
+}
+
+fn get_h_prime_fn(x: f32, y: f32) -> f32 {
+    if x == 0.0 && y == 0.0 {
+        return 0.0;
+    }
+
+    // In f64, so hues exactly 180° apart stay so after rounding
+    let mut hue_angle = (x as f64).atan2(y as f64).to_degrees() as f32;
+    if hue_angle < 0.0 {
+        hue_angle += 360.0;
+    }
+    hue_angle
+}
+
+fn get_a_prime(a: f32, c_bar: f32) -> f32 {
+    a + a * get_g(c_bar)
+}
+
+fn get_g(c_bar: f32) -> f32 {
+    (1.0 - get_chroma_weight(c_bar)) / 2.0
+}
+
+fn get_chroma_weight(c_bar: f32) -> f32 {
+    (c_bar.powi(7) / (c_bar.powi(7) + 25_f32.powi(7))).sqrt()
+}
+
+fn get_delta_h_prime(c_prime_1: f32, c_prime_2: f32, h_prime_1: f32, h_prime_2: f32) -> f32 {
+    if 0.0 == c_prime_1 || 0.0 == c_prime_2 {
+        return 0.0;
+    }
+
+    if (h_prime_1 - h_prime_2).abs() <= 180.0 {
+        return h_prime_2 - h_prime_1;
+    }
+
+    if h_prime_2 <= h_prime_1 {
+        return h_prime_2 - h_prime_1 + 360.0;
+    }
+    h_prime_2 - h_prime_1 - 360.0
+}
+
+fn get_uppercase_h_bar_prime(
+    c_prime_1: f32,
+    c_prime_2: f32,
+    h_prime_1: f32,
+    h_prime_2: f32,
+) -> f32 {
+    if 0.0 == c_prime_1 || 0.0 == c_prime_2 {
+        return h_prime_1 + h_prime_2;
+    }
+
+    if (h_prime_1 - h_prime_2).abs() <= 180.0 {
+        return (h_prime_1 + h_prime_2) / 2.0;
+    }
+
+    if h_prime_1 + h_prime_2 < 360.0 {
+        return (h_prime_1 + h_prime_2 + 360.0) / 2.0;
+    }
+    (h_prime_1 + h_prime_2 - 360.0) / 2.0
+}
+
+fn get_uppercase_t(uppercase_h_bar_prime: f32) -> f32 {
+    1.0 - 0.17 * degrees_to_radians(uppercase_h_bar_prime - 30.0).cos()
+        + 0.24 * degrees_to_radians(2.0 * uppercase_h_bar_prime).cos()
+        + 0.32 * degrees_to_radians(3.0 * uppercase_h_bar_prime + 6.0).cos()
+        - 0.20 * degrees_to_radians(4.0 * uppercase_h_bar_prime - 63.0).cos()
+}
+
+fn get_r_sub_t(c_bar_prime: f32, uppercase_h_bar_prime: f32) -> f32 {
+    -2.0 * get_chroma_weight(c_bar_prime)
+        * degrees_to_radians(60.0 * (-((uppercase_h_bar_prime - 275.0) / 25.0).powi(2)).exp()).sin()
+}
+
+fn degrees_to_radians(degrees: f32) -> f32 {
+    degrees * (PI / 180.0)
+}
+
+fn lab_to_xyz(lab: Lab) -> [f32; 3] {
+    let fy = (lab.l + 16.0) / 116.0;
+    let fx = lab.a / 500.0 + fy;
+    let fz = fy - lab.b / 200.0;
+    let inverse = |f: f32| {
+        if f.powi(3) > EPSILON {
+            f.powi(3)
+        } else {
+            (116.0 * f - 16.0) / KAPPA
+        }
+    };
+    let y = if lab.l > KAPPA * EPSILON {
+        fy.powi(3)
+    } else {
+        lab.l / KAPPA
+    };
+
+    [inverse(fx) * WHITE_X, y, inverse(fz) * WHITE_Z]
+}
+
+pub fn lab_to_oklab(lab: Lab) -> [f32; 3] {
+    let [x, y, z] = lab_to_xyz(lab);
+
+    let l = (0.818_933 * x + 0.361_866_74 * y - 0.128_859_71 * z).cbrt();
+    let m = (0.032_984_544 * x + 0.929_311_9 * y + 0.036_145_64 * z).cbrt();
+    let s = (0.048_200_3 * x + 0.264_366_27 * y + 0.633_851_7 * z).cbrt();
+
+    [
+        0.210_454_26 * l + 0.793_617_8 * m - 0.004_072_047 * s,
+        1.977_998_5 * l - 2.428_592_2 * m + 0.450_593_7 * s,
+        0.025_904_037 * l + 0.782_771_77 * m - 0.808_675_77 * s,
+    ]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn lab(l: f32, a: f32, b: f32) -> Lab {
+        Lab { l, a, b }
+    }
+
+    fn assert_close(actual: f32, expected: f32, tolerance: f32) {
+        assert!(
+            (actual - expected).abs() < tolerance,
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    #[test]
+    fn it_calculates_cie76() {
+        let diff = ColorMetric::Cie76.difference(lab(50.0, 2.5, 0.0), lab(73.0, 25.0, -18.0));
+        assert_close(diff, 36.8680, 1e-3);
+        let diff = ColorMetric::Cie76.difference(
+            lab(100.0, 21.572_104, 272.228_2),
+            lab(100.0, 426.679_45, 72.395_91),
+        );
+        assert_close(diff, 451.7133, 1e-2);
+    }
+
+    #[test]
+    fn it_calculates_cie94() {
+        let diff = ColorMetric::Cie94.difference(
+            lab(100.0, 21.572_104, 272.228_2),
+            lab(100.0, 426.679_45, 72.395_91),
+        );
+        assert_close(diff, 83.7792, 1e-2);
+        let diff = ColorMetric::Cie94.difference(lab(50.0, 2.5, 0.0), lab(73.0, 25.0, -18.0));
+        assert_close(diff, 34.6892, 1e-3);
+    }
+
+    #[test]
+    fn it_calculates_ciede2000() {
+        // All 34 reference pairs of Sharma, Wu & Dalal (2005). Pairs 7 and 8
+        // have a zero chroma, pairs 9 to 16 hues about 180° apart.
+        #[rustfmt::skip]
+        let pairs = [
+            (lab(50.0, 2.6772, -79.7751), lab(50.0, 0.0, -82.7485), 2.0425),
+            (lab(50.0, 3.1571, -77.2803), lab(50.0, 0.0, -82.7485), 2.8615),
+            (lab(50.0, 2.8361, -74.02), lab(50.0, 0.0, -82.7485), 3.4412),
+            (lab(50.0, -1.3802, -84.2814), lab(50.0, 0.0, -82.7485), 1.0),
+            (lab(50.0, -1.1848, -84.8006), lab(50.0, 0.0, -82.7485), 1.0),
+            (lab(50.0, -0.9009, -85.5211), lab(50.0, 0.0, -82.7485), 1.0),
+            (lab(50.0, 0.0, 0.0), lab(50.0, -1.0, 2.0), 2.3669),
+            (lab(50.0, -1.0, 2.0), lab(50.0, 0.0, 0.0), 2.3669),
+            (lab(50.0, 2.49, -0.001), lab(50.0, -2.49, 0.0009), 7.1792),
+            (lab(50.0, 2.49, -0.001), lab(50.0, -2.49, 0.001), 7.1792),
+            (lab(50.0, 2.49, -0.001), lab(50.0, -2.49, 0.0011), 7.2195),
+            (lab(50.0, 2.49, -0.001), lab(50.0, -2.49, 0.0012), 7.2195),
+            (lab(50.0, -0.001, 2.49), lab(50.0, 0.0009, -2.49), 4.8045),
+            (lab(50.0, -0.001, 2.49), lab(50.0, 0.001, -2.49), 4.8045),
+            (lab(50.0, -0.001, 2.49), lab(50.0, 0.0011, -2.49), 4.7461),
+            (lab(50.0, 2.5, 0.0), lab(50.0, 0.0, -2.5), 4.3065),
+            (lab(50.0, 2.5, 0.0), lab(73.0, 25.0, -18.0), 27.1492),
+            (lab(50.0, 2.5, 0.0), lab(61.0, -5.0, 29.0), 22.8977),
+            (lab(50.0, 2.5, 0.0), lab(56.0, -27.0, -3.0), 31.903),
+            (lab(50.0, 2.5, 0.0), lab(58.0, 24.0, 15.0), 19.4535),
+            (lab(50.0, 2.5, 0.0), lab(50.0, 3.1736, 0.5854), 1.0),
+            (lab(50.0, 2.5, 0.0), lab(50.0, 3.2972, 0.0), 1.0),
+            (lab(50.0, 2.5, 0.0), lab(50.0, 1.8634, 0.5757), 1.0),
+            (lab(50.0, 2.5, 0.0), lab(50.0, 3.2592, 0.335), 1.0),
+            (lab(60.2574, -34.0099, 36.2677), lab(60.4626, -34.1751, 39.4387), 1.2644),
+            (lab(63.0109, -31.0961, -5.8663), lab(62.8187, -29.7946, -4.0864), 1.263),
+            (lab(61.2901, 3.7196, -5.3901), lab(61.4292, 2.248, -4.962), 1.8731),
+            (lab(35.0831, -44.1164, 3.7933), lab(35.0232, -40.0716, 1.5901), 1.8645),
+            (lab(22.7233, 20.0904, -46.694), lab(23.0331, 14.973, -42.5619), 2.0373),
+            (lab(36.4612, 47.858, 18.3852), lab(36.2715, 50.5065, 21.2231), 1.4146),
+            (lab(90.8027, -2.0831, 1.441), lab(91.1528, -1.6435, 0.0447), 1.4441),
+            (lab(90.9257, -0.5406, -0.9208), lab(88.6381, -0.8985, -0.7239), 1.5381),
+            (lab(6.7747, -0.2908, -2.4247), lab(5.8714, -0.0985, -2.2286), 0.6377),
+            (lab(2.0776, 0.0795, -1.135), lab(0.9033, -0.0636, -0.5514), 0.9082),
+        ];
+        for (lab_1, lab_2, expected) in pairs {
+            assert_close(
+                ColorMetric::Ciede2000.difference(lab_1, lab_2),
+                expected,
+                1e-3,
+            );
+            assert_close(
+                ColorMetric::Ciede2000.difference(lab_2, lab_1),
+                expected,
+                1e-3,
+            );
+        }
+    }
+
+    #[test]
+    fn it_calculates_oklab() {
+        let white = Lab::from_rgb(&[255, 255, 255]);
+        let red = Lab::from_rgb(&[255, 0, 0]);
+        let blue = Lab::from_rgb(&[0, 0, 255]);
+
+        let [l, a, b] = lab_to_oklab(red);
+        assert_close(l, 0.627_955, 1e-3);
+        assert_close(a, 0.224_863, 1e-3);
+        assert_close(b, 0.125_846, 1e-3);
+
+        assert_close(ColorMetric::Oklab.difference(white, red), 45.2568, 0.1);
+        assert_close(ColorMetric::Oklab.difference(red, blue), 53.7090, 0.1);
+    }
+
+    #[test]
+    fn it_has_zero_difference_for_same_color() {
+        let color = lab(53.24, 80.09, 67.2);
+        for metric in ColorMetric::ALL {
+            assert_close(metric.difference(color, color), 0.0, 1e-4);
+        }
+    }
+
+    #[test]
+    fn it_bounds_difference_from_below() {
+        let labs: Vec<Lab> = (0..=255u8)
+            .step_by(51)
+            .flat_map(|r| (0..=255u8).step_by(51).map(move |g| (r, g)))
+            .flat_map(|(r, g)| (0..=255u8).step_by(51).map(move |b| [r, g, b]))
+            .map(|rgb| Lab::from_rgb(&rgb))
+            .collect();
+        for metric in ColorMetric::ALL {
+            for &lab_1 in &labs {
+                for &lab_2 in &labs {
+                    assert!(metric.lower_bound(lab_1, lab_2) <= metric.difference(lab_1, lab_2));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn it_parses_metric() {
+        assert_eq!("OKLab".parse::<ColorMetric>(), Ok(ColorMetric::Oklab));
+        assert_eq!("cie94".parse::<ColorMetric>(), Ok(ColorMetric::Cie94));
+        assert_eq!(
+            "cie2000".parse::<ColorMetric>().unwrap_err(),
+            "Value should be one of: cie76, cie94, ciede2000, oklab"
+        );
+    }
+}
@@ -1,3 +1,22 @@
+pub mod background;
+pub mod blend;
 pub mod canvas;
+pub mod catalog;
+pub mod cleanup;
 pub mod colors;
+pub mod decode;
+pub mod dither;
+pub mod export;
 mod image;
+pub mod index;
+pub mod inventory;
+pub mod loader;
+pub mod metric;
+pub mod oxs;
+pub mod pdf;
+pub mod preprocess;
+pub mod quantizer;
+pub mod sampling;
+pub mod size;
+pub mod svg;
+pub mod symbols;
@@ -0,0 +1,523 @@
+use image::DynamicImage;
+use roxmltree::{Document, Node};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+use crate::embroidery::blend::Blend;
+use crate::embroidery::canvas::{Canvas, CanvasConfig};
+use crate::embroidery::catalog::ThreadCatalog;
+use crate::embroidery::colors::{RgbColor, ThreadColor, CUSTOM_BRAND};
+use crate::embroidery::metric::ColorMetric;
+use crate::embroidery::size::{Fabric, MAX_CELLS};
+use crate::embroidery::svg::escape;
+use crate::embroidery::symbols::{is_valid_symbol, override_key};
+use crate::error::CanvasError;
+
+/// Palette index of the fabric, every thread coming after it
+const CLOTH_INDEX: usize = 0;
+
+/// Renders `canvas` as an Open Cross Stitch chart: the fabric and threads in
+/// the palette, then a full stitch for every stitched cell.
+pub fn render_oxs(canvas: &Canvas, title: &str) -> String {
+    let palette = canvas.get_thread_palette();
+    let rows = canvas.embroidery.len();
+    let columns = canvas.embroidery.first().map_or(0, |row| row.len());
+    let fabric = canvas.fabric();
+    let cloth = hex(canvas.fabric_color());
+
+    // Writing to a String cannot fail
+    let mut oxs = String::new();
+    let _ = writeln!(oxs, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    let _ = writeln!(oxs, "<chart>");
+    let _ = writeln!(
+        oxs,
+        r#"<format comments01="Open Cross Stitch chart, palette item 0 being the fabric"/>"#
+    );
+    let _ = writeln!(
+        oxs,
+        r#"<properties oxsversion="1.0" software="pixify" software_version="{}" chartheight="{rows}" chartwidth="{columns}" charttitle="{}" author="" copyright="" instructions="" stitchesperinch="{count}" stitchesperinch_y="{count}" palettecount="{}"/>"#,
+        env!("CARGO_PKG_VERSION"),
+        escape(title),
+        palette.len(),
+        count = fabric.count,
+    );
+
+    let _ = writeln!(oxs, "<palette>");
+    let _ = writeln!(
+        oxs,
+        r#"<palette_item index="{CLOTH_INDEX}" number="cloth" name="cloth" color="{cloth}" printcolor="{cloth}" blendcolor="nil" comments="" strands="2" symbol="" dashpattern="" bsstrands="1" bscolor="000000"/>"#
+    );
+    for (index, entry) in (CLOTH_INDEX + 1..).zip(&palette) {
+        let color = hex(entry.color.rgb);
+        // Blends have a strand of each thread, the second one in `blendcolor`
+        let (strands, blend_color) = match entry.strands.as_slice() {
+            [_, second] => {
+                let second = canvas
+                    .colors
+                    .iter()
+                    .find(|thread| thread.name == second.code)
+                    .map_or_else(|| color.clone(), |thread| hex(thread.rgb));
+                (1, second)
+            }
+            _ => (2, "nil".to_string()),
+        };
+        let _ = writeln!(
+            oxs,
+            r#"<palette_item index="{index}" number="{} {}" name="{}" color="{color}" printcolor="{color}" blendcolor="{blend_color}" comments="" strands="{strands}" symbol="{}" dashpattern="" bsstrands="1" bscolor="{color}"/>"#,
+            escape(entry.color.brand),
+            escape(&entry.color.name),
+            escape(&entry.color.name),
+            escape(&entry.symbol),
+        );
+    }
+    let _ = writeln!(oxs, "</palette>");
+
+    let indices: HashMap<RgbColor, usize> = (CLOTH_INDEX + 1..)
+        .zip(&palette)
+        .map(|(index, entry)| (entry.color.rgb, index))
+        .collect();
+    let _ = writeln!(oxs, "<fullstitches>");
+    for (y, row) in canvas.embroidery.iter().enumerate() {
+        for (x, color) in row.iter().enumerate() {
+            if let Some(index) = color.and_then(|color| indices.get(&color)) {
+                let _ = writeln!(oxs, r#"<stitch x="{x}" y="{y}" palindex="{index}"/>"#);
+            }
+        }
+    }
+    let _ = writeln!(oxs, "</fullstitches>");
+    for element in [
+        "partstitches",
+        "backstitches",
+        "ornaments_inc_knots_and_beads",
+        "commentboxes",
+    ] {
+        let _ = writeln!(oxs, "<{element}/>");
+    }
+    let _ = writeln!(oxs, "</chart>");
+    oxs
+}
+
+/// Color of a palette item: a thread, or a blend of two
+enum ItemColor {
+    Thread(ThreadColor),
+    Blend(Blend),
+}
+
+impl ItemColor {
+    fn rgb(&self) -> RgbColor {
+        match self {
+            ItemColor::Thread(thread) => thread.rgb,
+            ItemColor::Blend(blend) => blend.rgb,
+        }
+    }
+
+    /// Symbol override key of the thread, or blend
+    fn symbol_key(&self) -> (String, String) {
+        match self {
+            ItemColor::Thread(thread) => override_key(thread.brand, &thread.name.to_string()),
+            ItemColor::Blend(blend) => override_key(blend.threads[0].brand, &blend.name()),
+        }
+    }
+}
+
+/// Reads an Open Cross Stitch chart back into a canvas of its full stitches.
+/// Thread numbers are looked up in the catalog of their brand, `catalog` for
+/// numbers without one, and threads of other brands or missing from it are
+/// replaced with the closest thread of the same color. Custom threads keep
+/// the exact color of their `#RRGGBB` number. Items with a `blendcolor` are
+/// blends of their thread and the closest thread of that color. Part
+/// stitches, backstitches and ornaments are ignored.
+pub fn parse_oxs(text: &str, catalog: &'static ThreadCatalog) -> Result<Canvas, CanvasError> {
+    let document =
+        Document::parse(text).map_err(|err| CanvasError::MalformedChart(err.to_string()))?;
+    let chart = document.root_element();
+    if !chart.has_tag_name("chart") {
+        return Err(CanvasError::MalformedChart(
+            "Expected a 'chart' element".into(),
+        ));
+    }
+    let child = |name: &str| {
+        chart
+            .children()
+            .find(|node| node.has_tag_name(name))
+            .ok_or_else(|| CanvasError::MalformedChart(format!("Missing '{}' element", name)))
+    };
+
+    let properties = child("properties")?;
+    let columns: u32 = attribute(properties, "chartwidth")?;
+    let rows: u32 = attribute(properties, "chartheight")?;
+    if columns == 0 || rows == 0 {
+        return Err(CanvasError::MalformedChart(
+            "Chart should be at least one cell wide and high".into(),
+        ));
+    }
+    if columns > MAX_CELLS || rows > MAX_CELLS {
+        return Err(CanvasError::GridTooLarge(columns, rows));
+    }
+    let count: f32 = match properties.attribute("stitchesperinch") {
+        Some(_) => attribute(properties, "stitchesperinch")?,
+        None => Fabric::default().count,
+    };
+    if count.is_nan() || count <= 0.0 {
+        return Err(CanvasError::MalformedChart(
+            "Value of 'stitchesperinch' should be positive".into(),
+        ));
+    }
+
+    let mut fabric_color: Option<RgbColor> = None;
+    // By index, so that the palette is read in the same order every time
+    let mut items: BTreeMap<usize, ItemColor> = BTreeMap::new();
+    let mut symbols: HashMap<(String, String), String> = HashMap::new();
+    for item in child("palette")?
+        .children()
+        .filter(|node| node.has_tag_name("palette_item"))
+    {
+        let index: usize = attribute(item, "index")?;
+        let color = item
+            .attribute("color")
+            .and_then(RgbColor::from_hex)
+            .ok_or_else(|| {
+                CanvasError::MalformedChart(format!("Palette item {} has no valid color", index))
+            })?;
+        if index == CLOTH_INDEX {
+            fabric_color = Some(color);
+            continue;
+        }
+        let closest = |color: &RgbColor| catalog.find_closest(color, ColorMetric::default());
+        let number = item.attribute("number").unwrap_or_default();
+        let item_color = find_threads(number, color, catalog)
+            .unwrap_or_else(|| ItemColor::Thread(closest(&color)));
+        // Other editors number blends by their first thread, giving the
+        // color of the second one in `blendcolor`
+        let item_color = match (item_color, blend_color(item)) {
+            (ItemColor::Thread(first), Some(second)) if closest(&second) != first => {
+                ItemColor::Blend(Blend::new(first, closest(&second)))
+            }
+            (item_color, _) => item_color,
+        };
+        if let Some(symbol) = item
+            .attribute("symbol")
+            .filter(|symbol| is_valid_symbol(symbol))
+        {
+            if !symbols.values().any(|other| other == symbol) {
+                symbols.insert(item_color.symbol_key(), symbol.into());
+            }
+        }
+        if items.insert(index, item_color).is_some() {
+            return Err(CanvasError::MalformedChart(format!(
+                "Duplicate palette index {}",
+                index
+            )));
+        }
+    }
+
+    let mut embroidery: Vec<Vec<Option<RgbColor>>> =
+        vec![vec![None; columns as usize]; rows as usize];
+    for stitch in child("fullstitches")?
+        .children()
+        .filter(|node| node.has_tag_name("stitch"))
+    {
+        let x: u32 = attribute(stitch, "x")?;
+        let y: u32 = attribute(stitch, "y")?;
+        let index: usize = attribute(stitch, "palindex")?;
+        if x >= columns || y >= rows {
+            return Err(CanvasError::MalformedChart(format!(
+                "Stitch at {},{} is outside the chart",
+                x, y
+            )));
+        }
+        embroidery[y as usize][x as usize] = match index {
+            CLOTH_INDEX => None,
+            index => Some(
+                items
+                    .get(&index)
+                    .ok_or_else(|| {
+                        CanvasError::MalformedChart(format!("Unknown palette index {}", index))
+                    })?
+                    .rgb(),
+            ),
+        };
+    }
+
+    let mut colors: Vec<ThreadColor> = Vec::new();
+    let mut blends: Vec<Blend> = Vec::new();
+    for item in items.into_values() {
+        let threads = match item {
+            ItemColor::Thread(thread) => vec![thread],
+            ItemColor::Blend(blend) => {
+                if !blends.contains(&blend) {
+                    blends.push(blend);
+                }
+                blend.threads.to_vec()
+            }
+        };
+        for thread in threads {
+            if !colors.contains(&thread) {
+                colors.push(thread);
+            }
+        }
+    }
+
+    let grid = DynamicImage::new_rgb8(columns, rows);
+    let mut config = CanvasConfig::from_image(grid, Some(columns), None)
+        .with_catalog(catalog)
+        .with_fabric(Fabric {
+            count,
+            ..Fabric::default()
+        })
+        .with_symbol_overrides(symbols);
+    if let Some(fabric_color) = fabric_color {
+        config = config.with_fabric_color(fabric_color);
+    }
+    Ok(Canvas::from_cells(config, embroidery, colors, blends))
+}
+
+/// Thread, or blend of two joined by `+`, of a palette item number such as
+/// `DMC 310`, `DMC 310+3865` or `Custom #FF1D1E`. An item of one custom
+/// thread is only read as such when its code is the item `color`. `None` for
+/// brands without a catalog.
+fn find_threads(
+    number: &str,
+    color: RgbColor,
+    catalog: &'static ThreadCatalog,
+) -> Option<ItemColor> {
+    let (catalog, codes) = match number.trim().split_once(' ') {
+        // Custom threads may be blended with threads of the chart catalog
+        Some((brand, codes)) if brand.eq_ignore_ascii_case(CUSTOM_BRAND) => (catalog, codes),
+        Some((brand, codes)) => (ThreadCatalog::find(brand)?, codes),
+        None => (catalog, number.trim()),
+    };
+    let thread = |code: &str| match code.trim() {
+        code if code.starts_with('#') => RgbColor::from_hex(code).map(|rgb| rgb.custom_thread()),
+        code => catalog.get(code),
+    };
+    match codes.split_once('+') {
+        Some((first, second)) => Some(ItemColor::Blend(Blend::new(
+            thread(first)?,
+            thread(second)?,
+        ))),
+        None => thread(codes)
+            .filter(|thread| thread.brand != CUSTOM_BRAND || thread.rgb == color)
+            .map(ItemColor::Thread),
+    }
+}
+
+/// Color of the second thread of a blend, `nil` for items of one thread
+fn blend_color(item: Node) -> Option<RgbColor> {
+    item.attribute("blendcolor").and_then(RgbColor::from_hex)
+}
+
+fn attribute<T: std::str::FromStr>(node: Node, name: &str) -> Result<T, CanvasError> {
+    node.attribute(name)
+        .and_then(|value| value.trim().parse().ok())
+        .ok_or_else(|| {
+            CanvasError::MalformedChart(format!(
+                "Missing or invalid '{}' in '{}'",
+                name,
+                node.tag_name().name()
+            ))
+        })
+}
+
+fn hex(color: RgbColor) -> String {
+    color.to_hex().trim_start_matches('#').to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use image::{ImageBuffer, Rgb};
+    use std::io::Cursor;
+
+    fn gradient() -> Vec<u8> {
+        let img = ImageBuffer::from_fn(40, 10, |x, _| {
+            let value = (x * 255 / 39) as u8;
+            Rgb([value, value, value])
+        });
+        let mut bytes = Vec::new();
+        DynamicImage::ImageRgb8(img)
+            .write_to(&mut Cursor::new(&mut bytes), image::ImageFormat::Png)
+            .unwrap();
+        bytes
+    }
+
+    /// Gray gradient stitched with blends, the first cells left unstitched
+    fn canvas() -> Canvas {
+        let config = CanvasConfig::new(gradient(), Some(40), Some(3))
+            .unwrap()
+            .with_blends(60.0)
+            .with_fabric(Fabric {
+                count: 16.0,
+                ..Fabric::default()
+            })
+            .with_fabric_color(RgbColor {
+                red: 240,
+                green: 230,
+                blue: 200,
+            });
+        let mut canvas = Canvas::new(config).unwrap();
+        canvas.embroidery[0][0] = None;
+        canvas
+    }
+
+    fn summary(canvas: &Canvas) -> Vec<(String, String, u32, usize)> {
+        canvas
+            .get_thread_palette()
+            .into_iter()
+            .map(|entry| {
+                (
+                    entry.color.name,
+                    entry.symbol,
+                    entry.n_stitches,
+                    entry.strands.len(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn it_round_trips_charts() {
+        let canvas = canvas();
+        assert!(!canvas.blends.is_empty());
+        let oxs = render_oxs(&canvas, "Gray & white");
+
+        let imported = parse_oxs(&oxs, ThreadCatalog::dmc()).unwrap();
+        assert_eq!(imported.embroidery, canvas.embroidery);
+        assert_eq!(summary(&imported), summary(&canvas));
+        assert_eq!(imported.dimensions(), canvas.dimensions());
+        assert_eq!(imported.fabric_color(), canvas.fabric_color());
+        assert_eq!(render_oxs(&imported, "Gray & white"), oxs);
+    }
+
+    #[test]
+    fn it_round_trips_custom_palettes() {
+        let palette = ["#0A0B0C", "#7F8081", "3865", "#FAF0E6"]
+            .into_iter()
+            .map(|entry| {
+                RgbColor::from_hex(entry)
+                    .map(|color| color.custom_thread())
+                    .unwrap_or_else(|| ThreadCatalog::dmc().get(entry).unwrap())
+            })
+            .collect();
+        let config = CanvasConfig::new(gradient(), Some(40), Some(4))
+            .unwrap()
+            .with_palette(palette)
+            .with_blends(60.0);
+        let canvas = Canvas::new(config).unwrap();
+        assert!(canvas.colors.iter().any(|thread| thread.name == "#7F8081"));
+        assert!(!canvas.blends.is_empty());
+        let oxs = render_oxs(&canvas, "Custom");
+
+        let imported = parse_oxs(&oxs, ThreadCatalog::dmc()).unwrap();
+        assert_eq!(imported.embroidery, canvas.embroidery);
+        assert_eq!(summary(&imported), summary(&canvas));
+        assert_eq!(render_oxs(&imported, "Custom"), oxs);
+    }
+
+    #[test]
+    fn it_writes_chart_properties() {
+        let oxs = render_oxs(&canvas(), "Gray & white");
+        let document = Document::parse(&oxs).unwrap();
+        let properties = document
+            .descendants()
+            .find(|node| node.has_tag_name("properties"))
+            .unwrap();
+        assert_eq!(properties.attribute("chartwidth"), Some("40"));
+        assert_eq!(properties.attribute("chartheight"), Some("10"));
+        assert_eq!(properties.attribute("stitchesperinch"), Some("16"));
+        assert_eq!(properties.attribute("charttitle"), Some("Gray & white"));
+        let stitches = document
+            .descendants()
+            .filter(|node| node.has_tag_name("stitch"))
+            .count();
+        assert_eq!(stitches, 40 * 10 - 1);
+    }
+
+    #[test]
+    fn it_reads_charts_of_other_editors() {
+        let oxs = r#"<?xml version="1.0" encoding="UTF-8"?>
+<chart>
+<properties oxsversion="1.0" software="other" chartheight="2" chartwidth="3" stitchesperinch="14" palettecount="3"/>
+<palette>
+<palette_item index="0" number="cloth" name="cloth" color="FFFFFF"/>
+<palette_item index="1" number="DMC 310" name="Black" color="000000" symbol="X"/>
+<palette_item index="2" number="Other 1234" name="Red" color="FF0000"/>
+<palette_item index="3" number="DMC 310+B5200" name="Gray" color="808080"/>
+<palette_item index="4" number="Sullivans 310" name="Green" color="00FF00"/>
+<palette_item index="5" number="DMC 321" name="Pink" color="C72B3B" blendcolor="FFFFFF" strands="1"/>
+</palette>
+<fullstitches>
+<stitch x="0" y="0" palindex="1"/>
+<stitch x="1" y="0" palindex="2"/>
+<stitch x="2" y="1" palindex="3"/>
+<stitch x="0" y="1" palindex="0"/>
+<stitch x="1" y="1" palindex="4"/>
+<stitch x="2" y="0" palindex="5"/>
+</fullstitches>
+<backstitches/>
+</chart>"#;
+        let canvas = parse_oxs(oxs, ThreadCatalog::dmc()).unwrap();
+        let dmc = ThreadCatalog::dmc();
+        let closest =
+            |hex: &str| dmc.find_closest(&RgbColor::from_hex(hex).unwrap(), ColorMetric::default());
+        let red = closest("FF0000");
+        // Not DMC 310, Sullivans having no catalog
+        let green = closest("00FF00");
+        let blend = Blend::new(dmc.get("310").unwrap(), dmc.get("B5200").unwrap());
+        // Blended by its `blendcolor`
+        let pink = Blend::new(dmc.get("321").unwrap(), dmc.get("B5200").unwrap());
+        assert_eq!(
+            canvas.embroidery,
+            [
+                [
+                    Some(dmc.get("310").unwrap().rgb),
+                    Some(red.rgb),
+                    Some(pink.rgb)
+                ],
+                [None, Some(green.rgb), Some(blend.rgb)],
+            ]
+        );
+        let palette = canvas.get_thread_palette();
+        assert_eq!(palette.len(), 5);
+        assert!(palette.iter().any(|entry| entry.color.name == "321+B5200"));
+        let black = palette
+            .iter()
+            .find(|entry| entry.color.name == "310")
+            .unwrap();
+        assert_eq!(black.symbol, "X");
+    }
+
+    #[test]
+    fn it_rejects_malformed_charts() {
+        let chart = |stitch: &str| {
+            format!(
+                r#"<chart><properties chartwidth="2" chartheight="2"/><palette><palette_item index="0" color="FFFFFF"/><palette_item index="1" number="DMC 310" color="000000"/></palette><fullstitches>{stitch}</fullstitches></chart>"#
+            )
+        };
+        for (oxs, expected) in [
+            ("not xml".to_string(), None),
+            (
+                r#"<chart><palette/><fullstitches/></chart>"#.to_string(),
+                Some("Malformed OXS chart. Missing 'properties' element"),
+            ),
+            (
+                chart(r#"<stitch x="2" y="0" palindex="1"/>"#),
+                Some("Malformed OXS chart. Stitch at 2,0 is outside the chart"),
+            ),
+            (
+                chart(r#"<stitch x="0" y="0" palindex="5"/>"#),
+                Some("Malformed OXS chart. Unknown palette index 5"),
+            ),
+            (
+                chart(r#"<stitch x="0" y="0"/>"#),
+                Some("Malformed OXS chart. Missing or invalid 'palindex' in 'stitch'"),
+            ),
+        ] {
+            let err = parse_oxs(&oxs, ThreadCatalog::dmc()).err().unwrap();
+            assert!(matches!(err, CanvasError::MalformedChart(_)));
+            if let Some(expected) = expected {
+                assert_eq!(err.to_string(), expected);
+            }
+        }
+    }
+}
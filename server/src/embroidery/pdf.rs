@@ -0,0 +1,614 @@
+use miniz_oxide::deflate::compress_to_vec_zlib;
+use pdf_writer::{Content, Filter, Finish, Name, Pdf, Rect, Ref, Str};
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+
+use crate::embroidery::canvas::{Canvas, Palette};
+use crate::embroidery::colors::RgbColor;
+use crate::embroidery::size::Dimensions;
+
+/// Side of a chart cell, in points
+const CELL_SIZE: f32 = 10.0;
+const PAGE_MARGIN: f32 = 36.0;
+/// Room for the page title above the chart
+const HEADER_HEIGHT: f32 = 24.0;
+/// Room for the row and column numbers around the chart
+const LABEL_SIZE: f32 = 20.0;
+/// Cells printed on both sides of a page break
+const OVERLAP: u32 = 2;
+/// Cells between bold grid lines
+const MAJOR_GRID: u32 = 10;
+const LEGEND_ROW_HEIGHT: f32 = 16.0;
+
+const REGULAR: Name = Name(b"F1");
+const BOLD: Name = Name(b"F2");
+
+/// Advance widths of the printable ASCII characters in Helvetica, in
+/// thousandths of the font size
+const HELVETICA_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556,
+    556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556, 1015, 667, 667, 722, 722, 667,
+    611, 778, 722, 278, 500, 667, 556, 833, 722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667,
+    667, 611, 278, 278, 278, 469, 556, 333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500,
+    222, 833, 556, 556, 556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+];
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PageSize {
+    #[default]
+    A4,
+    Letter,
+}
+
+impl PageSize {
+    pub const ALL: [PageSize; 2] = [PageSize::A4, PageSize::Letter];
+
+    /// Width and height in points
+    fn dimensions(&self) -> (f32, f32) {
+        match self {
+            PageSize::A4 => (595.28, 841.89),
+            PageSize::Letter => (612.0, 792.0),
+        }
+    }
+}
+
+impl fmt::Display for PageSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PageSize::A4 => f.write_str("a4"),
+            PageSize::Letter => f.write_str("letter"),
+        }
+    }
+}
+
+impl FromStr for PageSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        PageSize::ALL
+            .into_iter()
+            .find(|size| size.to_string().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| "Value should be one of: a4, letter".into())
+    }
+}
+
+/// Renders `canvas` as a printable chart: a page map, the chart split
+/// across as many pages as needed, then a legend of the threads.
+pub fn render_pdf(canvas: &Canvas, page_size: PageSize) -> Vec<u8> {
+    let palette = canvas.get_thread_palette();
+    let chart = Chart::new(canvas, &palette, page_size);
+    let (width, height) = chart.page;
+    let legend_rows =
+        ((height - 2.0 * PAGE_MARGIN - HEADER_HEIGHT) / LEGEND_ROW_HEIGHT) as usize - 1;
+    let mut legend_pages: Vec<&[Palette]> = palette.chunks(legend_rows.max(1)).collect();
+    if legend_pages.is_empty() {
+        legend_pages.push(&[]);
+    }
+    let n_pages = 1 + chart.tiles().len() + legend_pages.len();
+
+    let mut pages = vec![chart.map_page(n_pages)];
+    for (index, (columns, rows)) in chart.tiles().into_iter().enumerate() {
+        pages.push(chart.chart_page(index + 2, n_pages, columns, rows));
+    }
+    for entries in legend_pages {
+        pages.push(chart.legend_page(entries));
+    }
+    write_pdf(&pages, width, height)
+}
+
+struct Chart<'a> {
+    embroidery: &'a [Vec<Option<RgbColor>>],
+    dimensions: Dimensions,
+    /// Symbol of every thread color
+    symbols: HashMap<RgbColor, String>,
+    columns: u32,
+    rows: u32,
+    /// Page width and height in points
+    page: (f32, f32),
+    column_tiles: Vec<Range<u32>>,
+    row_tiles: Vec<Range<u32>>,
+}
+
+impl<'a> Chart<'a> {
+    fn new(canvas: &'a Canvas, palette: &[Palette], page_size: PageSize) -> Self {
+        let (width, height) = page_size.dimensions();
+        let rows = canvas.embroidery.len() as u32;
+        let columns = canvas.embroidery.first().map_or(0, |row| row.len()) as u32;
+        let columns_per_page = ((width - 2.0 * PAGE_MARGIN - LABEL_SIZE) / CELL_SIZE) as u32;
+        let rows_per_page =
+            ((height - 2.0 * PAGE_MARGIN - HEADER_HEIGHT - LABEL_SIZE) / CELL_SIZE) as u32;
+        Chart {
+            embroidery: &canvas.embroidery,
+            dimensions: canvas.dimensions(),
+            symbols: palette
+                .iter()
+                .map(|entry| (entry.color.rgb, entry.symbol.clone()))
+                .collect(),
+            columns,
+            rows,
+            page: (width, height),
+            column_tiles: tiles(columns, columns_per_page),
+            row_tiles: tiles(rows, rows_per_page),
+        }
+    }
+
+    /// Columns and rows of every chart page, row by row
+    fn tiles(&self) -> Vec<(Range<u32>, Range<u32>)> {
+        self.row_tiles
+            .iter()
+            .flat_map(|rows| {
+                self.column_tiles
+                    .iter()
+                    .map(move |columns| (columns.clone(), rows.clone()))
+            })
+            .collect()
+    }
+
+    /// First page, with the size of the design and where each chart page
+    /// falls in it
+    fn map_page(&self, n_pages: usize) -> Vec<u8> {
+        let (width, height) = self.page;
+        let dimensions = self.dimensions;
+        let mut content = Content::new();
+        let mut y = height - PAGE_MARGIN - 16.0;
+        text(
+            &mut content,
+            BOLD,
+            16.0,
+            PAGE_MARGIN,
+            y,
+            "Cross stitch chart",
+        );
+        let lines = [
+            format!(
+                "{} x {} stitches, {} colors, {} pages",
+                self.columns,
+                self.rows,
+                self.symbols.len(),
+                n_pages
+            ),
+            format!(
+                "Finished size: {:.1} x {:.1} {}",
+                dimensions.finished_width, dimensions.finished_height, dimensions.unit
+            ),
+            format!(
+                "Fabric size: {:.1} x {:.1} {}, margins included",
+                dimensions.fabric_width, dimensions.fabric_height, dimensions.unit
+            ),
+        ];
+        for line in lines {
+            y -= 16.0;
+            text(&mut content, REGULAR, 10.0, PAGE_MARGIN, y, &line);
+        }
+        y -= 32.0;
+        text(&mut content, BOLD, 12.0, PAGE_MARGIN, y, "Page map");
+
+        let top = y - 12.0;
+        let scale = ((width - 2.0 * PAGE_MARGIN) / self.columns.max(1) as f32)
+            .min((top - PAGE_MARGIN) / self.rows.max(1) as f32);
+        // Every page owns the cells up to where the next one starts
+        let owned = |tiles: &[Range<u32>], index: usize, end: u32| {
+            let start = tiles[index].start;
+            let next = tiles.get(index + 1).map_or(end, |tile| tile.start);
+            (start as f32 * scale, (next - start) as f32 * scale)
+        };
+        content.set_line_width(0.75).set_stroke_gray(0.0);
+        let mut page = 2;
+        for row in 0..self.row_tiles.len() {
+            let (y_offset, tile_height) = owned(&self.row_tiles, row, self.rows);
+            for column in 0..self.column_tiles.len() {
+                let (x_offset, tile_width) = owned(&self.column_tiles, column, self.columns);
+                let (x, y) = (PAGE_MARGIN + x_offset, top - y_offset - tile_height);
+                content.rect(x, y, tile_width, tile_height).stroke();
+                let size = (tile_height * 0.5).min(tile_width * 0.3).min(14.0);
+                let label = page.to_string();
+                let x = x + (tile_width - text_width(&label, size)) / 2.0;
+                text(
+                    &mut content,
+                    REGULAR,
+                    size,
+                    x,
+                    y + (tile_height - size) / 2.0,
+                    &label,
+                );
+                page += 1;
+            }
+        }
+        compress(content)
+    }
+
+    fn chart_page(
+        &self,
+        page: usize,
+        n_pages: usize,
+        columns: Range<u32>,
+        rows: Range<u32>,
+    ) -> Vec<u8> {
+        let (_, height) = self.page;
+        let mut content = Content::new();
+        let title = format!(
+            "Page {} of {}: columns {}-{}, rows {}-{}",
+            page,
+            n_pages,
+            columns.start + 1,
+            columns.end,
+            rows.start + 1,
+            rows.end
+        );
+        text(
+            &mut content,
+            BOLD,
+            11.0,
+            PAGE_MARGIN,
+            height - PAGE_MARGIN - 11.0,
+            &title,
+        );
+
+        let left = PAGE_MARGIN + LABEL_SIZE;
+        let top = height - PAGE_MARGIN - HEADER_HEIGHT - LABEL_SIZE;
+        let x_at = |column: u32| left + (column - columns.start) as f32 * CELL_SIZE;
+        let y_at = |row: u32| top - (row - rows.start) as f32 * CELL_SIZE;
+        let (right, bottom) = (x_at(columns.end), y_at(rows.end));
+
+        // Cells, then their symbols on top
+        let mut fill = None;
+        for row in rows.clone() {
+            for column in columns.clone() {
+                if let Some(color) = self.embroidery[row as usize][column as usize] {
+                    if fill != Some(color) {
+                        set_fill(&mut content, color);
+                        fill = Some(color);
+                    }
+                    let (x, y) = (x_at(column), y_at(row + 1));
+                    content.rect(x, y, CELL_SIZE, CELL_SIZE).fill_nonzero();
+                }
+            }
+        }
+        let size = CELL_SIZE * 0.65;
+        for row in rows.clone() {
+            for column in columns.clone() {
+                let Some(color) = self.embroidery[row as usize][column as usize] else {
+                    continue;
+                };
+                let Some(symbol) = self.symbols.get(&color) else {
+                    continue;
+                };
+                let gray = if color.is_dark() { 1.0 } else { 0.0 };
+                content.set_fill_gray(gray);
+                let x = x_at(column) + (CELL_SIZE - text_width(symbol, size)) / 2.0;
+                let y = y_at(row + 1) + (CELL_SIZE - size * 0.7) / 2.0;
+                text(&mut content, REGULAR, size, x, y, symbol);
+            }
+        }
+        content.set_fill_gray(0.0);
+
+        // Thin lines around every cell, dashed lines where the cells printed
+        // on the neighbouring pages start, bold lines every ten cells
+        content.set_line_width(0.25).set_stroke_gray(0.5);
+        for column in columns.clone() {
+            content
+                .move_to(x_at(column), top)
+                .line_to(x_at(column), bottom);
+        }
+        for row in rows.clone() {
+            content.move_to(left, y_at(row)).line_to(right, y_at(row));
+        }
+        content.stroke();
+
+        content
+            .set_line_width(0.75)
+            .set_stroke_rgb(0.8, 0.0, 0.0)
+            .set_dash_pattern([3.0, 2.0], 0.0);
+        for column in overlap_edges(&columns, self.columns) {
+            content
+                .move_to(x_at(column), top)
+                .line_to(x_at(column), bottom);
+        }
+        for row in overlap_edges(&rows, self.rows) {
+            content.move_to(left, y_at(row)).line_to(right, y_at(row));
+        }
+        content.stroke().set_dash_pattern([], 0.0);
+
+        content.set_line_width(1.0).set_stroke_gray(0.0);
+        let last_column = (columns.end == self.columns).then_some(columns.end);
+        for column in major_lines(columns.start, columns.end).chain(last_column) {
+            content
+                .move_to(x_at(column), top)
+                .line_to(x_at(column), bottom);
+        }
+        let last_row = (rows.end == self.rows).then_some(rows.end);
+        for row in major_lines(rows.start, rows.end).chain(last_row) {
+            content.move_to(left, y_at(row)).line_to(right, y_at(row));
+        }
+        content.stroke();
+
+        // Numbers of every tenth line
+        let size = 7.0;
+        for column in major_lines(columns.start + 1, columns.end) {
+            let label = column.to_string();
+            let x = x_at(column) - text_width(&label, size) / 2.0;
+            text(&mut content, REGULAR, size, x, top + 4.0, &label);
+        }
+        for row in major_lines(rows.start + 1, rows.end) {
+            let label = row.to_string();
+            let x = left - 3.0 - text_width(&label, size);
+            text(
+                &mut content,
+                REGULAR,
+                size,
+                x,
+                y_at(row) - size * 0.35,
+                &label,
+            );
+        }
+
+        // Arrows pointing at the center lines of the design, under and right
+        // of the chart
+        let center_column = self.columns as f32 / 2.0;
+        if (columns.start as f32..=columns.end as f32).contains(&center_column) {
+            let x = left + (center_column - columns.start as f32) * CELL_SIZE;
+            content
+                .move_to(x, bottom - 2.0)
+                .line_to(x - 4.0, bottom - 9.0)
+                .line_to(x + 4.0, bottom - 9.0)
+                .close_path()
+                .fill_nonzero();
+        }
+        let center_row = self.rows as f32 / 2.0;
+        if (rows.start as f32..=rows.end as f32).contains(&center_row) {
+            let y = top - (center_row - rows.start as f32) * CELL_SIZE;
+            content
+                .move_to(right + 2.0, y)
+                .line_to(right + 9.0, y + 4.0)
+                .line_to(right + 9.0, y - 4.0)
+                .close_path()
+                .fill_nonzero();
+        }
+        compress(content)
+    }
+
+    fn legend_page(&self, entries: &[Palette]) -> Vec<u8> {
+        let (_, height) = self.page;
+        let mut content = Content::new();
+        let mut y = height - PAGE_MARGIN - 14.0;
+        text(&mut content, BOLD, 14.0, PAGE_MARGIN, y, "Legend");
+
+        let columns = [PAGE_MARGIN, PAGE_MARGIN + 60.0, PAGE_MARGIN + 160.0];
+        let stitches_right = PAGE_MARGIN + 320.0;
+        y -= HEADER_HEIGHT;
+        for (x, header) in columns.into_iter().zip(["Symbol", "Brand", "Code"]) {
+            text(&mut content, BOLD, 9.0, x, y, header);
+        }
+        let header = "Stitches";
+        let x = stitches_right - text_width(header, 9.0);
+        text(&mut content, BOLD, 9.0, x, y, header);
+
+        for entry in entries {
+            y -= LEGEND_ROW_HEIGHT;
+            let color = entry.color.rgb;
+            set_fill(&mut content, color);
+            content
+                .set_line_width(0.5)
+                .set_stroke_gray(0.0)
+                .rect(columns[0], y - 3.0, 16.0, 12.0)
+                .fill_nonzero_and_stroke();
+            if let Some(symbol) = self.symbols.get(&color) {
+                content.set_fill_gray(if color.is_dark() { 1.0 } else { 0.0 });
+                let x = columns[0] + (16.0 - text_width(symbol, 8.0)) / 2.0;
+                text(&mut content, REGULAR, 8.0, x, y, symbol);
+            }
+            content.set_fill_gray(0.0);
+            text(&mut content, REGULAR, 9.0, columns[1], y, entry.color.brand);
+            text(&mut content, REGULAR, 9.0, columns[2], y, &entry.color.name);
+            let stitches = entry.n_stitches.to_string();
+            let x = stitches_right - text_width(&stitches, 9.0);
+            text(&mut content, REGULAR, 9.0, x, y, &stitches);
+        }
+        compress(content)
+    }
+}
+
+/// Splits `cells` into pages of at most `per_page` cells, every page
+/// repeating the last `OVERLAP` cells of the previous one
+fn tiles(cells: u32, per_page: u32) -> Vec<Range<u32>> {
+    let per_page = per_page.max(OVERLAP + 1);
+    let mut tiles = Vec::new();
+    let mut start = 0;
+    loop {
+        let end = (start + per_page).min(cells);
+        tiles.push(start..end);
+        if end >= cells {
+            return tiles;
+        }
+        start = end - OVERLAP;
+    }
+}
+
+/// Every tenth line from `start` to `end` included
+fn major_lines(start: u32, end: u32) -> impl Iterator<Item = u32> {
+    let first = start.div_ceil(MAJOR_GRID) * MAJOR_GRID;
+    (first..=end).step_by(MAJOR_GRID as usize)
+}
+
+/// Lines of a page where the cells also printed on the previous or next
+/// page begin
+fn overlap_edges(tile: &Range<u32>, cells: u32) -> Vec<u32> {
+    let mut edges = Vec::new();
+    if tile.start > 0 {
+        edges.push(tile.start + OVERLAP);
+    }
+    if tile.end < cells {
+        edges.push(tile.end - OVERLAP);
+    }
+    edges
+}
+
+fn set_fill(content: &mut Content, color: RgbColor) {
+    content.set_fill_rgb(
+        color.red as f32 / 255.0,
+        color.green as f32 / 255.0,
+        color.blue as f32 / 255.0,
+    );
+}
+
+fn text(content: &mut Content, font: Name, size: f32, x: f32, y: f32, value: &str) {
+    content
+        .begin_text()
+        .set_font(font, size)
+        .next_line(x, y)
+        .show(Str(value.as_bytes()))
+        .end_text();
+}
+
+/// Width of `value` set in Helvetica
+fn text_width(value: &str, size: f32) -> f32 {
+    let thousandths: u32 = value
+        .chars()
+        .map(|c| match c {
+            ' '..='~' => HELVETICA_WIDTHS[c as usize - ' ' as usize] as u32,
+            _ => 556,
+        })
+        .sum();
+    thousandths as f32 * size / 1000.0
+}
+
+fn compress(content: Content) -> Vec<u8> {
+    compress_to_vec_zlib(&content.finish(), 6)
+}
+
+fn write_pdf(pages: &[Vec<u8>], width: f32, height: f32) -> Vec<u8> {
+    let catalog_id = Ref::new(1);
+    let tree_id = Ref::new(2);
+    let regular_id = Ref::new(3);
+    let bold_id = Ref::new(4);
+    let page_ids: Vec<(Ref, Ref)> = (0..pages.len() as i32)
+        .map(|index| (Ref::new(5 + 2 * index), Ref::new(6 + 2 * index)))
+        .collect();
+
+    let mut pdf = Pdf::new();
+    pdf.catalog(catalog_id).pages(tree_id);
+    pdf.pages(tree_id)
+        .kids(page_ids.iter().map(|&(page_id, _)| page_id))
+        .count(pages.len() as i32);
+    pdf.type1_font(regular_id).base_font(Name(b"Helvetica"));
+    pdf.type1_font(bold_id).base_font(Name(b"Helvetica-Bold"));
+    for (&(page_id, content_id), content) in page_ids.iter().zip(pages) {
+        let mut page = pdf.page(page_id);
+        page.media_box(Rect::new(0.0, 0.0, width, height))
+            .parent(tree_id)
+            .contents(content_id);
+        page.resources()
+            .fonts()
+            .pair(REGULAR, regular_id)
+            .pair(BOLD, bold_id);
+        page.finish();
+        pdf.stream(content_id, content).filter(Filter::FlateDecode);
+    }
+    pdf.finish()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::embroidery::canvas::CanvasConfig;
+    use image::{DynamicImage, ImageBuffer, Rgb};
+    use miniz_oxide::inflate::decompress_to_vec_zlib;
+    use std::io::Cursor;
+
+    fn canvas(width: u32, height: u32, n_cells_in_width: u32) -> Canvas {
+        let img = ImageBuffer::from_fn(width, height, |x, y| {
+            Rgb([(x * 7 % 256) as u8, (y * 5 % 256) as u8, 90])
+        });
+        let mut bytes = Vec::new();
+        DynamicImage::ImageRgb8(img)
+            .write_to(&mut Cursor::new(&mut bytes), image::ImageFormat::Png)
+            .unwrap();
+        let config = CanvasConfig::new(bytes, Some(n_cells_in_width), Some(6)).unwrap();
+        Canvas::new(config).unwrap()
+    }
+
+    fn count_pages(pdf: &[u8]) -> usize {
+        pdf.windows(12)
+            .filter(|window| window.starts_with(b"/Type /Page") && window[11] != b's')
+            .count()
+    }
+
+    /// Decompressed content of every page
+    fn page_contents(pdf: &[u8]) -> Vec<String> {
+        let mut contents = Vec::new();
+        let mut rest = pdf;
+        while let Some(start) = find(rest, b"stream\n") {
+            let data = &rest[start + 7..];
+            let end = find(data, b"\nendstream").unwrap();
+            let content = decompress_to_vec_zlib(&data[..end]).unwrap();
+            contents.push(String::from_utf8_lossy(&content).into_owned());
+            rest = &data[end + b"\nendstream".len()..];
+        }
+        contents
+    }
+
+    fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+        haystack
+            .windows(needle.len())
+            .position(|window| window == needle)
+    }
+
+    #[test]
+    fn it_splits_cells_into_overlapping_pages() {
+        assert_eq!(tiles(30, 50), vec![0..30]);
+        assert_eq!(tiles(120, 50), vec![0..50, 48..98, 96..120]);
+        assert_eq!(overlap_edges(&(48..98), 120), vec![50, 96]);
+        assert!(overlap_edges(&(0..30), 30).is_empty());
+        assert_eq!(
+            major_lines(48, 98).collect::<Vec<_>>(),
+            vec![50, 60, 70, 80, 90]
+        );
+        assert_eq!(major_lines(0, 20).collect::<Vec<_>>(), vec![0, 10, 20]);
+    }
+
+    #[test]
+    fn it_renders_single_page_chart() {
+        let canvas = canvas(40, 30, 40);
+        let pdf = render_pdf(&canvas, PageSize::A4);
+        assert!(pdf.starts_with(b"%PDF-"));
+        // Page map, chart and legend
+        assert_eq!(count_pages(&pdf), 3);
+
+        let contents = page_contents(&pdf);
+        assert!(contents[0].contains("(Page map)"));
+        assert!(contents[1].contains("(Page 2 of 3: columns 1-40, rows 1-30)"));
+        // Bold lines carry column and row numbers
+        assert!(contents[1].contains("(40)"));
+        assert!(contents[1].contains("(30)"));
+        assert!(contents[2].contains("(Legend)"));
+        for entry in canvas.get_thread_palette() {
+            assert!(contents[2].contains(&format!("({})", entry.color.name)));
+            assert!(contents[2].contains(&format!("({})", entry.n_stitches)));
+        }
+    }
+
+    #[test]
+    fn it_paginates_large_chart() {
+        let canvas = canvas(120, 80, 120);
+        let pdf = render_pdf(&canvas, PageSize::A4);
+        // 50 columns and 72 rows per A4 page, two of them repeated
+        assert_eq!(count_pages(&pdf), 1 + 3 * 2 + 1);
+        let contents = page_contents(&pdf);
+        assert!(contents[2].contains("(Page 3 of 8: columns 49-98, rows 1-72)"));
+        assert!(contents[6].contains("(Page 7 of 8: columns 97-120, rows 71-80)"));
+
+        let letter = render_pdf(&canvas, PageSize::Letter);
+        assert!(find(&letter, b"612 792").is_some());
+    }
+
+    #[test]
+    fn it_parses_page_size() {
+        assert_eq!("Letter".parse::<PageSize>(), Ok(PageSize::Letter));
+        assert_eq!(
+            "a3".parse::<PageSize>().unwrap_err(),
+            "Value should be one of: a4, letter"
+        );
+    }
+}
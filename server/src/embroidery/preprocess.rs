@@ -0,0 +1,393 @@
+use image::{DynamicImage, GenericImageView, RgbaImage};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::CanvasError;
+
+/// Share of the darkest and lightest pixels ignored by auto-levels
+const LEVELS_CLIP: f32 = 0.005;
+
+/// Clockwise rotation in quarter turns.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Rotation {
+    #[default]
+    None,
+    Quarter,
+    Half,
+    ThreeQuarters,
+}
+
+impl Rotation {
+    pub const ALL: [Rotation; 4] = [
+        Rotation::None,
+        Rotation::Quarter,
+        Rotation::Half,
+        Rotation::ThreeQuarters,
+    ];
+}
+
+impl fmt::Display for Rotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let degrees = match self {
+            Rotation::None => "0",
+            Rotation::Quarter => "90",
+            Rotation::Half => "180",
+            Rotation::ThreeQuarters => "270",
+        };
+        f.write_str(degrees)
+    }
+}
+
+impl FromStr for Rotation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Rotation::ALL
+            .into_iter()
+            .find(|rotation| rotation.to_string() == s.trim())
+            .ok_or_else(|| "Value should be one of: 0, 90, 180, 270".into())
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Mirror {
+    #[default]
+    None,
+    /// Flips left and right
+    Horizontal,
+    /// Flips top and bottom
+    Vertical,
+}
+
+impl Mirror {
+    pub const ALL: [Mirror; 3] = [Mirror::None, Mirror::Horizontal, Mirror::Vertical];
+}
+
+impl fmt::Display for Mirror {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Mirror::None => "none",
+            Mirror::Horizontal => "horizontal",
+            Mirror::Vertical => "vertical",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for Mirror {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Mirror::ALL
+            .into_iter()
+            .find(|mirror| mirror.to_string().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| {
+                let names: Vec<String> = Mirror::ALL.iter().map(|m| m.to_string()).collect();
+                format!("Value should be one of: {}", names.join(", "))
+            })
+    }
+}
+
+/// Rectangle of the uploaded image to keep, in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Crop {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl FromStr for Crop {
+    type Err = String;
+
+    /// Parses `x,y,width,height`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let values: Vec<u32> = s
+            .split(',')
+            .map(|value| value.trim().parse())
+            .collect::<Result<_, _>>()
+            .map_err(|_| "Value should be x,y,width,height".to_string())?;
+        match values[..] {
+            [x, y, width, height] if width > 0 && height > 0 => Ok(Crop {
+                x,
+                y,
+                width,
+                height,
+            }),
+            _ => Err("Value should be x,y,width,height".into()),
+        }
+    }
+}
+
+/// Edits applied to the uploaded image before it is turned into stitches:
+/// first the crop, then rotation and mirroring, then color adjustments.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Preprocess {
+    pub crop: Option<Crop>,
+    pub rotation: Rotation,
+    pub mirror: Mirror,
+    /// Stretches the tones so the image spans from black to white
+    pub auto_levels: bool,
+    /// Added to every channel, from -1 to 1
+    pub brightness: f32,
+    /// From -1 (flat gray) to 1 (doubled contrast)
+    pub contrast: f32,
+    /// From -1 (grayscale) to 1 (doubled saturation)
+    pub saturation: f32,
+    /// Values above 1 lighten mid-tones, below 1 darken them
+    pub gamma: f32,
+}
+
+impl Default for Preprocess {
+    fn default() -> Self {
+        Preprocess {
+            crop: None,
+            rotation: Rotation::None,
+            mirror: Mirror::None,
+            auto_levels: false,
+            brightness: 0.0,
+            contrast: 0.0,
+            saturation: 0.0,
+            gamma: 1.0,
+        }
+    }
+}
+
+impl Preprocess {
+    pub fn apply(&self, img: DynamicImage) -> Result<DynamicImage, CanvasError> {
+        let mut img = match self.crop {
+            Some(crop) => {
+                let (width, height) = img.dimensions();
+                let fits = crop.x.checked_add(crop.width).is_some_and(|x| x <= width)
+                    && crop.y.checked_add(crop.height).is_some_and(|y| y <= height);
+                if !fits {
+                    return Err(CanvasError::InvalidCrop);
+                }
+                img.crop_imm(crop.x, crop.y, crop.width, crop.height)
+            }
+            None => img,
+        };
+        img = match self.rotation {
+            Rotation::None => img,
+            Rotation::Quarter => img.rotate90(),
+            Rotation::Half => img.rotate180(),
+            Rotation::ThreeQuarters => img.rotate270(),
+        };
+        img = match self.mirror {
+            Mirror::None => img,
+            Mirror::Horizontal => img.fliph(),
+            Mirror::Vertical => img.flipv(),
+        };
+        if !self.adjusts_colors() {
+            return Ok(img);
+        }
+
+        let mut pixels = img.to_rgba8();
+        let levels = if self.auto_levels {
+            levels(&pixels)
+        } else {
+            (0.0, 1.0)
+        };
+        for pixel in pixels.pixels_mut() {
+            let mut rgb = [pixel[0], pixel[1], pixel[2]].map(|channel| channel as f32 / 255.0);
+            rgb = rgb.map(|value| (value - levels.0) / (levels.1 - levels.0));
+            rgb = rgb.map(|value| (value + self.brightness - 0.5) * (1.0 + self.contrast) + 0.5);
+            let luma = 0.2126 * rgb[0] + 0.7152 * rgb[1] + 0.0722 * rgb[2];
+            rgb = rgb.map(|value| luma + (value - luma) * (1.0 + self.saturation));
+            rgb = rgb.map(|value| value.clamp(0.0, 1.0).powf(1.0 / self.gamma));
+            for (channel, value) in rgb.into_iter().enumerate() {
+                pixel[channel] = (value * 255.0).round() as u8;
+            }
+        }
+        Ok(DynamicImage::ImageRgba8(pixels))
+    }
+
+    fn adjusts_colors(&self) -> bool {
+        self.auto_levels
+            || self.brightness != 0.0
+            || self.contrast != 0.0
+            || self.saturation != 0.0
+            || self.gamma != 1.0
+    }
+}
+
+/// Darkest and lightest luma of the visible pixels, ignoring outliers
+fn levels(pixels: &RgbaImage) -> (f32, f32) {
+    let mut histogram = [0u32; 256];
+    for pixel in pixels.pixels().filter(|pixel| pixel[3] > 0) {
+        let luma = 0.2126 * pixel[0] as f32 + 0.7152 * pixel[1] as f32 + 0.0722 * pixel[2] as f32;
+        histogram[luma.round() as usize] += 1;
+    }
+    let total: u32 = histogram.iter().sum();
+    let clip = (total as f32 * LEVELS_CLIP) as u32;
+    let low = percentile(&histogram, clip, 0..256);
+    let high = percentile(&histogram, clip, (0..256).rev());
+    if high <= low {
+        return (0.0, 1.0);
+    }
+    (low as f32 / 255.0, high as f32 / 255.0)
+}
+
+/// First luma in `order` passed by more than `clip` pixels
+fn percentile(histogram: &[u32; 256], clip: u32, order: impl Iterator<Item = usize>) -> usize {
+    let mut count = 0;
+    for luma in order {
+        count += histogram[luma];
+        if count > clip {
+            return luma;
+        }
+    }
+    0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use image::{ImageBuffer, Rgba};
+
+    const RED: Rgba<u8> = Rgba([200, 40, 40, 255]);
+    const BLUE: Rgba<u8> = Rgba([40, 40, 200, 255]);
+
+    /// 4×2 image, red but for a blue top-left corner
+    fn image() -> DynamicImage {
+        DynamicImage::ImageRgba8(ImageBuffer::from_fn(4, 2, |x, y| {
+            if x == 0 && y == 0 {
+                BLUE
+            } else {
+                RED
+            }
+        }))
+    }
+
+    fn pixel(img: &DynamicImage, x: u32, y: u32) -> Rgba<u8> {
+        img.to_rgba8().get_pixel(x, y).to_owned()
+    }
+
+    #[test]
+    fn it_leaves_image_unchanged_by_default() {
+        let img = Preprocess::default().apply(image()).unwrap();
+        assert_eq!(img.to_rgba8(), image().to_rgba8());
+    }
+
+    #[test]
+    fn it_crops() {
+        let crop = Crop {
+            x: 0,
+            y: 0,
+            width: 2,
+            height: 1,
+        };
+        let preprocess = Preprocess {
+            crop: Some(crop),
+            ..Default::default()
+        };
+        let img = preprocess.apply(image()).unwrap();
+        assert_eq!(img.dimensions(), (2, 1));
+        assert_eq!(pixel(&img, 0, 0), BLUE);
+
+        let preprocess = Preprocess {
+            crop: Some(Crop { x: 3, ..crop }),
+            ..Default::default()
+        };
+        assert!(matches!(
+            preprocess.apply(image()),
+            Err(CanvasError::InvalidCrop)
+        ));
+    }
+
+    #[test]
+    fn it_rotates_and_mirrors() {
+        let rotated = Preprocess {
+            rotation: Rotation::Quarter,
+            ..Default::default()
+        }
+        .apply(image())
+        .unwrap();
+        assert_eq!(rotated.dimensions(), (2, 4));
+        assert_eq!(pixel(&rotated, 1, 0), BLUE);
+
+        let mirrored = Preprocess {
+            mirror: Mirror::Horizontal,
+            ..Default::default()
+        }
+        .apply(image())
+        .unwrap();
+        assert_eq!(pixel(&mirrored, 3, 0), BLUE);
+
+        let flipped = Preprocess {
+            rotation: Rotation::Half,
+            mirror: Mirror::Vertical,
+            ..Default::default()
+        }
+        .apply(image())
+        .unwrap();
+        assert_eq!(pixel(&flipped, 3, 0), BLUE);
+    }
+
+    #[test]
+    fn it_adjusts_colors() {
+        let adjust = |preprocess: Preprocess| pixel(&preprocess.apply(image()).unwrap(), 1, 0);
+
+        let brighter = adjust(Preprocess {
+            brightness: 0.2,
+            ..Default::default()
+        });
+        assert_eq!(brighter, Rgba([251, 91, 91, 255]));
+
+        let flat = adjust(Preprocess {
+            contrast: -1.0,
+            ..Default::default()
+        });
+        assert_eq!(flat, Rgba([128, 128, 128, 255]));
+
+        let gray = adjust(Preprocess {
+            saturation: -1.0,
+            ..Default::default()
+        });
+        assert_eq!(gray[0], gray[1]);
+        assert_eq!(gray[1], gray[2]);
+
+        let lighter = adjust(Preprocess {
+            gamma: 2.0,
+            ..Default::default()
+        });
+        assert!(lighter[1] > RED[1]);
+        assert_eq!(lighter[3], 255);
+    }
+
+    #[test]
+    fn it_stretches_levels() {
+        let img = DynamicImage::ImageRgba8(ImageBuffer::from_fn(10, 1, |x, _| {
+            let value = 100 + x as u8 * 5;
+            Rgba([value, value, value, 255])
+        }));
+        let stretched = Preprocess {
+            auto_levels: true,
+            ..Default::default()
+        }
+        .apply(img)
+        .unwrap();
+        assert_eq!(pixel(&stretched, 0, 0), Rgba([0, 0, 0, 255]));
+        assert_eq!(pixel(&stretched, 9, 0), Rgba([255, 255, 255, 255]));
+    }
+
+    #[test]
+    fn it_parses_options() {
+        assert_eq!("270".parse::<Rotation>(), Ok(Rotation::ThreeQuarters));
+        assert!("45".parse::<Rotation>().is_err());
+        assert_eq!("Vertical".parse::<Mirror>(), Ok(Mirror::Vertical));
+        assert_eq!(
+            "1, 2,30,40".parse::<Crop>(),
+            Ok(Crop {
+                x: 1,
+                y: 2,
+                width: 30,
+                height: 40
+            })
+        );
+        assert_eq!(
+            "1,2,0,4".parse::<Crop>().unwrap_err(),
+            "Value should be x,y,width,height"
+        );
+    }
+}
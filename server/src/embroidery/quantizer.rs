@@ -0,0 +1,350 @@
+use lab::Lab;
+use palette_extract::{get_palette_with_options, MaxColors, PixelEncoding, PixelFilter, Quality};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::embroidery::colors::RgbColor;
+use crate::embroidery::index::LabIndex;
+use crate::embroidery::metric::ColorMetric;
+
+/// Bits kept per channel when pixels are grouped before clustering
+const HISTOGRAM_BITS: u32 = 5;
+
+/// Reduces the colors of an image to a small set of representative ones.
+pub trait Quantizer {
+    fn quantize(&self, pixels: &[RgbColor], n_colors: u8) -> Vec<RgbColor>;
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum QuantizerKind {
+    #[default]
+    KMeans,
+    PaletteExtract,
+}
+
+impl QuantizerKind {
+    pub const ALL: [QuantizerKind; 2] = [QuantizerKind::KMeans, QuantizerKind::PaletteExtract];
+}
+
+impl Quantizer for QuantizerKind {
+    fn quantize(&self, pixels: &[RgbColor], n_colors: u8) -> Vec<RgbColor> {
+        match self {
+            QuantizerKind::KMeans => KMeans::default().quantize(pixels, n_colors),
+            QuantizerKind::PaletteExtract => PaletteExtract.quantize(pixels, n_colors),
+        }
+    }
+}
+
+impl fmt::Display for QuantizerKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            QuantizerKind::KMeans => "kmeans",
+            QuantizerKind::PaletteExtract => "palette_extract",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for QuantizerKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        QuantizerKind::ALL
+            .into_iter()
+            .find(|kind| kind.to_string().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| {
+                let names: Vec<String> = QuantizerKind::ALL.iter().map(|k| k.to_string()).collect();
+                format!("Value should be one of: {}", names.join(", "))
+            })
+    }
+}
+
+/// k-means++ clustering in CIELAB. Seeding is driven by `seed`, so the same
+/// image always gives the same palette.
+#[derive(Debug, Clone, Copy)]
+pub struct KMeans {
+    pub seed: u64,
+    pub max_iterations: usize,
+}
+
+impl Default for KMeans {
+    fn default() -> Self {
+        KMeans {
+            seed: 0x5EED,
+            max_iterations: 50,
+        }
+    }
+}
+
+impl Quantizer for KMeans {
+    /// Returns exactly `n_colors` centroids, repeating some of them if the
+    /// image has fewer distinct colors.
+    fn quantize(&self, pixels: &[RgbColor], n_colors: u8) -> Vec<RgbColor> {
+        let points = histogram(pixels);
+        if points.is_empty() || n_colors == 0 {
+            return Vec::new();
+        }
+
+        let mut random = SplitMix64(self.seed);
+        let mut centroids = seed_centroids(&points, n_colors as usize, &mut random);
+        let mut assignments: Vec<usize> = vec![usize::MAX; points.len()];
+
+        for _ in 0..self.max_iterations {
+            let index = LabIndex::new(centroids.clone(), ColorMetric::Cie76);
+            let mut changed = false;
+            for (point, assignment) in points.iter().zip(assignments.iter_mut()) {
+                let closest = index.closest(point.lab).unwrap_or(0);
+                if *assignment != closest {
+                    *assignment = closest;
+                    changed = true;
+                }
+            }
+            if !changed {
+                break;
+            }
+
+            let mut sums: Vec<([f64; 3], f64)> = vec![([0.0; 3], 0.0); centroids.len()];
+            for (point, &assignment) in points.iter().zip(&assignments) {
+                let (sum, weight) = &mut sums[assignment];
+                sum[0] += point.lab.l as f64 * point.weight;
+                sum[1] += point.lab.a as f64 * point.weight;
+                sum[2] += point.lab.b as f64 * point.weight;
+                *weight += point.weight;
+            }
+            for (i, (sum, weight)) in sums.into_iter().enumerate() {
+                if weight > 0.0 {
+                    centroids[i] = Lab {
+                        l: (sum[0] / weight) as f32,
+                        a: (sum[1] / weight) as f32,
+                        b: (sum[2] / weight) as f32,
+                    };
+                } else {
+                    // An empty cluster takes over the point worst served by
+                    // its own centroid
+                    let (farthest, _) = points
+                        .iter()
+                        .zip(&assignments)
+                        .enumerate()
+                        .map(|(j, (point, &a))| {
+                            (
+                                j,
+                                point.weight * point.lab.squared_distance(&centroids[a]) as f64,
+                            )
+                        })
+                        .max_by(|x, y| x.1.total_cmp(&y.1))
+                        .unwrap_or((0, 0.0));
+                    centroids[i] = points[farthest].lab;
+                    assignments[farthest] = i;
+                }
+            }
+        }
+
+        centroids
+            .iter()
+            .map(|lab| RgbColor::from(image::Rgb(lab.to_rgb())))
+            .collect()
+    }
+}
+
+/// Median cut implementation of the `palette_extract` crate. It does not
+/// always return the requested number of colors.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PaletteExtract;
+
+impl Quantizer for PaletteExtract {
+    fn quantize(&self, pixels: &[RgbColor], n_colors: u8) -> Vec<RgbColor> {
+        // palette_extract library gives wrong number of colors depending on input
+        // to fix this, modification done below
+        let mut n_colors: u8 = n_colors;
+        if n_colors > 7 {
+            n_colors = n_colors.saturating_add(1);
+        } else if n_colors < 4 {
+            n_colors = n_colors.saturating_sub(1);
+        }
+
+        let bytes: Vec<u8> = pixels
+            .iter()
+            .flat_map(|&color| <[u8; 3]>::from(color))
+            .collect();
+        get_palette_with_options(
+            &bytes,
+            PixelEncoding::Rgb,
+            Quality::new(10),
+            MaxColors::new(n_colors),
+            PixelFilter::None,
+        )
+        .iter()
+        .map(|val| RgbColor {
+            red: val.r,
+            green: val.g,
+            blue: val.b,
+        })
+        .collect()
+    }
+}
+
+struct WeightedPoint {
+    lab: Lab,
+    weight: f64,
+}
+
+/// Groups pixels into bins of similar colors, each represented by the mean
+/// color of its pixels and weighted by their number.
+fn histogram(pixels: &[RgbColor]) -> Vec<WeightedPoint> {
+    let shift = 8 - HISTOGRAM_BITS;
+    let mut bins: Vec<([u64; 3], u64)> = vec![([0; 3], 0); 1 << (3 * HISTOGRAM_BITS)];
+    for color in pixels {
+        let key = ((color.red as usize >> shift) << (2 * HISTOGRAM_BITS))
+            | ((color.green as usize >> shift) << HISTOGRAM_BITS)
+            | (color.blue as usize >> shift);
+        let (sum, count) = &mut bins[key];
+        sum[0] += color.red as u64;
+        sum[1] += color.green as u64;
+        sum[2] += color.blue as u64;
+        *count += 1;
+    }
+
+    bins.into_iter()
+        .filter(|&(_, count)| count > 0)
+        .map(|(sum, count)| {
+            let mean = sum.map(|channel| ((channel as f64 / count as f64).round()) as u8);
+            WeightedPoint {
+                lab: Lab::from_rgb(&mean),
+                weight: count as f64,
+            }
+        })
+        .collect()
+}
+
+/// k-means++ seeding: every next centroid is drawn with probability
+/// proportional to its weighted squared distance from the chosen ones.
+fn seed_centroids(points: &[WeightedPoint], n_colors: usize, random: &mut SplitMix64) -> Vec<Lab> {
+    let weights: Vec<f64> = points.iter().map(|point| point.weight).collect();
+    let mut centroids = vec![points[random.choose(&weights)].lab];
+    let mut distances: Vec<f64> = points
+        .iter()
+        .map(|point| point.lab.squared_distance(&centroids[0]) as f64)
+        .collect();
+
+    while centroids.len() < n_colors {
+        let scores: Vec<f64> = points
+            .iter()
+            .zip(&distances)
+            .map(|(point, distance)| point.weight * distance)
+            .collect();
+        let next = if scores.iter().any(|&score| score > 0.0) {
+            points[random.choose(&scores)].lab
+        } else {
+            // Fewer distinct colors than requested
+            points[random.choose(&weights)].lab
+        };
+        for (point, distance) in points.iter().zip(distances.iter_mut()) {
+            *distance = distance.min(point.lab.squared_distance(&next) as f64);
+        }
+        centroids.push(next);
+    }
+    centroids
+}
+
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Picks an index with probability proportional to its weight
+    fn choose(&mut self, weights: &[f64]) -> usize {
+        let total: f64 = weights.iter().sum();
+        let mut target = self.next_f64() * total;
+        for (index, &weight) in weights.iter().enumerate() {
+            if target < weight {
+                return index;
+            }
+            target -= weight;
+        }
+        weights
+            .iter()
+            .rposition(|&weight| weight > 0.0)
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn color(red: u8, green: u8, blue: u8) -> RgbColor {
+        RgbColor { red, green, blue }
+    }
+
+    fn gradient() -> Vec<RgbColor> {
+        (0..64u32)
+            .flat_map(|x| (0..64u32).map(move |y| color((x * 4) as u8, (y * 4) as u8, 128)))
+            .collect()
+    }
+
+    #[test]
+    fn it_returns_exact_number_of_colors() {
+        let pixels = gradient();
+        for n_colors in [3, 5, 8, 20, 64] {
+            assert_eq!(
+                KMeans::default().quantize(&pixels, n_colors).len(),
+                n_colors as usize
+            );
+        }
+    }
+
+    #[test]
+    fn it_finds_clusters() {
+        let mut pixels = vec![color(200, 10, 10); 50];
+        pixels.extend(vec![color(10, 200, 10); 30]);
+        pixels.extend(vec![color(10, 10, 200); 20]);
+
+        let mut palette = KMeans::default().quantize(&pixels, 3);
+        palette.sort_by_key(|color| (color.red, color.green, color.blue));
+        assert_eq!(
+            palette,
+            vec![color(10, 10, 200), color(10, 200, 10), color(200, 10, 10)]
+        );
+    }
+
+    #[test]
+    fn it_is_deterministic() {
+        let pixels = gradient();
+        let quantizer = KMeans::default();
+        assert_eq!(
+            quantizer.quantize(&pixels, 12),
+            quantizer.quantize(&pixels, 12)
+        );
+    }
+
+    #[test]
+    fn it_repeats_colors_of_plain_image() {
+        let pixels = vec![color(0, 0, 0), color(255, 255, 255)];
+        let palette = KMeans::default().quantize(&pixels, 4);
+        assert_eq!(palette.len(), 4);
+        assert!(palette.contains(&color(0, 0, 0)));
+        assert!(palette.contains(&color(255, 255, 255)));
+    }
+
+    #[test]
+    fn it_parses_quantizer_kind() {
+        assert_eq!(
+            "Palette_Extract".parse::<QuantizerKind>(),
+            Ok(QuantizerKind::PaletteExtract)
+        );
+        assert_eq!(
+            "median".parse::<QuantizerKind>().unwrap_err(),
+            "Value should be one of: kmeans, palette_extract"
+        );
+    }
+}
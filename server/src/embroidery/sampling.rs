@@ -0,0 +1,245 @@
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImageView, ImageBuffer, Rgba, RgbaImage};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+/// How the color of a cell is taken from the pixels it covers.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Sampling {
+    /// CatmullRom resize in sRGB space
+    #[default]
+    CatmullRom,
+    /// Mean of the cell pixels in linear light
+    Box,
+    /// Per-channel median of the cell pixels
+    Median,
+    /// Mean of the most common group of similar colors in the cell
+    Mode,
+}
+
+impl Sampling {
+    pub const ALL: [Sampling; 4] = [
+        Sampling::CatmullRom,
+        Sampling::Box,
+        Sampling::Median,
+        Sampling::Mode,
+    ];
+
+    /// Samples `img` into one pixel per cell of a `columns` × `rows` grid.
+    pub fn sample(&self, img: &DynamicImage, columns: u32, rows: u32) -> DynamicImage {
+        if *self == Sampling::CatmullRom {
+            return img.resize_exact(columns, rows, FilterType::CatmullRom);
+        }
+        let (width, height) = img.dimensions();
+        let (columns, rows) = (columns.max(1), rows.max(1));
+        let pixels = img.to_rgba8();
+
+        let cells: RgbaImage = ImageBuffer::from_fn(columns, rows, |column, row| {
+            let (x_start, x_end) = span(column, columns, width);
+            let (y_start, y_end) = span(row, rows, height);
+            let cell: Vec<Rgba<u8>> = (y_start..y_end)
+                .flat_map(|y| (x_start..x_end).map(move |x| (x, y)))
+                .map(|(x, y)| *pixels.get_pixel(x, y))
+                .collect();
+            match self {
+                Sampling::Box => box_average(&cell),
+                Sampling::Median => median(&cell),
+                _ => mode(&cell),
+            }
+        });
+        DynamicImage::ImageRgba8(cells)
+    }
+}
+
+impl fmt::Display for Sampling {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Sampling::CatmullRom => "catmull_rom",
+            Sampling::Box => "box",
+            Sampling::Median => "median",
+            Sampling::Mode => "mode",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for Sampling {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Sampling::ALL
+            .into_iter()
+            .find(|sampling| sampling.to_string().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| {
+                let names: Vec<String> = Sampling::ALL.iter().map(|s| s.to_string()).collect();
+                format!("Value should be one of: {}", names.join(", "))
+            })
+    }
+}
+
+/// Source pixels `start..end` covered by cell `index` of `cells` spread
+/// over `size` pixels
+fn span(index: u32, cells: u32, size: u32) -> (u32, u32) {
+    let start = (index as u64 * size as u64 / cells as u64) as u32;
+    let end = ((index as u64 + 1) * size as u64 / cells as u64) as u32;
+    (start, end.max(start + 1).min(size))
+}
+
+fn mean_alpha(cell: &[Rgba<u8>]) -> u8 {
+    let sum: u32 = cell.iter().map(|pixel| pixel[3] as u32).sum();
+    (sum as f32 / cell.len() as f32).round() as u8
+}
+
+/// Pixels that are not fully transparent, or all of them when none is
+fn visible(cell: &[Rgba<u8>]) -> Vec<Rgba<u8>> {
+    let visible: Vec<Rgba<u8>> = cell.iter().copied().filter(|pixel| pixel[3] > 0).collect();
+    if visible.is_empty() {
+        cell.to_vec()
+    } else {
+        visible
+    }
+}
+
+fn to_linear(channel: u8) -> f32 {
+    static LINEAR: OnceLock<[f32; 256]> = OnceLock::new();
+    LINEAR.get_or_init(|| {
+        std::array::from_fn(|channel| {
+            let value = channel as f32 / 255.0;
+            if value <= 0.04045 {
+                value / 12.92
+            } else {
+                ((value + 0.055) / 1.055).powf(2.4)
+            }
+        })
+    })[channel as usize]
+}
+
+fn to_srgb(value: f32) -> u8 {
+    let value = value.clamp(0.0, 1.0);
+    let encoded = if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    };
+    (encoded * 255.0).round() as u8
+}
+
+/// Alpha-weighted mean in linear light, so edges between two colors do not
+/// come out darker than either
+fn box_average(cell: &[Rgba<u8>]) -> Rgba<u8> {
+    let mut sum = [0.0f32; 3];
+    let mut weight = 0.0;
+    for pixel in cell {
+        let alpha = (pixel[3] as f32 / 255.0).max(f32::EPSILON);
+        for (channel, sum) in sum.iter_mut().enumerate() {
+            *sum += to_linear(pixel[channel]) * alpha;
+        }
+        weight += alpha;
+    }
+    let [red, green, blue] = sum.map(|sum| to_srgb(sum / weight));
+    Rgba([red, green, blue, mean_alpha(cell)])
+}
+
+fn median(cell: &[Rgba<u8>]) -> Rgba<u8> {
+    let visible = visible(cell);
+    let channel = |index: usize| {
+        let mut values: Vec<u8> = visible.iter().map(|pixel| pixel[index]).collect();
+        values.sort_unstable();
+        values[values.len() / 2]
+    };
+    Rgba([channel(0), channel(1), channel(2), mean_alpha(cell)])
+}
+
+fn mode(cell: &[Rgba<u8>]) -> Rgba<u8> {
+    let mut groups: HashMap<[u8; 3], ([u32; 3], u32)> = HashMap::new();
+    for pixel in visible(cell) {
+        let (sum, count) = groups
+            .entry([pixel[0] >> 4, pixel[1] >> 4, pixel[2] >> 4])
+            .or_insert(([0; 3], 0));
+        for (channel, sum) in sum.iter_mut().enumerate() {
+            *sum += pixel[channel] as u32;
+        }
+        *count += 1;
+    }
+    let (sum, count) = groups
+        .into_values()
+        .max_by_key(|&(sum, count)| (count, sum))
+        .unwrap_or(([0; 3], 1));
+    let [red, green, blue] = sum.map(|sum| (sum as f32 / count as f32).round() as u8);
+    Rgba([red, green, blue, mean_alpha(cell)])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const RED: Rgba<u8> = Rgba([255, 0, 0, 255]);
+    const BLUE: Rgba<u8> = Rgba([0, 0, 255, 255]);
+
+    /// Two cells wide image, the left one with a blue stripe in red
+    fn image() -> DynamicImage {
+        DynamicImage::ImageRgba8(ImageBuffer::from_fn(8, 4, |x, _| match x {
+            1 | 4..=7 => BLUE,
+            _ => RED,
+        }))
+    }
+
+    #[test]
+    fn it_samples_exact_cells() {
+        for sampling in [Sampling::Box, Sampling::Median, Sampling::Mode] {
+            let cells = sampling.sample(&image(), 2, 1).to_rgba8();
+            assert_eq!(cells.dimensions(), (2, 1));
+            // No ringing from the left cell bleeds into the right one
+            assert_eq!(*cells.get_pixel(1, 0), BLUE, "{sampling}");
+        }
+    }
+
+    #[test]
+    fn it_takes_dominant_color() {
+        let cells = Sampling::Mode.sample(&image(), 2, 1).to_rgba8();
+        assert_eq!(*cells.get_pixel(0, 0), RED);
+        let cells = Sampling::Median.sample(&image(), 2, 1).to_rgba8();
+        assert_eq!(*cells.get_pixel(0, 0), RED);
+    }
+
+    #[test]
+    fn it_averages_in_linear_light() {
+        let img = DynamicImage::ImageRgba8(ImageBuffer::from_fn(2, 1, |x, _| {
+            if x == 0 {
+                Rgba([0, 0, 0, 255])
+            } else {
+                Rgba([255, 255, 255, 255])
+            }
+        }));
+        let cells = Sampling::Box.sample(&img, 1, 1).to_rgba8();
+        // Half the light is 188 in sRGB, not 128
+        assert_eq!(cells.get_pixel(0, 0)[0], 188);
+    }
+
+    #[test]
+    fn it_keeps_transparency() {
+        let img = DynamicImage::ImageRgba8(ImageBuffer::from_fn(4, 2, |x, _| {
+            if x < 2 {
+                Rgba([0, 0, 0, 0])
+            } else {
+                RED
+            }
+        }));
+        for sampling in [Sampling::Box, Sampling::Median, Sampling::Mode] {
+            let cells = sampling.sample(&img, 2, 1).to_rgba8();
+            assert_eq!(cells.get_pixel(0, 0)[3], 0);
+            assert_eq!(*cells.get_pixel(1, 0), RED);
+        }
+    }
+
+    #[test]
+    fn it_parses_sampling() {
+        assert_eq!("Median".parse::<Sampling>(), Ok(Sampling::Median));
+        assert_eq!(
+            "lanczos".parse::<Sampling>().unwrap_err(),
+            "Value should be one of: catmull_rom, box, median, mode"
+        );
+    }
+}
@@ -0,0 +1,168 @@
+use serde::{Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+pub const CENTIMETERS_PER_INCH: f32 = 2.54;
+/// Largest number of cells on either side of a canvas
+pub const MAX_CELLS: u32 = 1000;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Unit {
+    #[default]
+    Inches,
+    Centimeters,
+}
+
+impl Unit {
+    pub const ALL: [Unit; 2] = [Unit::Inches, Unit::Centimeters];
+
+    pub fn to_inches(&self, length: f32) -> f32 {
+        match self {
+            Unit::Inches => length,
+            Unit::Centimeters => length / CENTIMETERS_PER_INCH,
+        }
+    }
+
+    pub fn from_inches(&self, inches: f32) -> f32 {
+        match self {
+            Unit::Inches => inches,
+            Unit::Centimeters => inches * CENTIMETERS_PER_INCH,
+        }
+    }
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Unit::Inches => f.write_str("in"),
+            Unit::Centimeters => f.write_str("cm"),
+        }
+    }
+}
+
+impl FromStr for Unit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Unit::ALL
+            .into_iter()
+            .find(|unit| unit.to_string().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| "Value should be one of: in, cm".into())
+    }
+}
+
+impl Serialize for Unit {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+/// Fabric the pattern is stitched on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fabric {
+    /// Stitches per inch, e.g. 14 for 14-count Aida
+    pub count: f32,
+    pub unit: Unit,
+    /// Bare fabric left around the design on every side, in `unit`
+    pub margin: f32,
+}
+
+impl Default for Fabric {
+    fn default() -> Self {
+        Fabric {
+            count: 14.0,
+            unit: Unit::Inches,
+            margin: 3.0,
+        }
+    }
+}
+
+impl Fabric {
+    /// Number of stitches covering `length` in `unit`
+    pub fn stitches(&self, length: f32) -> f32 {
+        self.unit.to_inches(length) * self.count
+    }
+
+    /// Length in `unit` covered by `stitches`
+    pub fn length(&self, stitches: f32) -> f32 {
+        self.unit.from_inches(stitches / self.count)
+    }
+
+    /// Finished and fabric size of a grid of `columns` × `rows` cells, each
+    /// `cell_aspect_ratio` times as tall as it is wide
+    pub fn dimensions(&self, columns: u32, rows: u32, cell_aspect_ratio: f32) -> Dimensions {
+        let finished_width = self.length(columns as f32);
+        let finished_height = self.length(rows as f32 * cell_aspect_ratio);
+        Dimensions {
+            columns,
+            rows,
+            unit: self.unit,
+            finished_width,
+            finished_height,
+            fabric_width: finished_width + 2.0 * self.margin,
+            fabric_height: finished_height + 2.0 * self.margin,
+        }
+    }
+}
+
+/// Physical size the design should fit in, in the unit of the fabric.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct TargetSize {
+    pub width: Option<f32>,
+    pub height: Option<f32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Dimensions {
+    pub columns: u32,
+    pub rows: u32,
+    pub unit: Unit,
+    pub finished_width: f32,
+    pub finished_height: f32,
+    pub fabric_width: f32,
+    pub fabric_height: f32,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_measures_fabric() {
+        let fabric = Fabric::default();
+        assert_eq!(fabric.stitches(8.0), 112.0);
+
+        let dimensions = fabric.dimensions(112, 70, 1.0);
+        assert_eq!(dimensions.finished_width, 8.0);
+        assert_eq!(dimensions.finished_height, 5.0);
+        assert_eq!(dimensions.fabric_width, 14.0);
+        assert_eq!(dimensions.fabric_height, 11.0);
+    }
+
+    #[test]
+    fn it_converts_centimeters() {
+        let fabric = Fabric {
+            count: 10.0,
+            unit: Unit::Centimeters,
+            margin: 5.0,
+        };
+        assert!((fabric.stitches(25.4) - 100.0).abs() < 1e-3);
+        let dimensions = fabric.dimensions(100, 50, 2.0);
+        assert!((dimensions.finished_width - 25.4).abs() < 1e-3);
+        assert!((dimensions.finished_height - 25.4).abs() < 1e-3);
+        assert!((dimensions.fabric_width - 35.4).abs() < 1e-3);
+    }
+
+    #[test]
+    fn it_parses_unit() {
+        assert_eq!("CM".parse::<Unit>(), Ok(Unit::Centimeters));
+        assert_eq!(
+            "mm".parse::<Unit>().unwrap_err(),
+            "Value should be one of: in, cm"
+        );
+    }
+}
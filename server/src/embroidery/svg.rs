@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::embroidery::canvas::Canvas;
+use crate::embroidery::colors::RgbColor;
+
+/// Side of a cell, in user units
+const CELL_SIZE: u32 = 10;
+/// Room for the coordinates around the chart
+const LABEL_SIZE: u32 = 24;
+/// Cells between bold grid lines
+const MAJOR_GRID: u32 = 10;
+const FONT_FAMILY: &str = "Helvetica, Arial, sans-serif";
+
+/// Renders `canvas` as an SVG chart: a rectangle for every stitched cell,
+/// optionally with its symbol, a grid in bold every ten cells and the
+/// numbers of the bold lines.
+pub fn render_svg(canvas: &Canvas, show_symbols: bool) -> String {
+    let rows = canvas.embroidery.len() as u32;
+    let columns = canvas.embroidery.first().map_or(0, |row| row.len()) as u32;
+    let width = columns * CELL_SIZE + 2 * LABEL_SIZE;
+    let height = rows * CELL_SIZE + 2 * LABEL_SIZE;
+    let (left, top) = (LABEL_SIZE, LABEL_SIZE);
+    let (right, bottom) = (left + columns * CELL_SIZE, top + rows * CELL_SIZE);
+    let x_at = |column: u32| left + column * CELL_SIZE;
+    let y_at = |row: u32| top + row * CELL_SIZE;
+
+    // Writing to a String cannot fail
+    let mut svg = String::new();
+    let _ = writeln!(svg, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}">"#
+    );
+    let _ = writeln!(
+        svg,
+        r##"<rect id="fabric" width="{width}" height="{height}" fill="#FFFFFF"/>"##
+    );
+
+    let _ = writeln!(svg, r#"<g id="cells" shape-rendering="crispEdges">"#);
+    for (row, colors) in (0..).zip(&canvas.embroidery) {
+        for (column, color) in (0..).zip(colors) {
+            if let Some(color) = color {
+                let _ = writeln!(
+                    svg,
+                    r#"<rect x="{}" y="{}" width="{CELL_SIZE}" height="{CELL_SIZE}" fill="{}"/>"#,
+                    x_at(column),
+                    y_at(row),
+                    color.to_hex()
+                );
+            }
+        }
+    }
+    let _ = writeln!(svg, "</g>");
+
+    if show_symbols {
+        let symbols: HashMap<RgbColor, String> = canvas
+            .get_thread_palette()
+            .into_iter()
+            .map(|entry| (entry.color.rgb, entry.symbol))
+            .collect();
+        let _ = writeln!(
+            svg,
+            r#"<g id="symbols" font-family="{FONT_FAMILY}" font-size="{}" text-anchor="middle" dominant-baseline="central">"#,
+            CELL_SIZE as f32 * 0.65
+        );
+        let center = CELL_SIZE as f32 / 2.0;
+        for (row, colors) in (0..).zip(&canvas.embroidery) {
+            for (column, color) in (0..).zip(colors) {
+                let Some((color, symbol)) =
+                    color.and_then(|color| symbols.get(&color).map(|symbol| (color, symbol)))
+                else {
+                    continue;
+                };
+                let fill = if color.is_dark() {
+                    "#FFFFFF"
+                } else {
+                    "#000000"
+                };
+                let _ = writeln!(
+                    svg,
+                    r#"<text x="{}" y="{}" fill="{fill}">{}</text>"#,
+                    x_at(column) as f32 + center,
+                    y_at(row) as f32 + center,
+                    escape(symbol)
+                );
+            }
+        }
+        let _ = writeln!(svg, "</g>");
+    }
+
+    // Thin lines around every cell, bold ones every ten cells and on the
+    // edges drawn over them
+    let vertical = |path: &mut String, column: u32| {
+        let _ = write!(path, "M{} {}V{}", x_at(column), top, bottom);
+    };
+    let horizontal = |path: &mut String, row: u32| {
+        let _ = write!(path, "M{} {}H{}", left, y_at(row), right);
+    };
+    let mut minor = String::new();
+    (0..=columns).for_each(|column| vertical(&mut minor, column));
+    (0..=rows).for_each(|row| horizontal(&mut minor, row));
+    let mut major = String::new();
+    for column in (0..=columns).step_by(MAJOR_GRID as usize).chain([columns]) {
+        vertical(&mut major, column);
+    }
+    for row in (0..=rows).step_by(MAJOR_GRID as usize).chain([rows]) {
+        horizontal(&mut major, row);
+    }
+    let _ = writeln!(svg, r#"<g id="grid" fill="none">"#);
+    let _ = writeln!(
+        svg,
+        r##"<path d="{minor}" stroke="#808080" stroke-width="0.25"/>"##
+    );
+    let _ = writeln!(
+        svg,
+        r##"<path d="{major}" stroke="#000000" stroke-width="1"/>"##
+    );
+    let _ = writeln!(svg, "</g>");
+
+    let _ = writeln!(
+        svg,
+        r##"<g id="coordinates" font-family="{FONT_FAMILY}" font-size="7" fill="#000000">"##
+    );
+    for column in (MAJOR_GRID..=columns).step_by(MAJOR_GRID as usize) {
+        let _ = writeln!(
+            svg,
+            r#"<text x="{}" y="{}" text-anchor="middle">{column}</text>"#,
+            x_at(column),
+            top - 4
+        );
+    }
+    for row in (MAJOR_GRID..=rows).step_by(MAJOR_GRID as usize) {
+        let _ = writeln!(
+            svg,
+            r#"<text x="{}" y="{}" text-anchor="end" dominant-baseline="central">{row}</text>"#,
+            left - 3,
+            y_at(row)
+        );
+    }
+    let _ = writeln!(svg, "</g>");
+    let _ = writeln!(svg, "</svg>");
+    svg
+}
+
+/// Escapes `text` for XML text and attribute values.
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::embroidery::canvas::CanvasConfig;
+    use image::{DynamicImage, ImageBuffer, Rgba};
+    use roxmltree::{Document, Node};
+    use std::io::Cursor;
+
+    /// 30x20 cells, the top-left 10x5 cells transparent
+    fn canvas() -> Canvas {
+        let img = ImageBuffer::from_fn(30, 20, |x, y| {
+            if x < 10 && y < 5 {
+                Rgba([0, 0, 0, 0])
+            } else {
+                Rgba([(x * 8) as u8, (y * 12) as u8, 90, 255])
+            }
+        });
+        let mut bytes = Vec::new();
+        DynamicImage::ImageRgba8(img)
+            .write_to(&mut Cursor::new(&mut bytes), image::ImageFormat::Png)
+            .unwrap();
+        let config = CanvasConfig::new(bytes, Some(30), Some(5)).unwrap();
+        Canvas::new(config).unwrap()
+    }
+
+    fn group<'a>(document: &'a Document, id: &str) -> Option<Node<'a, 'a>> {
+        document
+            .descendants()
+            .find(|node| node.has_tag_name("g") && node.attribute("id") == Some(id))
+    }
+
+    fn count_children(group: Node, tag: &str) -> usize {
+        group
+            .children()
+            .filter(|node| node.has_tag_name(tag))
+            .count()
+    }
+
+    #[test]
+    fn it_renders_a_rectangle_per_stitch() {
+        let canvas = canvas();
+        let svg = render_svg(&canvas, true);
+        let document = Document::parse(&svg).unwrap();
+
+        let root = document.root_element();
+        assert_eq!(root.attribute("viewBox"), Some("0 0 348 248"));
+        let cells = group(&document, "cells").unwrap();
+        assert_eq!(count_children(cells, "rect"), 30 * 20 - 10 * 5);
+        let symbols = group(&document, "symbols").unwrap();
+        assert_eq!(count_children(symbols, "text"), 30 * 20 - 10 * 5);
+
+        let coordinates = group(&document, "coordinates").unwrap();
+        let labels: Vec<&str> = coordinates
+            .children()
+            .filter_map(|node| node.text())
+            .filter(|text| !text.trim().is_empty())
+            .collect();
+        assert_eq!(labels, ["10", "20", "30", "10", "20"]);
+    }
+
+    #[test]
+    fn it_leaves_symbols_out() {
+        let svg = render_svg(&canvas(), false);
+        let document = Document::parse(&svg).unwrap();
+        assert!(group(&document, "symbols").is_none());
+        assert!(group(&document, "grid").is_some());
+    }
+
+    #[test]
+    fn it_escapes_symbols() {
+        assert_eq!(escape("<&>"), "&lt;&amp;&gt;");
+    }
+}
@@ -0,0 +1,213 @@
+use lab::Lab;
+use std::collections::HashMap;
+
+use crate::embroidery::canvas::PaletteColor;
+use crate::embroidery::metric::ColorMetric;
+
+/// Glyphs printed in the chart cells, the easiest to tell apart first. Only
+/// ASCII so every chart font has them.
+const SYMBOLS: &[u8] = b"XO+#*=%@&$?<>^~/ABCDEFGHJKLMNPRSTUVWYZ0123456789abdeghknqrstuy";
+
+/// Glyphs easily mistaken for one another in a small cell
+const LOOK_ALIKES: [&[u8]; 16] = [
+    b"OQDC0", b"I1l|!i", b"S5$s", b"Z2z", b"B8", b"G6", b"+xX*", b"<(", b">)", b"-~=", b"UVuv",
+    b"nhu", b"PR", b"EF", b"MNW", b"Yy",
+];
+
+/// Colors closer than this are adjacent shades, whose symbols should not
+/// look alike
+const ADJACENT_DIFFERENCE: f32 = 20.0;
+
+/// Longest symbol that fits in a cell
+pub const MAX_SYMBOL_LENGTH: usize = 2;
+
+/// Gives each palette color a symbol, overridden by brand and thread code.
+#[derive(Debug, Clone, Default)]
+pub struct SymbolAllocator {
+    overrides: HashMap<(String, String), String>,
+}
+
+impl SymbolAllocator {
+    /// Overrides should be keyed by `override_key`.
+    pub fn new(overrides: HashMap<(String, String), String>) -> Self {
+        SymbolAllocator { overrides }
+    }
+
+    /// Symbols of `colors`, in the same order. Colors are taken in order and
+    /// get the first free symbol that does not look like the symbol of a
+    /// closer shade than `ADJACENT_DIFFERENCE`. An override already taken by
+    /// an earlier color is ignored, so every symbol is distinct.
+    pub fn allocate(&self, colors: &[PaletteColor], metric: ColorMetric) -> Vec<String> {
+        let labs: Vec<Lab> = colors
+            .iter()
+            .map(|color| Lab::from_rgb(&color.rgb.into()))
+            .collect();
+        let mut symbols: Vec<Option<String>> = Vec::with_capacity(colors.len());
+        for color in colors {
+            let symbol = self
+                .overrides
+                .get(&override_key(color.brand, &color.name))
+                .filter(|&symbol| !symbols.iter().flatten().any(|taken| taken == symbol));
+            symbols.push(symbol.cloned());
+        }
+
+        for index in 0..colors.len() {
+            if symbols[index].is_some() {
+                continue;
+            }
+            let is_confusing = |symbol: &String| {
+                symbols.iter().zip(&labs).any(|(other, &lab)| {
+                    other
+                        .as_ref()
+                        .is_some_and(|other| look_alike(symbol, other))
+                        && metric.difference(labs[index], lab) < ADJACENT_DIFFERENCE
+                })
+            };
+            // Falls back to the first free symbol when every one looks like
+            // a neighbour's
+            let free: Vec<String> = (0..)
+                .map(candidate)
+                .filter(|symbol| !symbols.iter().flatten().any(|taken| taken == symbol))
+                .take(colors.len() + 1)
+                .collect();
+            let symbol = free
+                .iter()
+                .find(|symbol| !is_confusing(symbol))
+                .unwrap_or(&free[0]);
+            symbols[index] = Some(symbol.clone());
+        }
+        symbols.into_iter().flatten().collect()
+    }
+}
+
+/// Key of the symbol override of a thread, or blend, of `brand`. Brands and
+/// codes are compared regardless of case.
+pub fn override_key(brand: &str, code: &str) -> (String, String) {
+    (brand.to_ascii_uppercase(), code.to_ascii_uppercase())
+}
+
+/// `index`th symbol: single glyphs, then pairs of them
+fn candidate(index: usize) -> String {
+    let n_symbols = SYMBOLS.len();
+    let last = SYMBOLS[index % n_symbols] as char;
+    match index / n_symbols {
+        0 => last.to_string(),
+        first => format!("{}{}", SYMBOLS[(first - 1) % n_symbols] as char, last),
+    }
+}
+
+/// Symbols of the same length whose glyphs are equal or look alike one by
+/// one
+fn look_alike(symbol: &str, other: &str) -> bool {
+    symbol.len() == other.len()
+        && symbol.bytes().zip(other.bytes()).all(|(a, b)| {
+            a == b
+                || LOOK_ALIKES
+                    .iter()
+                    .any(|group| group.contains(&a) && group.contains(&b))
+        })
+}
+
+/// Whether `symbol` can be printed in a cell.
+pub fn is_valid_symbol(symbol: &str) -> bool {
+    (1..=MAX_SYMBOL_LENGTH).contains(&symbol.len())
+        && symbol.bytes().all(|byte| byte.is_ascii_graphic())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::embroidery::colors::RgbColor;
+
+    fn color(name: &str, red: u8, green: u8, blue: u8) -> PaletteColor {
+        branded_color("DMC", name, red, green, blue)
+    }
+
+    fn branded_color(
+        brand: &'static str,
+        name: &str,
+        red: u8,
+        green: u8,
+        blue: u8,
+    ) -> PaletteColor {
+        PaletteColor {
+            brand,
+            name: name.into(),
+            thread_name: None,
+            rgb: RgbColor { red, green, blue },
+        }
+    }
+
+    #[test]
+    fn it_assigns_distinct_symbols() {
+        let colors: Vec<PaletteColor> = (0..300)
+            .map(|index| color(&index.to_string(), index as u8, 0, 0))
+            .collect();
+        let symbols = SymbolAllocator::default().allocate(&colors, ColorMetric::default());
+        assert_eq!(symbols.len(), 300);
+        for (index, value) in symbols.iter().enumerate() {
+            assert!(!symbols[..index].contains(value), "{value}");
+        }
+        assert_eq!(symbols[0], "X");
+    }
+
+    #[test]
+    fn it_avoids_look_alikes_on_adjacent_shades() {
+        let colors = [
+            color("1", 250, 250, 250),
+            color("2", 245, 245, 245),
+            color("3", 240, 240, 240),
+        ];
+        let symbols = SymbolAllocator::default().allocate(&colors, ColorMetric::default());
+        // "+" is next but looks like the "X" of a close shade
+        assert_eq!(symbols, ["X", "O", "#"]);
+
+        let colors = [
+            color("1", 250, 250, 250),
+            color("2", 120, 120, 120),
+            color("3", 0, 0, 0),
+        ];
+        let symbols = SymbolAllocator::default().allocate(&colors, ColorMetric::default());
+        assert_eq!(symbols, ["X", "O", "+"]);
+    }
+
+    #[test]
+    fn it_applies_overrides() {
+        let colors = [
+            color("310", 0, 0, 0),
+            color("B5200", 255, 255, 255),
+            branded_color("Anchor", "403", 0, 0, 0),
+        ];
+        let overrides = HashMap::from([
+            (override_key("dmc", "b5200"), "X".to_string()),
+            (override_key("DMC", "403"), "@".to_string()),
+        ]);
+        let symbols = SymbolAllocator::new(overrides).allocate(&colors, ColorMetric::default());
+        // DMC 403 is not in the palette, so Anchor 403 keeps its own symbol
+        assert_eq!(symbols, ["O", "X", "+"]);
+    }
+
+    #[test]
+    fn it_keeps_overridden_symbols_distinct() {
+        let colors = [color("310", 0, 0, 0), color("B5200", 255, 255, 255)];
+        let overrides = HashMap::from([
+            (override_key("DMC", "310"), "X".to_string()),
+            (override_key("DMC", "B5200"), "X".to_string()),
+        ]);
+        let symbols = SymbolAllocator::new(overrides).allocate(&colors, ColorMetric::default());
+        assert_eq!(symbols, ["X", "O"]);
+    }
+
+    #[test]
+    fn it_compares_glyphs() {
+        assert!(look_alike("O", "0"));
+        assert!(look_alike("X+", "*x"));
+        assert!(!look_alike("X", "XX"));
+        assert!(!look_alike("A", "B"));
+        assert!(is_valid_symbol("@"));
+        assert!(is_valid_symbol("AB"));
+        assert!(!is_valid_symbol("ABC"));
+        assert!(!is_valid_symbol(" "));
+        assert!(!is_valid_symbol("é"));
+    }
+}
@@ -1,7 +1,10 @@
 use actix_multipart::MultipartError;
 use actix_web::{HttpResponse, ResponseError};
+use std::path::PathBuf;
 use std::string::FromUtf8Error;
 
+use crate::embroidery::size::MAX_CELLS;
+
 #[derive(thiserror::Error, Debug)]
 pub enum UploadError {
     #[error(transparent)]
@@ -16,6 +19,14 @@ impl ResponseError for UploadError {
     fn error_response(&self) -> HttpResponse {
         match self {
             UploadError::InvalidPayload(err) => err.error_response(),
+            UploadError::Canvas(
+                err @ (CanvasError::GridTooLarge(..)
+                | CanvasError::InvalidCrop
+                | CanvasError::MalformedChart(_)),
+            ) => HttpResponse::BadRequest().json(err.to_string()),
+            UploadError::Canvas(err @ CanvasError::UnsupportedFormat) => {
+                HttpResponse::UnsupportedMediaType().json(err.to_string())
+            }
             s => HttpResponse::InternalServerError().json(s.to_string()),
         }
     }
@@ -35,6 +46,14 @@ impl ResponseError for ExportError {
     fn error_response(&self) -> HttpResponse {
         match self {
             ExportError::InvalidPayload(err) => err.error_response(),
+            ExportError::Canvas(
+                err @ (CanvasError::GridTooLarge(..)
+                | CanvasError::InvalidCrop
+                | CanvasError::MalformedChart(_)),
+            ) => HttpResponse::BadRequest().json(err.to_string()),
+            ExportError::Canvas(err @ CanvasError::UnsupportedFormat) => {
+                HttpResponse::UnsupportedMediaType().json(err.to_string())
+            }
             s => HttpResponse::InternalServerError().json(s.to_string()),
         }
     }
@@ -73,4 +92,28 @@ pub enum CanvasError {
     ImageFormat(#[from] std::io::Error),
     #[error(transparent)]
     Image(#[from] image::ImageError),
+    #[error("Grid of {0}x{1} cells exceeds the limit of {max} cells per side", max = MAX_CELLS)]
+    GridTooLarge(u32, u32),
+    #[error("Crop rectangle should be within the image")]
+    InvalidCrop,
+    #[error("Unsupported image format. Expected PNG, JPEG, WebP, GIF, BMP or TIFF")]
+    UnsupportedFormat,
+    #[error("Malformed OXS chart. {0}")]
+    MalformedChart(String),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum CatalogError {
+    #[error("Cannot read thread catalog '{0}'")]
+    Read(PathBuf, #[source] std::io::Error),
+    #[error("Unsupported thread catalog '{0}'. Expected a .csv or .json file")]
+    UnsupportedFile(PathBuf),
+    #[error("Malformed thread catalog '{0}'. {1}")]
+    Malformed(PathBuf, String),
+    #[error("Malformed row {1} in thread catalog '{0}'. {2}")]
+    MalformedRow(PathBuf, usize, String),
+    #[error("Thread catalog '{0}' has the same brand as '{1}'")]
+    DuplicateBrand(PathBuf, PathBuf),
+    #[error("Thread catalogs are already registered")]
+    AlreadyRegistered,
 }
@@ -1,4 +1,4 @@
 pub mod api;
 pub mod embroidery;
-mod error;
+pub mod error;
 pub mod http;
@@ -1,8 +1,18 @@
 use actix_web::{App, HttpServer};
 use pixify::api::routes;
+use pixify::embroidery::{catalog, loader};
+use std::io::{Error, ErrorKind};
+use std::path::Path;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
+    if let Ok(dir) = std::env::var("PIXIFY_CATALOG_DIR") {
+        let to_io_error =
+            |err: pixify::error::CatalogError| Error::new(ErrorKind::InvalidData, err.to_string());
+        let catalogs = loader::load_catalogs(Path::new(&dir)).map_err(to_io_error)?;
+        catalog::register(catalogs).map_err(to_io_error)?;
+    }
+
     HttpServer::new(|| App::new().configure(routes::services))
         .bind(("0.0.0.0", 8080))?
         .run()
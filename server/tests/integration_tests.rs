@@ -1,7 +1,43 @@
 #[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
 struct CanvasResponse {
-    pub embroidery: Vec<Vec<[u8; 3]>>,
+    pub embroidery: Vec<Vec<Option<[u8; 3]>>>,
     pub palette: Vec<Palette>,
+    pub color_shortfall: u8,
+    pub rare_colors: usize,
+    pub cleaned_cells: usize,
+    pub drift: Option<Drift>,
+    pub dimensions: Dimensions,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[allow(unused)]
+struct Dimensions {
+    pub columns: u32,
+    pub rows: u32,
+    pub unit: String,
+    pub finished_width: f32,
+    pub finished_height: f32,
+    pub fabric_width: f32,
+    pub fabric_height: f32,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Drift {
+    pub mean_delta_e: f32,
+    pub regions: Vec<RegionDrift>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[allow(unused)]
+struct RegionDrift {
+    pub column: usize,
+    pub row: usize,
+    pub n_stitches: usize,
+    pub mean_delta_e: f32,
 }
 
 #[derive(serde::Deserialize)]
@@ -9,13 +45,23 @@ struct CanvasResponse {
 #[allow(unused)]
 struct Palette {
     pub identifier: String,
+    pub symbol: String,
     pub color: Color,
     pub n_stitches: usize,
+    pub strands: Vec<Strands>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Strands {
+    pub code: String,
+    pub n_strands: usize,
 }
 
 #[derive(serde::Deserialize)]
 #[allow(unused)]
 struct Color {
+    pub brand: String,
     pub name: String,
     pub rgb: [u8; 3],
 }
@@ -24,8 +70,11 @@ struct Color {
 mod tests {
     use crate::CanvasResponse;
     use actix_web::{test, web::Bytes, App};
+    use image::{DynamicImage, ImageBuffer, ImageFormat, Rgb, Rgba};
     use pixify::api::routes;
     use pixify::http::multipart::MultipartBuilder;
+    use std::collections::HashSet;
+    use std::io::Cursor;
 
     #[actix_web::test]
     async fn it_uploads_image() {
@@ -47,6 +96,7 @@ mod tests {
         assert!(resp.status().is_success());
         let body: CanvasResponse = test::read_body_json(resp).await;
         assert_eq!(body.palette.len(), 5);
+        assert_eq!(body.color_shortfall, 0);
         assert_eq!(body.embroidery[0].len(), 10); // check embroidery row length
     }
 
@@ -98,7 +148,7 @@ mod tests {
     }
 
     #[actix_web::test]
-    async fn it_exports_image() {
+    async fn it_uploads_image_with_brand() {
         let app = test::init_service(App::new().configure(routes::services)).await;
         let pic = include_bytes!("pic.png").to_vec();
 
@@ -106,21 +156,1516 @@ mod tests {
         multipart.add_file("file", "pic.png", &pic);
         multipart.add_text("nColors", 5);
         multipart.add_text("nCellsInWidth", 10);
+        multipart.add_text("brand", "anchor");
         let (header, payload) = multipart.build();
         let req = test::TestRequest::post()
-            .uri("/api/export")
+            .uri("/api/upload")
             .insert_header(header)
             .set_payload(payload)
             .to_request();
         let resp = test::call_service(&app, req).await;
 
         assert!(resp.status().is_success());
-        assert!(resp.headers().contains_key("content-type"));
-        assert_eq!(resp.headers().get("content-type").unwrap(), "image/png");
-        assert!(resp.headers().contains_key("content-disposition"));
+        let body: CanvasResponse = test::read_body_json(resp).await;
+        assert!(body
+            .palette
+            .iter()
+            .all(|palette| palette.color.brand == "Anchor"));
+    }
+
+    #[actix_web::test]
+    async fn it_uploads_image_with_unknown_brand() {
+        let app = test::init_service(App::new().configure(routes::services)).await;
+        let pic = include_bytes!("pic.png").to_vec();
+
+        let mut multipart = MultipartBuilder::new();
+        multipart.add_file("file", "pic.png", &pic);
+        multipart.add_text("brand", "unknown");
+        let (header, payload) = multipart.build();
+        let req = test::TestRequest::post()
+            .uri("/api/upload")
+            .insert_header(header)
+            .set_payload(payload)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), 400);
+        let body = test::read_body(resp).await;
         assert_eq!(
-            resp.headers().get("content-disposition").unwrap(),
-            "attachment; filename=pic.png"
-        )
+            body,
+            Bytes::from_static(
+                b"\"Invalid value in 'brand'. Value should be one of: DMC, Anchor, Madeira, Cosmo\""
+            )
+        );
+    }
+
+    #[actix_web::test]
+    async fn it_uploads_image_with_metric() {
+        let app = test::init_service(App::new().configure(routes::services)).await;
+        let pic = include_bytes!("pic.png").to_vec();
+
+        for metric in ["cie76", "cie94", "ciede2000", "oklab"] {
+            let mut multipart = MultipartBuilder::new();
+            multipart.add_file("file", "pic.png", &pic);
+            multipart.add_text("nColors", 5);
+            multipart.add_text("nCellsInWidth", 10);
+            multipart.add_text("metric", metric);
+            let (header, payload) = multipart.build();
+            let req = test::TestRequest::post()
+                .uri("/api/upload")
+                .insert_header(header)
+                .set_payload(payload)
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+
+            assert!(resp.status().is_success());
+            let body: CanvasResponse = test::read_body_json(resp).await;
+            assert_eq!(body.embroidery[0].len(), 10);
+        }
+    }
+
+    #[actix_web::test]
+    async fn it_uploads_image_with_unknown_metric() {
+        let app = test::init_service(App::new().configure(routes::services)).await;
+        let pic = include_bytes!("pic.png").to_vec();
+
+        let mut multipart = MultipartBuilder::new();
+        multipart.add_file("file", "pic.png", &pic);
+        multipart.add_text("metric", "cmc");
+        let (header, payload) = multipart.build();
+        let req = test::TestRequest::post()
+            .uri("/api/upload")
+            .insert_header(header)
+            .set_payload(payload)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), 400);
+        let body = test::read_body(resp).await;
+        assert_eq!(
+            body,
+            Bytes::from_static(
+                b"\"Invalid value in 'metric'. Value should be one of: cie76, cie94, ciede2000, oklab\""
+            )
+        );
+    }
+
+    #[actix_web::test]
+    async fn it_uploads_image_with_quantizer() {
+        let app = test::init_service(App::new().configure(routes::services)).await;
+        let pic = include_bytes!("pic.png").to_vec();
+
+        for quantizer in ["kmeans", "palette_extract"] {
+            let mut multipart = MultipartBuilder::new();
+            multipart.add_file("file", "pic.png", &pic);
+            multipart.add_text("nColors", 5);
+            multipart.add_text("nCellsInWidth", 10);
+            multipart.add_text("quantizer", quantizer);
+            let (header, payload) = multipart.build();
+            let req = test::TestRequest::post()
+                .uri("/api/upload")
+                .insert_header(header)
+                .set_payload(payload)
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+
+            assert!(resp.status().is_success());
+            let body: CanvasResponse = test::read_body_json(resp).await;
+            assert_eq!(body.embroidery[0].len(), 10);
+        }
+    }
+
+    #[actix_web::test]
+    async fn it_uploads_image_with_unknown_quantizer() {
+        let app = test::init_service(App::new().configure(routes::services)).await;
+        let pic = include_bytes!("pic.png").to_vec();
+
+        let mut multipart = MultipartBuilder::new();
+        multipart.add_file("file", "pic.png", &pic);
+        multipart.add_text("quantizer", "octree");
+        let (header, payload) = multipart.build();
+        let req = test::TestRequest::post()
+            .uri("/api/upload")
+            .insert_header(header)
+            .set_payload(payload)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), 400);
+        let body = test::read_body(resp).await;
+        assert_eq!(
+            body,
+            Bytes::from_static(
+                b"\"Invalid value in 'quantizer'. Value should be one of: kmeans, palette_extract\""
+            )
+        );
+    }
+
+    #[actix_web::test]
+    async fn it_uploads_image_with_dither() {
+        let app = test::init_service(App::new().configure(routes::services)).await;
+        let pic = include_bytes!("pic.png").to_vec();
+
+        for dither in ["none", "floyd_steinberg", "atkinson", "sierra", "bayer"] {
+            let mut multipart = MultipartBuilder::new();
+            multipart.add_file("file", "pic.png", &pic);
+            multipart.add_text("nColors", 5);
+            multipart.add_text("nCellsInWidth", 20);
+            multipart.add_text("dither", dither);
+            multipart.add_text("ditherStrength", 0.75);
+            let (header, payload) = multipart.build();
+            let req = test::TestRequest::post()
+                .uri("/api/upload")
+                .insert_header(header)
+                .set_payload(payload)
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+
+            assert!(resp.status().is_success());
+            let body: CanvasResponse = test::read_body_json(resp).await;
+            assert!(body
+                .embroidery
+                .iter()
+                .flatten()
+                .flatten()
+                .all(|cell| body.palette.iter().any(|entry| entry.color.rgb == *cell)));
+        }
+    }
+
+    #[actix_web::test]
+    async fn it_uploads_image_with_invalid_dither_strength() {
+        let app = test::init_service(App::new().configure(routes::services)).await;
+        let pic = include_bytes!("pic.png").to_vec();
+
+        let mut multipart = MultipartBuilder::new();
+        multipart.add_file("file", "pic.png", &pic);
+        multipart.add_text("dither", "bayer");
+        multipart.add_text("ditherStrength", 1.5);
+        let (header, payload) = multipart.build();
+        let req = test::TestRequest::post()
+            .uri("/api/upload")
+            .insert_header(header)
+            .set_payload(payload)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), 400);
+        let body = test::read_body(resp).await;
+        assert_eq!(
+            body,
+            Bytes::from_static(
+                b"\"Invalid value in 'ditherStrength'. Value should be within 0 and 1\""
+            )
+        );
+    }
+
+    #[actix_web::test]
+    async fn it_uploads_transparent_image() {
+        let app = test::init_service(App::new().configure(routes::services)).await;
+        let image = ImageBuffer::from_fn(20, 20, |x, y| {
+            if y < 10 {
+                Rgba([0, 0, 0, 0])
+            } else {
+                Rgba([(x * 12) as u8, 100, 200, 255])
+            }
+        });
+        let mut pic = Vec::new();
+        DynamicImage::ImageRgba8(image)
+            .write_to(&mut Cursor::new(&mut pic), ImageFormat::Png)
+            .unwrap();
+
+        let mut multipart = MultipartBuilder::new();
+        multipart.add_file("file", "pic.png", &pic);
+        multipart.add_text("nColors", 3);
+        multipart.add_text("nCellsInWidth", 10);
+        multipart.add_text("alphaThreshold", 200);
+        let (header, payload) = multipart.build();
+        let req = test::TestRequest::post()
+            .uri("/api/upload")
+            .insert_header(header)
+            .set_payload(payload)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+        let body: CanvasResponse = test::read_body_json(resp).await;
+        assert!(body.embroidery[0].iter().all(Option::is_none));
+        assert!(body.embroidery[9].iter().all(Option::is_some));
+        let n_stitches: usize = body.palette.iter().map(|entry| entry.n_stitches).sum();
+        assert_eq!(n_stitches, 50);
+    }
+
+    #[actix_web::test]
+    async fn it_uploads_image_with_fabric_color() {
+        let app = test::init_service(App::new().configure(routes::services)).await;
+        let image = ImageBuffer::from_fn(20, 20, |x, y| {
+            if (4..16).contains(&x) && (4..16).contains(&y) {
+                Rgb([200, 30, 30])
+            } else {
+                Rgb([255, 255, 255])
+            }
+        });
+        let mut pic = Vec::new();
+        DynamicImage::ImageRgb8(image)
+            .write_to(&mut Cursor::new(&mut pic), ImageFormat::Png)
+            .unwrap();
+
+        for (fabric_color, auto_background) in [("B5200", false), ("#FFFFFF", true)] {
+            let mut multipart = MultipartBuilder::new();
+            multipart.add_file("file", "pic.png", &pic);
+            multipart.add_text("nColors", 3);
+            multipart.add_text("nCellsInWidth", 10);
+            multipart.add_text("fabricColor", fabric_color);
+            multipart.add_text("fabricTolerance", 5);
+            multipart.add_text("autoBackground", auto_background);
+            let (header, payload) = multipart.build();
+            let req = test::TestRequest::post()
+                .uri("/api/upload")
+                .insert_header(header)
+                .set_payload(payload)
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+
+            assert!(resp.status().is_success());
+            let body: CanvasResponse = test::read_body_json(resp).await;
+            assert!(body.embroidery[0].iter().all(Option::is_none));
+            let n_stitched = body.embroidery.iter().flatten().flatten().count();
+            let n_stitches: usize = body.palette.iter().map(|entry| entry.n_stitches).sum();
+            assert_eq!(n_stitches, n_stitched);
+            assert!((36..=64).contains(&n_stitched));
+        }
+    }
+
+    #[actix_web::test]
+    async fn it_uploads_image_with_unknown_fabric_color() {
+        let app = test::init_service(App::new().configure(routes::services)).await;
+        let pic = include_bytes!("pic.png").to_vec();
+
+        let mut multipart = MultipartBuilder::new();
+        multipart.add_file("file", "pic.png", &pic);
+        multipart.add_text("fabricColor", "aida");
+        let (header, payload) = multipart.build();
+        let req = test::TestRequest::post()
+            .uri("/api/upload")
+            .insert_header(header)
+            .set_payload(payload)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), 400);
+        let body = test::read_body(resp).await;
+        assert_eq!(
+            body,
+            Bytes::from_static(
+                b"\"Invalid value in 'fabricColor'. Value should be a DMC code or a hex color\""
+            )
+        );
+    }
+
+    #[actix_web::test]
+    async fn it_uploads_image_with_confetti_cleanup() {
+        let app = test::init_service(App::new().configure(routes::services)).await;
+        let pic = include_bytes!("pic.png").to_vec();
+
+        let mut cleaned_cells = Vec::new();
+        for min_cluster_size in [1, 4] {
+            let mut multipart = MultipartBuilder::new();
+            multipart.add_file("file", "pic.png", &pic);
+            multipart.add_text("nColors", 10);
+            multipart.add_text("nCellsInWidth", 40);
+            multipart.add_text("dither", "floyd_steinberg");
+            multipart.add_text("minClusterSize", min_cluster_size);
+            multipart.add_text("connectivity", 8);
+            let (header, payload) = multipart.build();
+            let req = test::TestRequest::post()
+                .uri("/api/upload")
+                .insert_header(header)
+                .set_payload(payload)
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+
+            assert!(resp.status().is_success());
+            let body: CanvasResponse = test::read_body_json(resp).await;
+            cleaned_cells.push(body.cleaned_cells);
+        }
+        assert_eq!(cleaned_cells[0], 0);
+        assert!(cleaned_cells[1] > 0);
+    }
+
+    #[actix_web::test]
+    async fn it_uploads_image_with_unknown_connectivity() {
+        let app = test::init_service(App::new().configure(routes::services)).await;
+        let pic = include_bytes!("pic.png").to_vec();
+
+        let mut multipart = MultipartBuilder::new();
+        multipart.add_file("file", "pic.png", &pic);
+        multipart.add_text("minClusterSize", 3);
+        multipart.add_text("connectivity", 6);
+        let (header, payload) = multipart.build();
+        let req = test::TestRequest::post()
+            .uri("/api/upload")
+            .insert_header(header)
+            .set_payload(payload)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), 400);
+        let body = test::read_body(resp).await;
+        assert_eq!(
+            body,
+            Bytes::from_static(
+                b"\"Invalid value in 'connectivity'. Value should be one of: 4, 8\""
+            )
+        );
+    }
+
+    #[actix_web::test]
+    async fn it_uploads_image_with_min_stitches_per_color() {
+        let app = test::init_service(App::new().configure(routes::services)).await;
+        let pic = include_bytes!("pic.png").to_vec();
+
+        let mut multipart = MultipartBuilder::new();
+        multipart.add_file("file", "pic.png", &pic);
+        multipart.add_text("nColors", 30);
+        multipart.add_text("nCellsInWidth", 30);
+        multipart.add_text("minStitchesPerColor", 25);
+        let (header, payload) = multipart.build();
+        let req = test::TestRequest::post()
+            .uri("/api/upload")
+            .insert_header(header)
+            .set_payload(payload)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+        let body: CanvasResponse = test::read_body_json(resp).await;
+        assert!(!body.palette.is_empty());
+        assert!(body.palette.iter().all(|entry| entry.n_stitches >= 25));
+        assert_eq!(body.rare_colors, 0);
+        assert_eq!(
+            body.palette.last().unwrap().identifier,
+            format!("{:02}", body.palette.len())
+        );
+    }
+
+    #[actix_web::test]
+    async fn it_reports_rare_colors_kept() {
+        let app = test::init_service(App::new().configure(routes::services)).await;
+        let pic = include_bytes!("pic.png").to_vec();
+
+        let mut multipart = MultipartBuilder::new();
+        multipart.add_file("file", "pic.png", &pic);
+        multipart.add_text("nColors", 8);
+        multipart.add_text("nCellsInWidth", 30);
+        multipart.add_text("includeThreads", "310, 666");
+        multipart.add_text("minStitchesPerColor", 10000);
+        let (header, payload) = multipart.build();
+        let req = test::TestRequest::post()
+            .uri("/api/upload")
+            .insert_header(header)
+            .set_payload(payload)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+        let body: CanvasResponse = test::read_body_json(resp).await;
+        // Fewer cells than the minimum, so only the included threads are left
+        assert_eq!(body.palette.len(), 2);
+        assert_eq!(body.rare_colors, 2);
+    }
+
+    #[actix_web::test]
+    async fn it_uploads_image_with_invalid_min_stitches_per_color() {
+        let app = test::init_service(App::new().configure(routes::services)).await;
+        let pic = include_bytes!("pic.png").to_vec();
+
+        for value in ["0", "-3", "many"] {
+            let mut multipart = MultipartBuilder::new();
+            multipart.add_file("file", "pic.png", &pic);
+            multipart.add_text("minStitchesPerColor", value);
+            let (header, payload) = multipart.build();
+            let req = test::TestRequest::post()
+                .uri("/api/upload")
+                .insert_header(header)
+                .set_payload(payload)
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+
+            assert_eq!(resp.status(), 400);
+            let body = test::read_body(resp).await;
+            assert_eq!(
+                body,
+                Bytes::from_static(
+                    b"\"Invalid value in 'minStitchesPerColor'. Value should be a positive number\""
+                )
+            );
+        }
+    }
+
+    #[actix_web::test]
+    async fn it_uploads_image_with_included_and_excluded_threads() {
+        let app = test::init_service(App::new().configure(routes::services)).await;
+        let pic = include_bytes!("pic.png").to_vec();
+
+        let mut multipart = MultipartBuilder::new();
+        multipart.add_file("file", "pic.png", &pic);
+        multipart.add_text("nColors", 8);
+        multipart.add_text("nCellsInWidth", 30);
+        multipart.add_text("includeThreads", "310, 666");
+        multipart.add_text("excludeThreads", "B5200 3865");
+        let (header, payload) = multipart.build();
+        let req = test::TestRequest::post()
+            .uri("/api/upload")
+            .insert_header(header)
+            .set_payload(payload)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+        let body: CanvasResponse = test::read_body_json(resp).await;
+        assert!(body.palette.len() <= 8);
+        assert!(body
+            .palette
+            .iter()
+            .all(|entry| entry.color.name != "B5200" && entry.color.name != "3865"));
+    }
+
+    #[actix_web::test]
+    async fn it_uploads_image_with_included_threads_of_one_color() {
+        let app = test::init_service(App::new().configure(routes::services)).await;
+        let pic = include_bytes!("pic.png").to_vec();
+
+        let mut multipart = MultipartBuilder::new();
+        multipart.add_file("file", "pic.png", &pic);
+        multipart.add_text("includeThreads", "776, 33");
+        let (header, payload) = multipart.build();
+        let req = test::TestRequest::post()
+            .uri("/api/upload")
+            .insert_header(header)
+            .set_payload(payload)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), 400);
+        let body = test::read_body(resp).await;
+        assert_eq!(
+            body,
+            Bytes::from_static(b"\"Invalid value in 'includeThreads'. Duplicate color '33'\"")
+        );
+    }
+
+    #[actix_web::test]
+    async fn it_uploads_image_with_unknown_thread() {
+        let app = test::init_service(App::new().configure(routes::services)).await;
+        let pic = include_bytes!("pic.png").to_vec();
+
+        let mut multipart = MultipartBuilder::new();
+        multipart.add_file("file", "pic.png", &pic);
+        multipart.add_text("includeThreads", "310,9999");
+        let (header, payload) = multipart.build();
+        let req = test::TestRequest::post()
+            .uri("/api/upload")
+            .insert_header(header)
+            .set_payload(payload)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), 400);
+        let body = test::read_body(resp).await;
+        assert_eq!(
+            body,
+            Bytes::from_static(
+                b"\"Invalid value in 'includeThreads'. Unknown thread code '9999'\""
+            )
+        );
+    }
+
+    #[actix_web::test]
+    async fn it_uploads_image_with_inventory() {
+        let app = test::init_service(App::new().configure(routes::services)).await;
+        let pic = include_bytes!("pic.png").to_vec();
+        let owned = ["310", "B5200", "321", "3865", "798", "699", "444"];
+
+        let mut multipart = MultipartBuilder::new();
+        multipart.add_file("file", "pic.png", &pic);
+        multipart.add_text("nColors", 5);
+        multipart.add_text("nCellsInWidth", 20);
+        multipart.add_text("inventory", owned.join(","));
+        let (header, payload) = multipart.build();
+        let req = test::TestRequest::post()
+            .uri("/api/upload")
+            .insert_header(header)
+            .set_payload(payload)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+        let body: CanvasResponse = test::read_body_json(resp).await;
+        assert!(body
+            .palette
+            .iter()
+            .all(|entry| owned.contains(&entry.color.name.as_str())));
+        let drift = body.drift.unwrap();
+        assert!(drift.mean_delta_e > 0.0);
+        let n_stitches: usize = body.palette.iter().map(|entry| entry.n_stitches).sum();
+        assert_eq!(
+            drift
+                .regions
+                .iter()
+                .map(|region| region.n_stitches)
+                .sum::<usize>(),
+            n_stitches
+        );
+    }
+
+    #[actix_web::test]
+    async fn it_uploads_image_with_inventory_csv() {
+        let app = test::init_service(App::new().configure(routes::services)).await;
+        let pic = include_bytes!("pic.png").to_vec();
+        let csv = b"code,skeins\n310,1\nB5200,2\n666,1\n".to_vec();
+
+        let mut multipart = MultipartBuilder::new();
+        multipart.add_file("file", "pic.png", &pic);
+        multipart.add_file("inventory", "stash.csv", &csv);
+        multipart.add_text("nColors", 3);
+        let (header, payload) = multipart.build();
+        let req = test::TestRequest::post()
+            .uri("/api/upload")
+            .insert_header(header)
+            .set_payload(payload)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+        let body: CanvasResponse = test::read_body_json(resp).await;
+        assert!(body
+            .palette
+            .iter()
+            .all(|entry| ["310", "B5200", "666"].contains(&entry.color.name.as_str())));
+        assert!(body.drift.is_some());
+    }
+
+    #[actix_web::test]
+    async fn it_uploads_image_with_unknown_inventory_thread() {
+        let app = test::init_service(App::new().configure(routes::services)).await;
+        let pic = include_bytes!("pic.png").to_vec();
+
+        let csv = b"310\nXYZ\n".to_vec();
+
+        let mut multipart = MultipartBuilder::new();
+        multipart.add_file("file", "pic.png", &pic);
+        multipart.add_file("inventory", "stash.csv", &csv);
+        let (header, payload) = multipart.build();
+        let req = test::TestRequest::post()
+            .uri("/api/upload")
+            .insert_header(header)
+            .set_payload(payload)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), 400);
+        let body = test::read_body(resp).await;
+        assert_eq!(
+            body,
+            Bytes::from_static(b"\"Invalid value in 'inventory'. Unknown thread code 'XYZ'\"")
+        );
+    }
+
+    #[actix_web::test]
+    async fn it_uploads_image_with_blends() {
+        let app = test::init_service(App::new().configure(routes::services)).await;
+        let pic = include_bytes!("pic.png").to_vec();
+
+        let mut multipart = MultipartBuilder::new();
+        multipart.add_file("file", "pic.png", &pic);
+        multipart.add_text("nColors", 10);
+        multipart.add_text("nCellsInWidth", 30);
+        multipart.add_text("blends", true);
+        let (header, payload) = multipart.build();
+        let req = test::TestRequest::post()
+            .uri("/api/upload")
+            .insert_header(header)
+            .set_payload(payload)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+        let body: CanvasResponse = test::read_body_json(resp).await;
+        for entry in &body.palette {
+            let codes: Vec<&str> = entry.color.name.split('+').collect();
+            assert_eq!(
+                codes,
+                entry
+                    .strands
+                    .iter()
+                    .map(|strands| strands.code.as_str())
+                    .collect::<Vec<&str>>()
+            );
+            let n_strands: usize = entry.strands.iter().map(|strands| strands.n_strands).sum();
+            assert_eq!(n_strands, entry.n_stitches * 2);
+        }
+    }
+
+    #[actix_web::test]
+    async fn it_uploads_image_with_custom_palette() {
+        let app = test::init_service(App::new().configure(routes::services)).await;
+        let pic = include_bytes!("pic.png").to_vec();
+
+        let mut multipart = MultipartBuilder::new();
+        multipart.add_file("file", "pic.png", &pic);
+        multipart.add_text("nCellsInWidth", 20);
+        multipart.add_text("palette", "310, B5200, #FF0000");
+        let (header, payload) = multipart.build();
+        let req = test::TestRequest::post()
+            .uri("/api/upload")
+            .insert_header(header)
+            .set_payload(payload)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+        let body: CanvasResponse = test::read_body_json(resp).await;
+        assert!(!body.palette.is_empty() && body.palette.len() <= 3);
+        assert_eq!(body.color_shortfall, 0);
+        assert!(body
+            .palette
+            .iter()
+            .all(|entry| ["310", "B5200", "#FF0000"].contains(&entry.color.name.as_str())));
+    }
+
+    #[actix_web::test]
+    async fn it_uploads_image_with_close_custom_colors() {
+        let app = test::init_service(App::new().configure(routes::services)).await;
+        let image = ImageBuffer::from_fn(20, 20, |x, _| {
+            if x < 10 {
+                Rgb([255, 0, 0])
+            } else {
+                Rgb([248, 0, 0])
+            }
+        });
+        let mut pic = Vec::new();
+        DynamicImage::ImageRgb8(image)
+            .write_to(&mut Cursor::new(&mut pic), ImageFormat::Png)
+            .unwrap();
+
+        let mut multipart = MultipartBuilder::new();
+        multipart.add_file("file", "pic.png", &pic);
+        multipart.add_text("nCellsInWidth", 20);
+        multipart.add_text("palette", "#FF0000, #f80000");
+        let (header, payload) = multipart.build();
+        let req = test::TestRequest::post()
+            .uri("/api/upload")
+            .insert_header(header)
+            .set_payload(payload)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+        let body: CanvasResponse = test::read_body_json(resp).await;
+        let mut colors: Vec<(&str, &str, [u8; 3], usize)> = body
+            .palette
+            .iter()
+            .map(|entry| {
+                (
+                    entry.color.brand.as_str(),
+                    entry.color.name.as_str(),
+                    entry.color.rgb,
+                    entry.n_stitches,
+                )
+            })
+            .collect();
+        colors.sort_by_key(|&(_, name, _, _)| name);
+        assert_eq!(
+            colors,
+            [
+                ("Custom", "#F80000", [248, 0, 0], 200),
+                ("Custom", "#FF0000", [255, 0, 0], 200)
+            ]
+        );
+    }
+
+    #[actix_web::test]
+    async fn it_uploads_image_with_invalid_custom_palette() {
+        let app = test::init_service(App::new().configure(routes::services)).await;
+        let pic = include_bytes!("pic.png").to_vec();
+
+        for (palette, expected) in [
+            (
+                "310, b5200, 310",
+                "\"Invalid value in 'palette'. Duplicate color '310'\"",
+            ),
+            (
+                "310, #000000",
+                "\"Invalid value in 'palette'. Duplicate color '#000000'\"",
+            ),
+            (
+                "310, 12345",
+                "\"Invalid value in 'palette'. Unknown thread code or color '12345'\"",
+            ),
+        ] {
+            let mut multipart = MultipartBuilder::new();
+            multipart.add_file("file", "pic.png", &pic);
+            multipart.add_text("palette", palette);
+            let (header, payload) = multipart.build();
+            let req = test::TestRequest::post()
+                .uri("/api/upload")
+                .insert_header(header)
+                .set_payload(payload)
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+
+            assert_eq!(resp.status(), 400);
+            let body = test::read_body(resp).await;
+            assert_eq!(body, Bytes::from(expected));
+        }
+    }
+
+    #[actix_web::test]
+    async fn it_uploads_image_with_sampling() {
+        let app = test::init_service(App::new().configure(routes::services)).await;
+        let pic = include_bytes!("pic.png").to_vec();
+
+        for sampling in ["catmull_rom", "box", "median", "mode"] {
+            let mut multipart = MultipartBuilder::new();
+            multipart.add_file("file", "pic.png", &pic);
+            multipart.add_text("nColors", 5);
+            multipart.add_text("nCellsInWidth", 10);
+            multipart.add_text("sampling", sampling);
+            let (header, payload) = multipart.build();
+            let req = test::TestRequest::post()
+                .uri("/api/upload")
+                .insert_header(header)
+                .set_payload(payload)
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+
+            assert!(resp.status().is_success());
+            let body: CanvasResponse = test::read_body_json(resp).await;
+            assert_eq!(body.embroidery[0].len(), 10);
+        }
+    }
+
+    #[actix_web::test]
+    async fn it_uploads_image_with_unknown_sampling() {
+        let app = test::init_service(App::new().configure(routes::services)).await;
+        let pic = include_bytes!("pic.png").to_vec();
+
+        let mut multipart = MultipartBuilder::new();
+        multipart.add_file("file", "pic.png", &pic);
+        multipart.add_text("sampling", "lanczos");
+        let (header, payload) = multipart.build();
+        let req = test::TestRequest::post()
+            .uri("/api/upload")
+            .insert_header(header)
+            .set_payload(payload)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), 400);
+        let body = test::read_body(resp).await;
+        assert_eq!(
+            body,
+            Bytes::from_static(
+                b"\"Invalid value in 'sampling'. Value should be one of: catmull_rom, box, median, mode\""
+            )
+        );
+    }
+
+    #[actix_web::test]
+    async fn it_uploads_image_with_cell_aspect_ratio() {
+        let app = test::init_service(App::new().configure(routes::services)).await;
+        let pic = include_bytes!("pic.png").to_vec();
+        let (width, height) = image::load_from_memory(&pic)
+            .unwrap()
+            .to_rgba8()
+            .dimensions();
+
+        let mut multipart = MultipartBuilder::new();
+        multipart.add_file("file", "pic.png", &pic);
+        multipart.add_text("nCellsInWidth", 20);
+        multipart.add_text("cellAspectRatio", 1.25);
+        let (header, payload) = multipart.build();
+        let req = test::TestRequest::post()
+            .uri("/api/upload")
+            .insert_header(header)
+            .set_payload(payload)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+        let body: CanvasResponse = test::read_body_json(resp).await;
+        let rows = (height as f32 * 20.0 / width as f32 / 1.25).round() as usize;
+        assert_eq!(body.embroidery.len(), rows);
+        assert_eq!(body.embroidery[0].len(), 20);
+    }
+
+    #[actix_web::test]
+    async fn it_uploads_image_with_invalid_cell_aspect_ratio() {
+        let app = test::init_service(App::new().configure(routes::services)).await;
+        let pic = include_bytes!("pic.png").to_vec();
+
+        let mut multipart = MultipartBuilder::new();
+        multipart.add_file("file", "pic.png", &pic);
+        multipart.add_text("cellAspectRatio", 0);
+        let (header, payload) = multipart.build();
+        let req = test::TestRequest::post()
+            .uri("/api/upload")
+            .insert_header(header)
+            .set_payload(payload)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), 400);
+        let body = test::read_body(resp).await;
+        assert_eq!(
+            body,
+            Bytes::from_static(
+                b"\"Invalid value in 'cellAspectRatio'. Value should be within 0.25 and 4\""
+            )
+        );
+    }
+
+    #[actix_web::test]
+    async fn it_uploads_image_with_physical_size() {
+        let app = test::init_service(App::new().configure(routes::services)).await;
+        let pic = include_bytes!("pic.png").to_vec();
+
+        let mut multipart = MultipartBuilder::new();
+        multipart.add_file("file", "pic.png", &pic);
+        multipart.add_text("nColors", 5);
+        multipart.add_text("targetWidth", 50.8);
+        multipart.add_text("unit", "cm");
+        multipart.add_text("fabricCount", 14);
+        multipart.add_text("margin", 5);
+        let (header, payload) = multipart.build();
+        let req = test::TestRequest::post()
+            .uri("/api/upload")
+            .insert_header(header)
+            .set_payload(payload)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+        let body: CanvasResponse = test::read_body_json(resp).await;
+        // 20 inches of 14-count fabric
+        assert_eq!(body.dimensions.columns, 280);
+        assert_eq!(body.embroidery[0].len(), 280);
+        assert_eq!(body.embroidery.len(), body.dimensions.rows as usize);
+        assert_eq!(body.dimensions.unit, "cm");
+        assert!((body.dimensions.finished_width - 50.8).abs() < 0.01);
+        assert!((body.dimensions.fabric_width - 60.8).abs() < 0.01);
+    }
+
+    #[actix_web::test]
+    async fn it_uploads_image_with_too_large_physical_size() {
+        let app = test::init_service(App::new().configure(routes::services)).await;
+        let pic = include_bytes!("pic.png").to_vec();
+
+        let mut multipart = MultipartBuilder::new();
+        multipart.add_file("file", "pic.png", &pic);
+        multipart.add_text("targetWidth", 80);
+        multipart.add_text("fabricCount", 14);
+        let (header, payload) = multipart.build();
+        let req = test::TestRequest::post()
+            .uri("/api/upload")
+            .insert_header(header)
+            .set_payload(payload)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), 400);
+        let body = test::read_body(resp).await;
+        assert!(body.starts_with(b"\"Grid of 1120x"));
+    }
+
+    #[actix_web::test]
+    async fn it_uploads_image_with_cells_and_physical_size() {
+        let app = test::init_service(App::new().configure(routes::services)).await;
+        let pic = include_bytes!("pic.png").to_vec();
+
+        let mut multipart = MultipartBuilder::new();
+        multipart.add_file("file", "pic.png", &pic);
+        multipart.add_text("nCellsInWidth", 30);
+        multipart.add_text("targetHeight", 4);
+        let (header, payload) = multipart.build();
+        let req = test::TestRequest::post()
+            .uri("/api/upload")
+            .insert_header(header)
+            .set_payload(payload)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), 400);
+        let body = test::read_body(resp).await;
+        assert_eq!(
+            body,
+            Bytes::from_static(
+                b"\"Invalid value in 'nCellsInWidth'. Value cannot be combined with targetWidth or targetHeight\""
+            )
+        );
+    }
+
+    #[actix_web::test]
+    async fn it_uploads_rotated_photo() {
+        let app = test::init_service(App::new().configure(routes::services)).await;
+        // Stored on its side, with an EXIF orientation to turn it upright
+        let pic = include_bytes!("fixtures/orientation-6.jpg").to_vec();
+
+        let mut multipart = MultipartBuilder::new();
+        multipart.add_file("file", "photo.jpg", &pic);
+        multipart.add_text("nColors", 3);
+        multipart.add_text("nCellsInWidth", 12);
+        let (header, payload) = multipart.build();
+        let req = test::TestRequest::post()
+            .uri("/api/upload")
+            .insert_header(header)
+            .set_payload(payload)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+        let body: CanvasResponse = test::read_body_json(resp).await;
+        assert_eq!(body.embroidery.len(), 8);
+        assert_eq!(body.embroidery[0].len(), 12);
+    }
+
+    /// Left half black, right half white
+    fn half_black_image() -> Vec<u8> {
+        let image = ImageBuffer::from_fn(40, 20, |x, _| {
+            if x < 20 {
+                Rgb([0, 0, 0])
+            } else {
+                Rgb([255, 255, 255])
+            }
+        });
+        let mut pic = Vec::new();
+        DynamicImage::ImageRgb8(image)
+            .write_to(&mut Cursor::new(&mut pic), ImageFormat::Png)
+            .unwrap();
+        pic
+    }
+
+    #[actix_web::test]
+    async fn it_uploads_image_with_preprocessing() {
+        let app = test::init_service(App::new().configure(routes::services)).await;
+        let pic = half_black_image();
+
+        let mut multipart = MultipartBuilder::new();
+        multipart.add_file("file", "pic.png", &pic);
+        multipart.add_text("nColors", 3);
+        multipart.add_text("nCellsInWidth", 10);
+        multipart.add_text("crop", "10,0,30,20");
+        multipart.add_text("rotation", 90);
+        multipart.add_text("mirror", "vertical");
+        multipart.add_text("brightness", 0.1);
+        multipart.add_text("contrast", 0.2);
+        multipart.add_text("autoLevels", true);
+        let (header, payload) = multipart.build();
+        let req = test::TestRequest::post()
+            .uri("/api/upload")
+            .insert_header(header)
+            .set_payload(payload)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+        let body: CanvasResponse = test::read_body_json(resp).await;
+        // The 30x20 crop turned on its side
+        assert_eq!(body.embroidery.len(), 15);
+        assert_eq!(body.embroidery[0].len(), 10);
+        // Black went to the top, then was flipped to the bottom
+        let first = body.embroidery[0][5].unwrap();
+        let last = body.embroidery[14][5].unwrap();
+        assert!(first[0] > 200, "{first:?}");
+        assert!(last[0] < 50, "{last:?}");
+    }
+
+    #[actix_web::test]
+    async fn it_uploads_image_with_invalid_crop() {
+        let app = test::init_service(App::new().configure(routes::services)).await;
+        let pic = half_black_image();
+
+        let mut multipart = MultipartBuilder::new();
+        multipart.add_file("file", "pic.png", &pic);
+        multipart.add_text("crop", "30,0,20,20");
+        let (header, payload) = multipart.build();
+        let req = test::TestRequest::post()
+            .uri("/api/upload")
+            .insert_header(header)
+            .set_payload(payload)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), 400);
+        let body = test::read_body(resp).await;
+        assert_eq!(
+            body,
+            Bytes::from_static(b"\"Crop rectangle should be within the image\"")
+        );
+    }
+
+    #[actix_web::test]
+    async fn it_uploads_image_with_invalid_brightness() {
+        let app = test::init_service(App::new().configure(routes::services)).await;
+        let pic = half_black_image();
+
+        let mut multipart = MultipartBuilder::new();
+        multipart.add_file("file", "pic.png", &pic);
+        multipart.add_text("brightness", 2);
+        let (header, payload) = multipart.build();
+        let req = test::TestRequest::post()
+            .uri("/api/upload")
+            .insert_header(header)
+            .set_payload(payload)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), 400);
+        let body = test::read_body(resp).await;
+        assert_eq!(
+            body,
+            Bytes::from_static(
+                b"\"Invalid value in 'brightness'. Value should be within -1 and 1\""
+            )
+        );
+    }
+
+    /// Gradient image encoded in `format`
+    fn encode_image(format: ImageFormat) -> Vec<u8> {
+        let image = ImageBuffer::from_fn(40, 30, |x, y| Rgb([(x * 6) as u8, (y * 8) as u8, 120]));
+        let mut pic = Vec::new();
+        DynamicImage::ImageRgb8(image)
+            .write_to(&mut Cursor::new(&mut pic), format)
+            .unwrap();
+        pic
+    }
+
+    const FORMATS: [ImageFormat; 6] = [
+        ImageFormat::Png,
+        ImageFormat::Jpeg,
+        ImageFormat::WebP,
+        ImageFormat::Gif,
+        ImageFormat::Bmp,
+        ImageFormat::Tiff,
+    ];
+
+    #[actix_web::test]
+    async fn it_uploads_image_in_every_format() {
+        let app = test::init_service(App::new().configure(routes::services)).await;
+
+        for format in FORMATS {
+            let pic = encode_image(format);
+            let filename = format!("pic.{}", format.extensions_str()[0]);
+            let mut multipart = MultipartBuilder::new();
+            multipart.add_file("file", &filename, &pic);
+            multipart.add_text("nColors", 5);
+            multipart.add_text("nCellsInWidth", 8);
+            let (header, payload) = multipart.build();
+            let req = test::TestRequest::post()
+                .uri("/api/upload")
+                .insert_header(header)
+                .set_payload(payload)
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+
+            assert!(resp.status().is_success(), "{format:?}");
+            let body: CanvasResponse = test::read_body_json(resp).await;
+            assert_eq!(body.embroidery.len(), 6, "{format:?}");
+            assert_eq!(body.embroidery[0].len(), 8, "{format:?}");
+        }
+    }
+
+    #[actix_web::test]
+    async fn it_exports_image_in_every_format() {
+        let app = test::init_service(App::new().configure(routes::services)).await;
+
+        for format in FORMATS {
+            let pic = encode_image(format);
+            let mut multipart = MultipartBuilder::new();
+            multipart.add_file("file", "pic", &pic);
+            multipart.add_text("nColors", 5);
+            multipart.add_text("nCellsInWidth", 8);
+            let (header, payload) = multipart.build();
+            let req = test::TestRequest::post()
+                .uri("/api/export")
+                .insert_header(header)
+                .set_payload(payload)
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+
+            assert!(resp.status().is_success(), "{format:?}");
+            assert_eq!(resp.headers().get("content-type").unwrap(), "image/png");
+            let body = test::read_body(resp).await;
+            assert!(image::load_from_memory(&body).is_ok(), "{format:?}");
+        }
+    }
+
+    #[actix_web::test]
+    async fn it_uploads_unsupported_format() {
+        let app = test::init_service(App::new().configure(routes::services)).await;
+        let pic = b"%PDF-1.4 not an image".to_vec();
+
+        let mut multipart = MultipartBuilder::new();
+        multipart.add_file("file", "pic.pdf", &pic);
+        let (header, payload) = multipart.build();
+        let req = test::TestRequest::post()
+            .uri("/api/upload")
+            .insert_header(header)
+            .set_payload(payload)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), 415);
+        let body = test::read_body(resp).await;
+        assert_eq!(
+            body,
+            Bytes::from_static(
+                b"\"Unsupported image format. Expected PNG, JPEG, WebP, GIF, BMP or TIFF\""
+            )
+        );
+    }
+
+    #[actix_web::test]
+    async fn it_exports_image() {
+        let app = test::init_service(App::new().configure(routes::services)).await;
+        let pic = include_bytes!("pic.png").to_vec();
+
+        let mut multipart = MultipartBuilder::new();
+        multipart.add_file("file", "pic.png", &pic);
+        multipart.add_text("nColors", 5);
+        multipart.add_text("nCellsInWidth", 10);
+        let (header, payload) = multipart.build();
+        let req = test::TestRequest::post()
+            .uri("/api/export")
+            .insert_header(header)
+            .set_payload(payload)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+        assert!(resp.headers().contains_key("content-type"));
+        assert_eq!(resp.headers().get("content-type").unwrap(), "image/png");
+        assert!(resp.headers().contains_key("content-disposition"));
+        assert_eq!(
+            resp.headers().get("content-disposition").unwrap(),
+            "attachment; filename=pic.png"
+        )
+    }
+
+    #[actix_web::test]
+    async fn it_exports_pdf_chart() {
+        let app = test::init_service(App::new().configure(routes::services)).await;
+        let pic = include_bytes!("pic.png").to_vec();
+
+        let mut multipart = MultipartBuilder::new();
+        multipart.add_file("file", "pic.png", &pic);
+        multipart.add_text("nColors", 5);
+        multipart.add_text("nCellsInWidth", 60);
+        multipart.add_text("format", "pdf");
+        multipart.add_text("pageSize", "letter");
+        let (header, payload) = multipart.build();
+        let req = test::TestRequest::post()
+            .uri("/api/export")
+            .insert_header(header)
+            .set_payload(payload)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+        assert_eq!(
+            resp.headers().get("content-type").unwrap(),
+            "application/pdf"
+        );
+        assert_eq!(
+            resp.headers().get("content-disposition").unwrap(),
+            "attachment; filename=pic.pdf"
+        );
+        let body = test::read_body(resp).await;
+        assert!(body.starts_with(b"%PDF-"));
+    }
+
+    #[actix_web::test]
+    async fn it_exports_unknown_format() {
+        let app = test::init_service(App::new().configure(routes::services)).await;
+        let pic = include_bytes!("pic.png").to_vec();
+
+        let mut multipart = MultipartBuilder::new();
+        multipart.add_file("file", "pic.png", &pic);
+        multipart.add_text("format", "docx");
+        let (header, payload) = multipart.build();
+        let req = test::TestRequest::post()
+            .uri("/api/export")
+            .insert_header(header)
+            .set_payload(payload)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), 400);
+        let body = test::read_body(resp).await;
+        assert_eq!(
+            body,
+            Bytes::from_static(
+                b"\"Invalid value in 'format'. Value should be one of: png, pdf, svg, oxs\""
+            )
+        );
+    }
+
+    #[actix_web::test]
+    async fn it_exports_svg_chart() {
+        let app = test::init_service(App::new().configure(routes::services)).await;
+        let pic = half_black_image();
+
+        let mut multipart = MultipartBuilder::new();
+        multipart.add_file("file", "pic.png", &pic);
+        multipart.add_text("nColors", 3);
+        multipart.add_text("nCellsInWidth", 20);
+        multipart.add_text("format", "svg");
+        multipart.add_text("showSymbols", false);
+        let (header, payload) = multipart.build();
+        let req = test::TestRequest::post()
+            .uri("/api/export")
+            .insert_header(header)
+            .set_payload(payload)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+        assert_eq!(resp.headers().get("content-type").unwrap(), "image/svg+xml");
+        assert_eq!(
+            resp.headers().get("content-disposition").unwrap(),
+            "attachment; filename=pic.svg"
+        );
+        let body = test::read_body(resp).await;
+        let svg = std::str::from_utf8(&body).unwrap();
+        let document = roxmltree::Document::parse(svg).unwrap();
+        let cells = document
+            .descendants()
+            .find(|node| node.attribute("id") == Some("cells"))
+            .unwrap();
+        let rects = cells
+            .children()
+            .filter(|node| node.has_tag_name("rect"))
+            .count();
+        assert_eq!(rects, 20 * 10);
+        assert!(!svg.contains(r#"id="symbols""#));
+    }
+
+    #[actix_web::test]
+    async fn it_uploads_image_with_symbol_overrides() {
+        let app = test::init_service(App::new().configure(routes::services)).await;
+        let pic = half_black_image();
+
+        let mut multipart = MultipartBuilder::new();
+        multipart.add_file("file", "pic.png", &pic);
+        multipart.add_text("nCellsInWidth", 20);
+        multipart.add_text("palette", "310, B5200");
+        multipart.add_text("symbols", "310:@@ b5200:.");
+        let (header, payload) = multipart.build();
+        let req = test::TestRequest::post()
+            .uri("/api/upload")
+            .insert_header(header)
+            .set_payload(payload)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+        let body: CanvasResponse = test::read_body_json(resp).await;
+        let symbols: Vec<(&str, &str)> = body
+            .palette
+            .iter()
+            .map(|entry| (entry.color.name.as_str(), entry.symbol.as_str()))
+            .collect();
+        assert_eq!(symbols, [("310", "@@"), ("B5200", ".")]);
+    }
+
+    #[actix_web::test]
+    async fn it_uploads_image_with_distinct_symbols() {
+        let app = test::init_service(App::new().configure(routes::services)).await;
+        let pic = include_bytes!("pic.png").to_vec();
+
+        let mut multipart = MultipartBuilder::new();
+        multipart.add_file("file", "pic.png", &pic);
+        multipart.add_text("nColors", 20);
+        multipart.add_text("nCellsInWidth", 40);
+        let (header, payload) = multipart.build();
+        let req = test::TestRequest::post()
+            .uri("/api/upload")
+            .insert_header(header)
+            .set_payload(payload)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+        let body: CanvasResponse = test::read_body_json(resp).await;
+        let symbols: HashSet<&str> = body
+            .palette
+            .iter()
+            .map(|entry| entry.symbol.as_str())
+            .collect();
+        assert_eq!(symbols.len(), body.palette.len());
+        assert!(symbols.iter().all(|symbol| !symbol.is_empty()));
+    }
+
+    #[actix_web::test]
+    async fn it_uploads_image_with_invalid_symbols() {
+        let app = test::init_service(App::new().configure(routes::services)).await;
+        let pic = include_bytes!("pic.png").to_vec();
+
+        for (symbols, expected) in [
+            (
+                "310",
+                "\"Invalid value in 'symbols'. Value should be a list of code:symbol pairs\"",
+            ),
+            (
+                "310:abc",
+                "\"Invalid value in 'symbols'. Symbol 'abc' should be one or two printable ASCII characters\"",
+            ),
+            (
+                "310:X B5200:X",
+                "\"Invalid value in 'symbols'. Duplicate symbol 'X'\"",
+            ),
+        ] {
+            let mut multipart = MultipartBuilder::new();
+            multipart.add_file("file", "pic.png", &pic);
+            multipart.add_text("symbols", symbols);
+            let (header, payload) = multipart.build();
+            let req = test::TestRequest::post()
+                .uri("/api/upload")
+                .insert_header(header)
+                .set_payload(payload)
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+
+            assert_eq!(resp.status(), 400);
+            let body = test::read_body(resp).await;
+            assert_eq!(body, Bytes::from(expected));
+        }
+    }
+
+    #[actix_web::test]
+    async fn it_exports_and_imports_oxs_chart() {
+        let app = test::init_service(App::new().configure(routes::services)).await;
+        let pic = include_bytes!("pic.png").to_vec();
+
+        let upload = |uri: &'static str| {
+            let mut multipart = MultipartBuilder::new();
+            multipart.add_file("file", "pic.png", &pic);
+            multipart.add_text("nColors", 6);
+            multipart.add_text("nCellsInWidth", 30);
+            multipart.add_text("format", "oxs");
+            multipart.add_text("symbols", "310:@");
+            let (header, payload) = multipart.build();
+            test::TestRequest::post()
+                .uri(uri)
+                .insert_header(header)
+                .set_payload(payload)
+                .to_request()
+        };
+        let resp = test::call_service(&app, upload("/api/export")).await;
+        assert!(resp.status().is_success());
+        assert_eq!(
+            resp.headers().get("content-type").unwrap(),
+            "application/xml"
+        );
+        assert_eq!(
+            resp.headers().get("content-disposition").unwrap(),
+            "attachment; filename=pic.oxs"
+        );
+        let chart = test::read_body(resp).await.to_vec();
+        assert!(std::str::from_utf8(&chart)
+            .unwrap()
+            .contains("<fullstitches>"));
+
+        let resp = test::call_service(&app, upload("/api/upload")).await;
+        let uploaded: CanvasResponse = test::read_body_json(resp).await;
+
+        let mut multipart = MultipartBuilder::new();
+        multipart.add_file("file", "pic.oxs", &chart);
+        let (header, payload) = multipart.build();
+        let req = test::TestRequest::post()
+            .uri("/api/import")
+            .insert_header(header)
+            .set_payload(payload)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert!(resp.status().is_success());
+        let imported: CanvasResponse = test::read_body_json(resp).await;
+        assert_eq!(imported.embroidery, uploaded.embroidery);
+        let summary = |body: &CanvasResponse| -> Vec<(String, String, usize)> {
+            body.palette
+                .iter()
+                .map(|entry| {
+                    (
+                        entry.color.name.clone(),
+                        entry.symbol.clone(),
+                        entry.n_stitches,
+                    )
+                })
+                .collect()
+        };
+        assert_eq!(summary(&imported), summary(&uploaded));
+    }
+
+    #[actix_web::test]
+    async fn it_imports_oxs_chart_with_invalid_brand() {
+        let app = test::init_service(App::new().configure(routes::services)).await;
+        let chart = b"<chart/>".to_vec();
+
+        for (brands, expected) in [
+            (
+                vec!["Unknown"],
+                "\"Invalid value in 'brand'. Value should be one of: DMC, Anchor, Madeira, Cosmo\"",
+            ),
+            (
+                vec!["DMC", "Anchor"],
+                "\"Invalid value in 'brand'. Field should contain 1 item\"",
+            ),
+        ] {
+            let mut multipart = MultipartBuilder::new();
+            multipart.add_file("file", "pic.oxs", &chart);
+            for brand in brands {
+                multipart.add_text("brand", brand);
+            }
+            let (header, payload) = multipart.build();
+            let req = test::TestRequest::post()
+                .uri("/api/import")
+                .insert_header(header)
+                .set_payload(payload)
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+
+            assert_eq!(resp.status(), 400);
+            let body = test::read_body(resp).await;
+            assert_eq!(body, Bytes::from(expected));
+        }
+    }
+
+    #[actix_web::test]
+    async fn it_imports_malformed_oxs_chart() {
+        let app = test::init_service(App::new().configure(routes::services)).await;
+
+        for (chart, expected) in [
+            (
+                "<chart/>",
+                "\"Malformed OXS chart. Missing 'properties' element\"",
+            ),
+            ("", "\"Missing value. Expected 'file' to be provided\""),
+        ] {
+            let chart = chart.as_bytes().to_vec();
+            let mut multipart = MultipartBuilder::new();
+            multipart.add_file("file", "pic.oxs", &chart);
+            let (header, payload) = multipart.build();
+            let req = test::TestRequest::post()
+                .uri("/api/import")
+                .insert_header(header)
+                .set_payload(payload)
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+
+            assert_eq!(resp.status(), 400);
+            let body = test::read_body(resp).await;
+            assert_eq!(body, Bytes::from(expected));
+        }
     }
 }
@@ -31,15 +31,15 @@ export default function EmbroideryCanvas({
     };
 
     const updateCanvas = (rowId: number) => {
-        const newEmbroidery: number[][][] = [...canvas.embroidery];
+        const newEmbroidery: (number[] | null)[][] = [...canvas.embroidery];
         const newPalette: PaletteColor[] = [...canvas.palette];
 
-        const isEqual = (arr1: number[], arr2: number[]): boolean =>
-            arr1.every((el, index) => el === arr2[index]);
+        const isEqual = (arr1: number[], arr2: number[] | null): boolean =>
+            arr2 !== null && arr1.every((el, index) => el === arr2[index]);
 
         return (cellId: number) => {
             return (rgb: number[]) => {
-                const oldColor: number[] = newEmbroidery[rowId][cellId];
+                const oldColor: number[] | null = newEmbroidery[rowId][cellId];
                 newEmbroidery[rowId][cellId] = rgb;
 
                 canvas.embroidery = newEmbroidery;
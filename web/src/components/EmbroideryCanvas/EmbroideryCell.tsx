@@ -1,7 +1,9 @@
 import './EmbroideryCell.css';
 
+const FABRIC_COLOR = '255,255,255';
+
 interface EmbroideryCell {
-    color: number[];
+    color: number[] | null;
     identifier: string;
     updateCanvas: (color: number[]) => void;
     changeCanvasUpdater: (cb: (color: number[]) => void) => void;
@@ -30,7 +32,7 @@ export default function EmbroideryCell({
         <td
             className='embroidery-cell'
             style={{
-                backgroundColor: `rgba(${color.toString()},0.5)`,
+                backgroundColor: `rgba(${color?.toString() ?? FABRIC_COLOR},0.5)`,
                 transform: isSelected ? 'scale(1.5,1.4)' : undefined,
             }}
             onClick={handleOnClick}
@@ -1,7 +1,7 @@
 import EmbroideryCell from './EmbroideryCell';
 
 interface EmbroideryRowProps {
-    row: number[][];
+    row: (number[] | null)[];
     palette: Map<string, string>;
     updateCanvas: (cellId: number) => (color: number[]) => void;
     changeCanvasUpdater: (cb: (color: number[]) => void) => void;
@@ -19,7 +19,10 @@ export default function EmbroideryRow({
     setSelectedCellPosition,
     selectedCellPosition,
 }: EmbroideryRowProps) {
-    const getIdentifier = (color: number[]): string => {
+    const getIdentifier = (color: number[] | null): string => {
+        if (!color) {
+            return '';
+        }
         const colorString = color.toString();
         return palette.has(colorString) ? palette.get(colorString)! : '';
     };
@@ -1,10 +1,11 @@
 export interface Canvas {
-    embroidery: number[][][];
+    embroidery: (number[] | null)[][];
     palette: PaletteColor[];
 }
 
 export interface PaletteColor {
     identifier: string;
+    symbol: string;
     color: { rgb: number[]; name: string };
     nStitches: number;
 }